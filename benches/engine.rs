@@ -0,0 +1,59 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::{Rng, SeedableRng};
+
+use spell::engine::Engine;
+use spell::generators::generate_world;
+use spell::rect::Rectf;
+use spell::vec2::Vec2f;
+use spell::world::{ActorId, Body, Disk, Magick, MaterialType, Projectile, ProjectileId, World};
+
+fn make_world(projectiles: usize) -> World {
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+    let mut world = generate_world(
+        Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0)),
+        &mut rng,
+    );
+    for _ in 0..projectiles {
+        world.id_counter += 1;
+        world.projectiles.push(Projectile {
+            id: ProjectileId(world.id_counter),
+            actor_id: ActorId(0),
+            body: Body {
+                shape: Disk { radius: 0.2 },
+                material_type: MaterialType::Ice,
+            },
+            position: Vec2f::new(rng.gen_range(-95.0..95.0), rng.gen_range(-95.0..95.0)),
+            health: 1.0,
+            magick: Magick::default(),
+            velocity: Vec2f::new(rng.gen_range(-20.0..20.0), rng.gen_range(-20.0..20.0)),
+            dynamic_force: Vec2f::ZERO,
+            position_z: 1.0,
+            velocity_z: 0.0,
+            deadline: f64::MAX,
+        });
+    }
+    world
+}
+
+fn engine_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("engine_update");
+    for projectiles in [0, 100, 200, 400].iter() {
+        let world = make_world(*projectiles);
+        for (name, exhaustive) in [("grid", false), ("exhaustive", true)].iter() {
+            group.bench_with_input(BenchmarkId::new(*name, projectiles), &world, |b, world| {
+                let mut engine = Engine::default();
+                engine.set_exhaustive_broadphase(*exhaustive);
+                let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+                b.iter_batched(
+                    || world.clone(),
+                    |mut world| engine.update(1.0 / 60.0, &mut world, &mut rng),
+                    BatchSize::SmallInput,
+                )
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, engine_update);
+criterion_main!(benches);
@@ -0,0 +1,355 @@
+use rand::Rng;
+
+use crate::engine::Engine;
+use crate::index::WorldIndex;
+use crate::protocol::{ActorAction, CastAction};
+use crate::world::{ActorId, Element, PlayerId, World};
+
+const MIN_DISTANCE: f64 = 6.0;
+const MAX_DISTANCE: f64 = 15.0;
+const MAX_TARGET_DISTANCE: f64 = 2.0 * MAX_DISTANCE;
+const SHIELD_COOLDOWN: f64 = 5.0;
+const COMBOS: [&[Element]; 6] = [
+    &[Element::Fire, Element::Fire, Element::Fire],
+    &[Element::Arcane, Element::Fire],
+    &[Element::Lightning, Element::Lightning],
+    &[Element::Earth, Element::Fire],
+    &[Element::Cold, Element::Cold, Element::Cold],
+    &[Element::Water, Element::Water],
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum BotState {
+    Idle { until: f64 },
+    AddElements { combo: usize, added: usize },
+    Cast { until: f64 },
+    Shield { added: bool },
+}
+
+#[derive(Debug, Clone)]
+pub struct Bot {
+    player_id: PlayerId,
+    actor_id: Option<ActorId>,
+    target: Option<ActorId>,
+    health: f64,
+    shield_time: f64,
+    state: BotState,
+}
+
+impl Bot {
+    pub fn new(player_id: PlayerId) -> Self {
+        Self {
+            player_id,
+            actor_id: None,
+            target: None,
+            health: 0.0,
+            shield_time: 0.0,
+            state: BotState::Idle { until: 0.0 },
+        }
+    }
+
+    pub fn player_id(&self) -> PlayerId {
+        self.player_id
+    }
+
+    pub fn update<R: Rng>(
+        &mut self,
+        actor_index: usize,
+        world: &World,
+        index: &WorldIndex,
+        rng: &mut R,
+    ) -> ActorAction {
+        let actor = &world.actors[actor_index];
+        if self.actor_id != Some(actor.id) {
+            self.actor_id = Some(actor.id);
+            self.target = None;
+            self.health = actor.health;
+            self.state = BotState::Idle { until: world.time };
+        }
+        let hit = actor.health < self.health;
+        self.health = actor.health;
+        let target_index = self.select_target(actor_index, world, index);
+        let mut actor_action = ActorAction {
+            moving: false,
+            target_direction: actor.target_direction,
+            cast_action: None,
+        };
+        let mut distance = None;
+        if let Some(target_index) = target_index {
+            let to_target = world.actors[target_index].position - actor.position;
+            let norm = to_target.norm();
+            if norm > f64::EPSILON {
+                actor_action.target_direction = to_target / norm;
+            }
+            distance = Some(norm);
+        }
+        actor_action.cast_action = self.get_cast_action(hit, distance, world.time, rng);
+        if let Some(distance) = distance {
+            if matches!(
+                self.state,
+                BotState::AddElements { .. } | BotState::Cast { .. }
+            ) {
+                actor_action.moving = false;
+            } else if distance < MIN_DISTANCE {
+                actor_action.moving = true;
+                actor_action.target_direction = -actor_action.target_direction;
+            } else {
+                actor_action.moving = distance > MAX_DISTANCE;
+            }
+        }
+        actor_action
+    }
+
+    fn select_target(
+        &mut self,
+        actor_index: usize,
+        world: &World,
+        index: &WorldIndex,
+    ) -> Option<usize> {
+        let actor = &world.actors[actor_index];
+        let is_enemy = |i: usize| {
+            let other = &world.actors[i];
+            i != actor_index
+                && other.active
+                && (actor.team.is_none() || other.team != actor.team)
+                && other.position.distance(actor.position) <= MAX_TARGET_DISTANCE
+        };
+        if let Some(target) = self.target {
+            if let Some(i) = index.actor(target) {
+                if is_enemy(i) {
+                    return Some(i);
+                }
+            }
+        }
+        let target_index = (0..world.actors.len())
+            .filter(|i| is_enemy(*i))
+            .min_by(|l, r| {
+                let l = world.actors[*l].position.distance(actor.position);
+                let r = world.actors[*r].position.distance(actor.position);
+                l.partial_cmp(&r).unwrap()
+            });
+        self.target = target_index.map(|i| world.actors[i].id);
+        target_index
+    }
+
+    fn get_cast_action<R: Rng>(
+        &mut self,
+        hit: bool,
+        distance: Option<f64>,
+        now: f64,
+        rng: &mut R,
+    ) -> Option<CastAction> {
+        if hit && self.shield_time <= now && !matches!(self.state, BotState::Cast { .. }) {
+            self.state = BotState::Shield { added: false };
+        }
+        match self.state.clone() {
+            BotState::Idle { until } => {
+                if until <= now
+                    && matches!(distance, Some(v) if (MIN_DISTANCE..=MAX_DISTANCE).contains(&v))
+                {
+                    self.state = BotState::AddElements {
+                        combo: rng.gen_range(0..COMBOS.len()),
+                        added: 0,
+                    };
+                }
+                None
+            }
+            BotState::AddElements { combo, added } => {
+                if added < COMBOS[combo].len() {
+                    self.state = BotState::AddElements {
+                        combo,
+                        added: added + 1,
+                    };
+                    Some(CastAction::AddSpellElement(COMBOS[combo][added]))
+                } else {
+                    self.state = BotState::Cast {
+                        until: now + rng.gen_range(0.3..1.5),
+                    };
+                    Some(CastAction::StartDirectedMagick)
+                }
+            }
+            BotState::Cast { until } => {
+                if until <= now || !matches!(distance, Some(v) if v <= MAX_DISTANCE) {
+                    self.state = BotState::Idle {
+                        until: now + rng.gen_range(0.5..2.0),
+                    };
+                    Some(CastAction::CompleteDirectedMagick)
+                } else {
+                    None
+                }
+            }
+            BotState::Shield { added } => {
+                if added {
+                    self.shield_time = now + SHIELD_COOLDOWN;
+                    self.state = BotState::Idle {
+                        until: now + rng.gen_range(0.5..1.5),
+                    };
+                    Some(CastAction::SelfMagick)
+                } else {
+                    self.state = BotState::Shield { added: true };
+                    Some(CastAction::AddSpellElement(Element::Shield))
+                }
+            }
+        }
+    }
+}
+
+pub fn update_bots<R: Rng>(bots: &mut [Bot], engine: &mut Engine, world: &mut World, rng: &mut R) {
+    for bot in bots.iter_mut() {
+        if let Some(actor_index) = engine.index().player_actor(bot.player_id) {
+            let actor_action = bot.update(actor_index, world, engine.index(), rng);
+            engine.apply_actor_action(actor_action, actor_index, world);
+        }
+    }
+}
+
+pub fn get_bot_name(number: usize) -> String {
+    get_numbered_name("Bot", number)
+}
+
+pub fn get_numbered_name(prefix: &str, number: usize) -> String {
+    let mut suffix = Vec::new();
+    let mut value = number;
+    loop {
+        suffix.push((b'A' + (value % 26) as u8) as char);
+        value /= 26;
+        if value == 0 {
+            break;
+        }
+    }
+    format!("{}{}", prefix, suffix.iter().rev().collect::<String>())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::generators::generate_player_actor;
+    use crate::protocol::{is_valid_player_name, WorldEvent};
+    use crate::rect::Rectf;
+    use crate::vec2::Vec2f;
+    use crate::world::{Player, TeamId};
+
+    use super::*;
+
+    fn make_world(positions: &[Vec2f]) -> World {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let bounds = Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0));
+        let mut world = World {
+            bounds: bounds.clone(),
+            ..Default::default()
+        };
+        for (i, position) in positions.iter().enumerate() {
+            let player_id = PlayerId(i as u64 + 1);
+            let actor_id = ActorId(i as u64 + 1 + positions.len() as u64);
+            let mut actor =
+                generate_player_actor(actor_id, player_id, String::new(), None, &bounds, &mut rng);
+            actor.position = *position;
+            world.actors.push(actor);
+            world.players.push(Player {
+                id: player_id,
+                active: true,
+                name: get_bot_name(i),
+                team: None,
+                actor_id: Some(actor_id),
+                spawn_time: 0.0,
+                deaths: 0,
+                kills: 0,
+                assists: 0,
+                damage_dealt: 0.0,
+                damage_healed: 0.0,
+            });
+        }
+        world.id_counter = 2 * positions.len() as u64 + 1;
+        world
+    }
+
+    fn make_index(world: &World) -> WorldIndex {
+        let mut index = WorldIndex::default();
+        index.rebuild(world);
+        index
+    }
+
+    #[test]
+    fn bot_should_move_toward_far_enemy_and_away_from_close_one() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut world = make_world(&[Vec2f::ZERO, Vec2f::only_x(20.0)]);
+        let index = make_index(&world);
+        let mut bot = Bot::new(PlayerId(1));
+        let actor_action = bot.update(0, &world, &index, &mut rng);
+        assert!(actor_action.moving);
+        assert_eq!(actor_action.target_direction, Vec2f::I);
+        world.actors[1].position = Vec2f::only_x(2.0);
+        let actor_action = bot.update(0, &world, &index, &mut rng);
+        assert!(actor_action.moving);
+        assert_eq!(actor_action.target_direction, -Vec2f::I);
+    }
+
+    #[test]
+    fn bot_should_ignore_teammates() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut world = make_world(&[Vec2f::ZERO, Vec2f::only_x(20.0), Vec2f::only_y(25.0)]);
+        world.actors[0].team = Some(TeamId(0));
+        world.actors[1].team = Some(TeamId(0));
+        world.actors[2].team = Some(TeamId(1));
+        let index = make_index(&world);
+        let mut bot = Bot::new(PlayerId(1));
+        let actor_action = bot.update(0, &world, &index, &mut rng);
+        assert_eq!(actor_action.target_direction, Vec2f::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn bot_should_cast_shield_when_hit() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut world = make_world(&[Vec2f::ZERO, Vec2f::only_x(10.0)]);
+        let index = make_index(&world);
+        let mut bot = Bot::new(PlayerId(1));
+        bot.update(0, &world, &index, &mut rng);
+        world.actors[0].health = 0.5;
+        assert_eq!(
+            bot.update(0, &world, &index, &mut rng).cast_action,
+            Some(CastAction::AddSpellElement(Element::Shield))
+        );
+        assert_eq!(
+            bot.update(0, &world, &index, &mut rng).cast_action,
+            Some(CastAction::SelfMagick)
+        );
+    }
+
+    #[test]
+    fn bots_should_fight_each_other() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut world = make_world(&[Vec2f::ZERO, Vec2f::only_x(10.0)]);
+        let mut bots = vec![Bot::new(PlayerId(1)), Bot::new(PlayerId(2))];
+        let mut engine = Engine::default();
+        engine.update(0.01, &mut world, &mut rng);
+        let mut casts = 0;
+        for _ in 0..600 {
+            update_bots(&mut bots, &mut engine, &mut world, &mut rng);
+            engine.update(1.0 / 60.0, &mut world, &mut rng);
+            casts += engine
+                .world_events()
+                .iter()
+                .filter(|v| {
+                    matches!(
+                        v,
+                        WorldEvent::Cast {
+                            cast_action: CastAction::StartDirectedMagick,
+                            ..
+                        }
+                    )
+                })
+                .count();
+        }
+        assert!(casts > 0);
+        assert!(world.players.iter().any(|v| v.damage_dealt > 0.0));
+    }
+
+    #[test]
+    fn get_bot_name_should_return_valid_player_names() {
+        assert_eq!(get_bot_name(0), "BotA");
+        assert_eq!(get_bot_name(25), "BotZ");
+        assert_eq!(get_bot_name(26), "BotBA");
+        assert!(is_valid_player_name(&get_bot_name(100)));
+    }
+}
@@ -0,0 +1,31 @@
+#[macro_use]
+extern crate log;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use clap::Clap;
+
+use spell::load_test::{run_load_test, LoadTestParams};
+
+fn main() {
+    env_logger::init();
+    let params = LoadTestParams::parse();
+    let stop = Arc::new(AtomicBool::new(false));
+    setup_ctrlc_handler(stop.clone());
+    match run_load_test(params, stop) {
+        Ok(report) => println!("{}", serde_json::to_string_pretty(&report).unwrap()),
+        Err(e) => {
+            error!("Load test has failed: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn setup_ctrlc_handler(stop: Arc<AtomicBool>) {
+    ctrlc::set_handler(move || {
+        info!("Stopping load test...");
+        stop.store(true, Ordering::Release)
+    })
+    .unwrap();
+}
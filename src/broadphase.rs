@@ -0,0 +1,244 @@
+use crate::rect::Rectf;
+use crate::vec2::Vec2f;
+
+const MAX_GRID_SIZE: usize = 256;
+
+pub struct UniformGrid<T> {
+    bounds: Rectf,
+    cell_size: f64,
+    width: usize,
+    height: usize,
+    cells: Vec<Vec<usize>>,
+    items: Vec<(T, Rectf)>,
+    exhaustive: bool,
+}
+
+impl<T> Default for UniformGrid<T> {
+    fn default() -> Self {
+        Self {
+            bounds: Rectf::default(),
+            cell_size: 0.0,
+            width: 0,
+            height: 0,
+            cells: Vec::new(),
+            items: Vec::new(),
+            exhaustive: false,
+        }
+    }
+}
+
+impl<T: Copy + Ord> UniformGrid<T> {
+    /// Makes queries return all inserted items to measure the grid against brute force search.
+    pub fn set_exhaustive(&mut self, value: bool) {
+        self.exhaustive = value;
+    }
+
+    pub fn reset(&mut self, bounds: &Rectf, cell_size: f64) {
+        self.items.clear();
+        let cell_size = cell_size
+            .max(bounds.width() / MAX_GRID_SIZE as f64)
+            .max(bounds.height() / MAX_GRID_SIZE as f64);
+        if self.bounds == *bounds && self.cell_size == cell_size {
+            self.cells.iter_mut().for_each(|v| v.clear());
+            return;
+        }
+        self.bounds = bounds.clone();
+        self.cell_size = cell_size;
+        self.width = get_grid_size(bounds.width(), cell_size);
+        self.height = get_grid_size(bounds.height(), cell_size);
+        self.cells.clear();
+        self.cells.resize(self.width * self.height, Vec::new());
+    }
+
+    pub fn insert(&mut self, item: T, rect: Rectf) {
+        if self.cells.is_empty() {
+            return;
+        }
+        let index = self.items.len();
+        let (min_x, min_y, max_x, max_y) = self.get_cells_range(&rect);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                self.cells[y * self.width + x].push(index);
+            }
+        }
+        self.items.push((item, rect));
+    }
+
+    pub fn query(&self, rect: &Rectf, result: &mut Vec<T>) {
+        result.clear();
+        self.add_overlapping(rect, result);
+        result.sort_unstable();
+        result.dedup();
+    }
+
+    pub fn query_segment(&self, begin: Vec2f, end: Vec2f, result: &mut Vec<T>) {
+        result.clear();
+        if self.cells.is_empty() {
+            return;
+        }
+        if self.exhaustive {
+            result.extend(self.items.iter().map(|(item, _)| *item));
+            result.sort_unstable();
+            return;
+        }
+        let pieces = ((begin.distance(end) / self.cell_size).ceil() as usize).max(1);
+        let step = (end - begin) / pieces as f64;
+        let mut piece_begin = begin;
+        for i in 1..=pieces {
+            let piece_end = if i == pieces {
+                end
+            } else {
+                begin + step * i as f64
+            };
+            self.add_overlapping(
+                &Rectf::new(piece_begin, piece_begin).union(&Rectf::new(piece_end, piece_end)),
+                result,
+            );
+            piece_begin = piece_end;
+        }
+        result.sort_unstable();
+        result.dedup();
+    }
+
+    fn add_overlapping(&self, rect: &Rectf, result: &mut Vec<T>) {
+        if self.cells.is_empty() {
+            return;
+        }
+        if self.exhaustive {
+            result.extend(self.items.iter().map(|(item, _)| *item));
+            return;
+        }
+        let (min_x, min_y, max_x, max_y) = self.get_cells_range(rect);
+        for y in min_y..=max_y {
+            for x in min_x..=max_x {
+                for index in self.cells[y * self.width + x].iter() {
+                    let (item, item_rect) = &self.items[*index];
+                    if item_rect.overlaps(rect) {
+                        result.push(*item);
+                    }
+                }
+            }
+        }
+    }
+
+    fn get_cells_range(&self, rect: &Rectf) -> (usize, usize, usize, usize) {
+        (
+            get_cell(rect.min.x - self.bounds.min.x, self.cell_size, self.width),
+            get_cell(rect.min.y - self.bounds.min.y, self.cell_size, self.height),
+            get_cell(rect.max.x - self.bounds.min.x, self.cell_size, self.width),
+            get_cell(rect.max.y - self.bounds.min.y, self.cell_size, self.height),
+        )
+    }
+}
+
+fn get_grid_size(length: f64, cell_size: f64) -> usize {
+    if cell_size > 0.0 && length.is_finite() {
+        ((length / cell_size).ceil() as usize).clamp(1, MAX_GRID_SIZE)
+    } else {
+        1
+    }
+}
+
+fn get_cell(offset: f64, cell_size: f64, size: usize) -> usize {
+    (offset / cell_size).floor().clamp(0.0, (size - 1) as f64) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    fn make_grid(rects: &[Rectf]) -> UniformGrid<usize> {
+        let mut grid = UniformGrid::default();
+        grid.reset(&Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0)), 4.0);
+        for (i, rect) in rects.iter().enumerate() {
+            grid.insert(i, rect.clone());
+        }
+        grid
+    }
+
+    fn generate_rects<R: Rng>(number: usize, rng: &mut R) -> Vec<Rectf> {
+        (0..number)
+            .map(|_| {
+                let position =
+                    Vec2f::new(rng.gen_range(-150.0..150.0), rng.gen_range(-150.0..150.0));
+                let size = Vec2f::new(rng.gen_range(0.0..20.0), rng.gen_range(0.0..20.0));
+                Rectf::new(position, position + size)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn query_should_return_sorted_overlapping_items_including_out_of_bounds() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let rects = generate_rects(300, &mut rng);
+        let grid = make_grid(&rects);
+        let mut result = Vec::new();
+        for query in generate_rects(100, &mut rng) {
+            grid.query(&query, &mut result);
+            let expected: Vec<usize> = (0..rects.len())
+                .filter(|i| rects[*i].overlaps(&query))
+                .collect();
+            assert_eq!(result, expected);
+        }
+    }
+
+    #[test]
+    fn reset_should_limit_number_of_cells_for_large_bounds() {
+        let mut grid = UniformGrid::default();
+        grid.reset(&Rectf::new(Vec2f::both(-1e6), Vec2f::both(1e6)), 4.0);
+        assert_eq!(grid.cells.len(), MAX_GRID_SIZE * MAX_GRID_SIZE);
+        grid.insert(0, Rectf::new(Vec2f::both(-1.0), Vec2f::both(1.0)));
+        let mut result = Vec::new();
+        grid.query(&Rectf::new(Vec2f::ZERO, Vec2f::ZERO), &mut result);
+        assert_eq!(result, vec![0]);
+    }
+
+    #[test]
+    fn exhaustive_grid_should_return_all_items() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let rects = generate_rects(30, &mut rng);
+        let mut grid = make_grid(&rects);
+        grid.set_exhaustive(true);
+        let expected: Vec<usize> = (0..rects.len()).collect();
+        let mut result = Vec::new();
+        grid.query(&Rectf::new(Vec2f::ZERO, Vec2f::ZERO), &mut result);
+        assert_eq!(result, expected);
+        grid.query_segment(Vec2f::ZERO, Vec2f::both(1.0), &mut result);
+        assert_eq!(result, expected);
+    }
+
+    #[test]
+    fn not_built_grid_should_return_nothing() {
+        let mut grid = UniformGrid::default();
+        grid.insert(0, Rectf::new(Vec2f::both(-1.0), Vec2f::both(1.0)));
+        let mut result = vec![1];
+        grid.query(&Rectf::new(Vec2f::ZERO, Vec2f::ZERO), &mut result);
+        assert!(result.is_empty());
+        grid.query_segment(Vec2f::ZERO, Vec2f::both(1e3), &mut result);
+        assert!(result.is_empty());
+    }
+
+    #[test]
+    fn query_segment_should_return_all_items_overlapping_segment_bounds_pieces() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let rects = generate_rects(300, &mut rng);
+        let grid = make_grid(&rects);
+        let mut result = Vec::new();
+        for _ in 0..100 {
+            let begin = Vec2f::new(rng.gen_range(-150.0..150.0), rng.gen_range(-150.0..150.0));
+            let end = Vec2f::new(rng.gen_range(-150.0..150.0), rng.gen_range(-150.0..150.0));
+            grid.query_segment(begin, end, &mut result);
+            for (i, rect) in rects.iter().enumerate() {
+                let intersects = (0..=1000).any(|v| {
+                    let point = begin + (end - begin) * (v as f64 / 1000.0);
+                    rect.overlaps(&Rectf::new(point, point))
+                });
+                if intersects {
+                    assert!(result.contains(&i), "{} {:?}", i, rect);
+                }
+            }
+        }
+    }
+}
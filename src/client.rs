@@ -2,18 +2,27 @@ use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
 use std::sync::Arc;
-use std::thread::{sleep, spawn, JoinHandle};
+use std::thread::{spawn, JoinHandle};
 use std::time::{Duration, Instant};
 
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
 use tokio::net::UdpSocket;
 use tokio::runtime::Builder;
 
+use crate::encryption::{Direction, Encryption, EncryptionKey};
 use crate::protocol::{
-    deserialize_server_message, deserialize_server_message_data, get_server_message_data_type,
-    ClientMessage, ClientMessageData, GameUpdate, PlayerControl, ServerMessageData,
-    HEARTBEAT_PERIOD,
+    deserialize_server_message, deserialize_server_message_data, get_challenge_cookie,
+    get_rejected_protocol_version, get_server_message_data_type, is_reliable_client_message_data,
+    serialize_client_message, Capabilities, ClientMessage, ClientMessageData, DeserializeError,
+    GameUpdate, PlayerControl, ServerMessage, ServerMessageAssembler, ServerMessageData,
+    HEARTBEAT_PERIOD, MAX_CHAT_MESSAGE_LEN, MAX_SERVER_MESSAGE_SIZE,
 };
-use crate::world::PlayerId;
+use crate::quantization::dequantize_world_update;
+use crate::reliable::{ReliableReceiver, ReliableSender};
+use crate::world::{PlayerId, TeamId};
+
+const MAX_PENDING_RELIABLE_MESSAGES: usize = 16;
 
 pub struct Client {
     game_client: Option<GameClient>,
@@ -79,12 +88,20 @@ impl Client {
     pub fn receiver(&self) -> &Receiver<GameUpdate> {
         self.game_client.as_ref().unwrap().receiver()
     }
+
+    pub fn send_chat_message(&self, text: String, team_only: bool) -> Result<(), String> {
+        self.game_client
+            .as_ref()
+            .unwrap()
+            .send_chat_message(text, team_only)
+    }
 }
 
 pub struct GameClient {
     id: u64,
     player_control_sender: Option<Sender<PlayerControl>>,
     game_update_receiver: Option<Receiver<GameUpdate>>,
+    chat_sender: Sender<ClientMessageData>,
     handle: Option<JoinHandle<Result<(), String>>>,
     stop: Arc<AtomicBool>,
     done: Arc<AtomicBool>,
@@ -104,6 +121,7 @@ impl GameClient {
             id: settings.id,
             player_control_sender: Some(player_control_sender),
             game_update_receiver: Some(game_update_receiver),
+            chat_sender: client_sender.clone(),
             handle: Some(run_background_game_client(
                 settings,
                 game_update_sender,
@@ -146,6 +164,15 @@ impl GameClient {
     pub fn receiver(&self) -> &Receiver<GameUpdate> {
         self.game_update_receiver.as_ref().unwrap()
     }
+
+    pub fn send_chat_message(&self, text: String, team_only: bool) -> Result<(), String> {
+        if text.len() > MAX_CHAT_MESSAGE_LEN {
+            return Err(String::from("Chat message is too long"));
+        }
+        self.chat_sender
+            .send(ClientMessageData::Chat { text, team_only })
+            .map_err(|e| format!("Failed to send chat message: {}", e))
+    }
 }
 
 impl Drop for GameClient {
@@ -274,6 +301,8 @@ pub struct UdpClientSettings {
     pub id: u64,
     pub server_address: SocketAddr,
     pub read_timeout: Duration,
+    pub reconnect_timeout: Duration,
+    pub encryption_key: Option<EncryptionKey>,
 }
 
 pub async fn run_udp_client(
@@ -283,42 +312,56 @@ pub async fn run_udp_client(
     stop: Arc<AtomicBool>,
 ) -> Result<(), std::io::Error> {
     info!("[{}] Run UDP client: {:?}", settings.id, settings);
-    let local_address = match settings.server_address {
-        SocketAddr::V4(..) => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)),
-        SocketAddr::V6(..) => SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0)),
-    };
-    let socket = UdpSocket::bind(local_address).await?;
-    info!(
-        "[{}] UDP client is listening on {}",
-        settings.id,
-        socket.local_addr().unwrap()
-    );
-    socket.connect(settings.server_address).await?;
-    info!(
-        "[{}] UDP client is connected to {}",
-        settings.id, settings.server_address
-    );
-    let mut recv_buffer = vec![0u8; 65_507];
+    let mut socket = connect_udp_socket(&settings).await?;
+    let mut recv_buffer = vec![0u8; MAX_SERVER_MESSAGE_SIZE];
     let mut last_update = Instant::now();
     let mut last_recv = last_update;
+    let mut last_reconnect = last_update;
     let mut update_period = Duration::from_secs_f64(1.0);
+    let mut quantization = None;
     let mut prev_received_message_number = 0;
-    let mut session_id = None;
-    let mut client_message_number = 0;
+    let mut session = UdpClientSession {
+        id: None,
+        secret: 0,
+        message_number: 0,
+        last_join: None,
+        resume_token: None,
+        reliable_sender: ReliableSender::new(MAX_PENDING_RELIABLE_MESSAGES),
+        reliable_receiver: ReliableReceiver::default(),
+        send_ack: false,
+    };
+    let encryption = settings
+        .encryption_key
+        .as_ref()
+        .map(|v| Encryption::new(v, SmallRng::from_entropy().gen()));
+    let mut server_message_assembler = ServerMessageAssembler::default();
     while !stop.load(Ordering::Acquire) {
         if !send_client_messages(
             &settings,
             &receiver,
             &socket,
             Instant::now() + update_period / 2,
-            session_id.unwrap_or(0),
-            &mut client_message_number,
+            encryption.as_ref(),
+            &mut session,
         )
         .await?
         {
             debug!("[{}] UDP client is quitting...", settings.id);
             break;
         }
+        send_reliable_client_messages(&settings, &socket, encryption.as_ref(), &mut session)
+            .await?;
+        if session.send_ack {
+            send_client_message(
+                &settings,
+                &socket,
+                encryption.as_ref(),
+                &mut session,
+                None,
+                ClientMessageData::Heartbeat,
+            )
+            .await?;
+        }
         let now = Instant::now();
         if now - last_recv >= settings.read_timeout {
             sender
@@ -328,6 +371,35 @@ pub async fn run_udp_client(
                 .ok();
             break;
         }
+        if session.resume_token.is_some()
+            && now - last_recv >= settings.reconnect_timeout
+            && now - last_reconnect >= settings.reconnect_timeout
+        {
+            info!("[{}] UDP client is reconnecting...", settings.id);
+            socket = connect_udp_socket(&settings).await?;
+            server_message_assembler = ServerMessageAssembler::default();
+            last_reconnect = now;
+            session.id = None;
+            session.secret = 0;
+            session.send_ack = false;
+            session.reliable_receiver = ReliableReceiver::default();
+            if let Some(ClientMessageData::Join { resume_token, .. }) = session.last_join.as_mut() {
+                *resume_token = session.resume_token;
+            }
+            let pending: Vec<ClientMessageData> = session
+                .reliable_sender
+                .clear()
+                .filter(|v| !matches!(v, ClientMessageData::Join { .. }))
+                .collect();
+            if let Some(join) = session.last_join.clone() {
+                session.reliable_sender.push(join);
+            }
+            for data in pending {
+                session.reliable_sender.push(data);
+            }
+            send_reliable_client_messages(&settings, &socket, encryption.as_ref(), &mut session)
+                .await?;
+        }
         let passed = now - last_update;
         let recv_timeout = if passed < update_period {
             update_period - passed
@@ -337,34 +409,75 @@ pub async fn run_udp_client(
         if let Ok(Ok(size)) =
             tokio::time::timeout(recv_timeout, socket.recv(&mut recv_buffer)).await
         {
+            if let Some(cookie) = get_challenge_cookie(&recv_buffer[0..size]) {
+                if session.id.is_none() {
+                    session.secret = cookie;
+                    session.reliable_sender.resend();
+                    send_reliable_client_messages(
+                        &settings,
+                        &socket,
+                        encryption.as_ref(),
+                        &mut session,
+                    )
+                    .await?;
+                }
+                continue;
+            }
+            let server_message =
+                match read_server_message(encryption.as_ref(), &recv_buffer[0..size]) {
+                    Ok(v) => v,
+                    Err(e @ DeserializeError::IncompatibleServerProtocolVersion(..))
+                        if session.id.is_none() =>
+                    {
+                        error!("[{}] {}", settings.id, e);
+                        if sender
+                            .send(ServerMessageData::Error(e.to_string()))
+                            .is_err()
+                        {
+                            break;
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!(
+                            "[{}] Failed to deserialize server message: {}",
+                            settings.id, e
+                        );
+                        continue;
+                    }
+                };
             last_recv = Instant::now();
-            let server_message = match deserialize_server_message(&recv_buffer[0..size]) {
-                Ok(v) => v,
-                Err(e) => {
+            if let Some(session_id) = session.id {
+                if session_id != server_message.session_id {
                     warn!(
-                        "[{}] Failed to deserialize server message: {}",
-                        settings.id, e
+                        "[{}] Received server message for invalid session: received={} expected={}",
+                        settings.id, session_id, server_message.session_id
                     );
                     continue;
                 }
-            };
-            if session_id.is_some() && session_id.unwrap() != server_message.session_id {
-                warn!(
-                    "[{}] Received server message for invalid session: received={} expected={}",
-                    settings.id,
-                    session_id.unwrap(),
-                    server_message.session_id
-                );
-                continue;
             }
-            if prev_received_message_number >= server_message.number {
+            let server_message = match server_message_assembler.add(server_message, last_recv) {
+                Some(v) => v,
+                None => continue,
+            };
+            session.reliable_sender.ack(server_message.ack);
+            if let Some(number) = server_message.reliable {
+                session.send_ack = true;
+                if !session.reliable_receiver.accept(number) {
+                    debug!(
+                        "[{}] Received duplicate or out of order server message with reliable number {}",
+                        settings.id, number
+                    );
+                    continue;
+                }
+            } else if prev_received_message_number >= server_message.number {
                 warn!(
                     "[{}] Received outdated server message: prev received number={} new received number={}",
                     settings.id, prev_received_message_number, server_message.number
                 );
                 continue;
             }
-            prev_received_message_number = server_message.number;
+            prev_received_message_number = server_message.number.max(prev_received_message_number);
             let data = match deserialize_server_message_data(
                 &server_message.data,
                 server_message.decompressed_data_size as usize,
@@ -378,15 +491,43 @@ pub async fn run_udp_client(
                     continue;
                 }
             };
-            if session_id.is_none() {
-                session_id = Some(server_message.session_id);
+            if session.id.is_none() {
+                session.id = Some(server_message.session_id);
             }
             if let ServerMessageData::NewPlayer {
-                update_period: v, ..
+                update_period: v,
+                quantization: q,
+                resume_token,
+                secret,
+                ..
             } = &data
             {
                 update_period = *v;
+                quantization = *q;
+                session.resume_token = Some(*resume_token);
+                session.secret = *secret;
             }
+            let data = match data {
+                ServerMessageData::QuantizedWorldUpdate {
+                    ack_actor_action_world_frame,
+                    ack_cast_action_world_frame,
+                    world_update,
+                } => match quantization
+                    .as_ref()
+                    .and_then(|v| dequantize_world_update(*world_update, v))
+                {
+                    Some(world_update) => ServerMessageData::GameUpdate(GameUpdate::WorldUpdate {
+                        ack_actor_action_world_frame,
+                        ack_cast_action_world_frame,
+                        world_update: Box::new(world_update),
+                    }),
+                    None => {
+                        warn!("[{}] Failed to dequantize world update", settings.id);
+                        continue;
+                    }
+                },
+                v => v,
+            };
             if let Err(e) = sender.send(data) {
                 debug!(
                     "[{}] UDP client has failed to send a message: {}",
@@ -400,29 +541,58 @@ pub async fn run_udp_client(
     Ok(())
 }
 
+struct UdpClientSession {
+    id: Option<u64>,
+    secret: u64,
+    message_number: u64,
+    last_join: Option<ClientMessageData>,
+    resume_token: Option<u64>,
+    reliable_sender: ReliableSender<ClientMessageData>,
+    reliable_receiver: ReliableReceiver,
+    send_ack: bool,
+}
+
+async fn connect_udp_socket(settings: &UdpClientSettings) -> std::io::Result<UdpSocket> {
+    let local_address = match settings.server_address {
+        SocketAddr::V4(..) => SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)),
+        SocketAddr::V6(..) => SocketAddr::V6(SocketAddrV6::new(Ipv6Addr::UNSPECIFIED, 0, 0, 0)),
+    };
+    let socket = UdpSocket::bind(local_address).await?;
+    info!(
+        "[{}] UDP client is listening on {}",
+        settings.id,
+        socket.local_addr().unwrap()
+    );
+    socket.connect(settings.server_address).await?;
+    info!(
+        "[{}] UDP client is connected to {}",
+        settings.id, settings.server_address
+    );
+    Ok(socket)
+}
+
 async fn send_client_messages(
     settings: &UdpClientSettings,
     receiver: &Receiver<ClientMessageData>,
     socket: &UdpSocket,
     until: Instant,
-    session_id: u64,
-    number: &mut u64,
+    encryption: Option<&Encryption>,
+    session: &mut UdpClientSession,
 ) -> std::io::Result<bool> {
     while Instant::now() < until {
         if let Ok(data) = receiver.try_recv() {
-            *number += 1;
-            let client_message = ClientMessage {
-                session_id,
-                number: *number,
-                data,
-            };
-            let buffer = bincode::serialize(&client_message).unwrap();
-            if let Err(e) = send_with_retries(socket, &buffer, 3).await {
-                error!(
-                    "[{}] UDP client has failed to send message to server: {}",
-                    settings.id, e
+            if matches!(data, ClientMessageData::Join { .. }) {
+                session.last_join = Some(data.clone());
+            }
+            if !is_reliable_client_message_data(&data) {
+                send_client_message(settings, socket, encryption, session, None, data).await?;
+            } else if session.reliable_sender.push(data).is_some() {
+                send_reliable_client_messages(settings, socket, encryption, session).await?;
+            } else {
+                warn!(
+                    "[{}] UDP client has dropped reliable message: too many pending messages",
+                    settings.id
                 );
-                return Err(e);
             }
         } else {
             break;
@@ -431,6 +601,83 @@ async fn send_client_messages(
     Ok(true)
 }
 
+async fn send_reliable_client_messages(
+    settings: &UdpClientSettings,
+    socket: &UdpSocket,
+    encryption: Option<&Encryption>,
+    session: &mut UdpClientSession,
+) -> std::io::Result<()> {
+    let messages: Vec<(u64, ClientMessageData)> = session
+        .reliable_sender
+        .poll(Instant::now())
+        .cloned()
+        .collect();
+    for (number, data) in messages {
+        send_client_message(settings, socket, encryption, session, Some(number), data).await?;
+    }
+    Ok(())
+}
+
+async fn send_client_message(
+    settings: &UdpClientSettings,
+    socket: &UdpSocket,
+    encryption: Option<&Encryption>,
+    session: &mut UdpClientSession,
+    reliable: Option<u64>,
+    data: ClientMessageData,
+) -> std::io::Result<()> {
+    session.message_number += 1;
+    session.send_ack = false;
+    let client_message = ClientMessage {
+        session_id: session.id.unwrap_or(0),
+        number: session.message_number,
+        data,
+        secret: session.secret,
+        reliable,
+        ack: session.reliable_receiver.ack(),
+    };
+    let mut buffer = serialize_client_message(&client_message);
+    if let Some(encryption) = encryption {
+        buffer = encryption.encrypt(
+            Direction::ClientToServer,
+            client_message.session_id,
+            client_message.number,
+            0,
+            &buffer,
+        );
+    }
+    if let Err(e) = send_with_retries(socket, &buffer, 3).await {
+        error!(
+            "[{}] UDP client has failed to send message to server: {}",
+            settings.id, e
+        );
+        return Err(e);
+    }
+    Ok(())
+}
+
+fn read_server_message(
+    encryption: Option<&Encryption>,
+    input: &[u8],
+) -> Result<ServerMessage, DeserializeError> {
+    if let Some(v) = get_rejected_protocol_version(input) {
+        return Err(DeserializeError::IncompatibleServerProtocolVersion(v));
+    }
+    let encryption = match encryption {
+        Some(v) => v,
+        None => return deserialize_server_message(input),
+    };
+    let (header, data) = encryption.decrypt(Direction::ServerToClient, input)?;
+    let server_message = deserialize_server_message(&data)?;
+    if header.session_id != server_message.session_id
+        || header.number != server_message.number
+        || header.fragment != server_message.fragment
+    {
+        return Err(DeserializeError::DecryptError);
+    }
+    Ok(server_message)
+}
+
 async fn send_with_retries(
     socket: &UdpSocket,
     buffer: &[u8],
@@ -456,6 +703,9 @@ pub struct GameClientSettings {
     pub connect_timeout: Duration,
     pub retry_period: Duration,
     pub player_name: String,
+    pub team: Option<TeamId>,
+    pub spectator: bool,
+    pub capabilities: Capabilities,
 }
 
 pub struct GameChannel {
@@ -479,10 +729,10 @@ pub fn run_game_client(
         Ok(v) => v,
         Err(e) => return Err(format!("Failed to join the server: {}", e)),
     };
-    info!(
-        "[{}] Joined to server as player {:?}",
-        settings.id, player_id.0
-    );
+    match player_id {
+        Some(v) => info!("[{}] Joined to server as player {:?}", settings.id, v.0),
+        None => info!("[{}] Joined to server as spectator", settings.id),
+    }
     let ServerChannel {
         sender: server_sender,
         receiver: server_receiver,
@@ -491,8 +741,13 @@ pub fn run_game_client(
         sender: game_sender,
         receiver: game_receiver,
     } = game;
-    if let Err(..) = game_sender.send(GameUpdate::SetPlayerId(player_id)) {
-        return Err(String::from("Failed to request player id."));
+    if let Some(player_id) = player_id {
+        if game_sender
+            .send(GameUpdate::SetPlayerId(player_id))
+            .is_err()
+        {
+            return Err(String::from("Failed to request player id."));
+        }
     }
     let client_id = settings.id;
     let stop_receiver = Arc::new(AtomicBool::new(false));
@@ -521,10 +776,18 @@ fn try_join_server(
     settings: &GameClientSettings,
     server: &ServerChannel,
     stop: &Arc<AtomicBool>,
-) -> Result<PlayerId, String> {
-    let now = Instant::now();
-    let connect_deadline = now + settings.connect_timeout;
-    let mut last_send = now - settings.retry_period;
+) -> Result<Option<PlayerId>, String> {
+    let connect_deadline = Instant::now() + settings.connect_timeout;
+    debug!("[{}] Game client is trying to join server...", settings.id);
+    if let Err(e) = server.sender.send(ClientMessageData::Join {
+        player_name: settings.player_name.clone(),
+        team: settings.team,
+        spectator: settings.spectator,
+        capabilities: settings.capabilities,
+        resume_token: None,
+    }) {
+        return Err(format!("Failed to send join message: {}", e));
+    }
     loop {
         if Instant::now() >= connect_deadline {
             info!(
@@ -536,26 +799,6 @@ fn try_join_server(
         if stop.load(Ordering::Acquire) {
             return Err(String::from("Aborted"));
         }
-        let since_last_send = Instant::now() - last_send;
-        if since_last_send < settings.retry_period {
-            sleep(settings.retry_period - since_last_send);
-        }
-        if stop.load(Ordering::Acquire) {
-            return Err(String::from("Aborted"));
-        }
-        debug!("[{}] Game client is trying to join server...", settings.id);
-        if let Err(e) = server
-            .sender
-            .send(ClientMessageData::Join(settings.player_name.clone()))
-        {
-            debug!(
-                "[{}] Game client has failed to send join message: {}",
-                settings.id, e
-            );
-            last_send = Instant::now();
-            continue;
-        }
-        last_send = Instant::now();
         debug!(
             "[{}] Game client is waiting for server response...",
             settings.id
@@ -583,10 +826,8 @@ fn try_join_server(
                     ),
                 }
             }
-            Err(e) => debug!(
-                "[{}] Game client has failed to receive message: {}",
-                settings.id, e
-            ),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(e) => return Err(format!("Failed to receive message: {}", e)),
         }
     }
 }
@@ -643,7 +884,8 @@ fn run_server_receiver(
     while !stop.load(Ordering::Acquire) {
         match receiver.recv() {
             Ok(data) => match data {
-                ServerMessageData::NewPlayer { .. } => (),
+                ServerMessageData::NewPlayer { .. }
+                | ServerMessageData::QuantizedWorldUpdate { .. } => (),
                 ServerMessageData::Error(error) => {
                     warn!("[{}] Server error: {}", client_id, error);
                 }
@@ -0,0 +1,200 @@
+use std::fmt::Formatter;
+use std::str::FromStr;
+
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::DeserializeError;
+
+pub const ENCRYPTION_KEY_SIZE: usize = 32;
+const SALT_MASK: u64 = 0xFFFF_FFFF_FFFF;
+
+#[derive(Clone, PartialEq)]
+pub struct EncryptionKey([u8; ENCRYPTION_KEY_SIZE]);
+
+impl FromStr for EncryptionKey {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if value.len() != 2 * ENCRYPTION_KEY_SIZE || !value.is_ascii() {
+            return Err(format!(
+                "Encryption key should contain {} hex digits",
+                2 * ENCRYPTION_KEY_SIZE
+            ));
+        }
+        let mut key = [0u8; ENCRYPTION_KEY_SIZE];
+        for (i, v) in key.iter_mut().enumerate() {
+            *v = u8::from_str_radix(&value[2 * i..2 * i + 2], 16)
+                .map_err(|e| format!("Invalid encryption key: {}", e))?;
+        }
+        Ok(Self(key))
+    }
+}
+
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptionKey(..)")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+impl Direction {
+    fn aad(&self) -> &'static [u8] {
+        match self {
+            Direction::ClientToServer => b"client",
+            Direction::ServerToClient => b"server",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub struct EncryptedMessageHeader {
+    pub salt: u64,
+    pub session_id: u64,
+    pub number: u64,
+    pub fragment: u16,
+}
+
+#[derive(Deserialize, Serialize)]
+struct EncryptedMessage {
+    header: EncryptedMessageHeader,
+    data: Vec<u8>,
+}
+
+pub struct Encryption {
+    cipher: XChaCha20Poly1305,
+    salt: u64,
+}
+
+impl Encryption {
+    pub fn new(key: &EncryptionKey, salt: u64) -> Self {
+        Self {
+            cipher: XChaCha20Poly1305::new(&Key::from(key.0)),
+            salt: salt & SALT_MASK,
+        }
+    }
+
+    pub fn encrypt(
+        &self,
+        direction: Direction,
+        session_id: u64,
+        number: u64,
+        fragment: u16,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let header = EncryptedMessageHeader {
+            salt: self.salt,
+            session_id,
+            number,
+            fragment,
+        };
+        let payload = Payload {
+            msg: data,
+            aad: direction.aad(),
+        };
+        let data = self.cipher.encrypt(&make_nonce(&header), payload).unwrap();
+        bincode::serialize(&EncryptedMessage { header, data }).unwrap()
+    }
+
+    pub fn decrypt(
+        &self,
+        direction: Direction,
+        input: &[u8],
+    ) -> Result<(EncryptedMessageHeader, Vec<u8>), DeserializeError> {
+        let message: EncryptedMessage = match bincode::deserialize(input) {
+            Ok(v) => v,
+            Err(e) => return Err(DeserializeError::DeserializeError(e)),
+        };
+        if message.header.salt & !SALT_MASK != 0 {
+            return Err(DeserializeError::DecryptError);
+        }
+        let payload = Payload {
+            msg: &message.data,
+            aad: direction.aad(),
+        };
+        match self.cipher.decrypt(&make_nonce(&message.header), payload) {
+            Ok(v) => Ok((message.header, v)),
+            Err(..) => Err(DeserializeError::DecryptError),
+        }
+    }
+}
+
+fn make_nonce(header: &EncryptedMessageHeader) -> XNonce {
+    let mut nonce = [0u8; 24];
+    nonce[0..6].copy_from_slice(&header.salt.to_le_bytes()[0..6]);
+    nonce[6..8].copy_from_slice(&header.fragment.to_le_bytes());
+    nonce[8..16].copy_from_slice(&header.session_id.to_le_bytes());
+    nonce[16..24].copy_from_slice(&header.number.to_le_bytes());
+    XNonce::from(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn encryption_key_should_be_parsed_from_hex() {
+        let key = EncryptionKey::from_str(KEY).unwrap();
+        assert_eq!(key.0[0], 0);
+        assert_eq!(key.0[31], 31);
+        assert_eq!(format!("{:?}", key), "EncryptionKey(..)");
+        assert!(EncryptionKey::from_str("0001").is_err());
+        assert!(EncryptionKey::from_str(&KEY.replace('0', "z")).is_err());
+    }
+
+    #[test]
+    fn decrypt_should_return_encrypted_data() {
+        let key = EncryptionKey::from_str(KEY).unwrap();
+        let sender = Encryption::new(&key, 42);
+        let receiver = Encryption::new(&key, 13);
+        let encrypted = sender.encrypt(Direction::ServerToClient, 1, 2, 3, b"data");
+        let (header, data) = receiver
+            .decrypt(Direction::ServerToClient, &encrypted)
+            .unwrap();
+        assert_eq!(
+            header,
+            EncryptedMessageHeader {
+                salt: 42,
+                session_id: 1,
+                number: 2,
+                fragment: 3,
+            }
+        );
+        assert_eq!(data, b"data");
+    }
+
+    #[test]
+    fn decrypt_should_fail_for_modified_or_reflected_message() {
+        let key = EncryptionKey::from_str(KEY).unwrap();
+        let encryption = Encryption::new(&key, 42);
+        let encrypted = encryption.encrypt(Direction::ClientToServer, 1, 2, 0, b"data");
+        assert!(encryption
+            .decrypt(Direction::ServerToClient, &encrypted)
+            .is_err());
+        let mut modified = encrypted.clone();
+        *modified.last_mut().unwrap() ^= 1;
+        assert!(encryption
+            .decrypt(Direction::ClientToServer, &modified)
+            .is_err());
+        let mut message: EncryptedMessage = bincode::deserialize(&encrypted).unwrap();
+        message.header.number = 3;
+        assert!(encryption
+            .decrypt(
+                Direction::ClientToServer,
+                &bincode::serialize(&message).unwrap()
+            )
+            .is_err());
+        let other = Encryption::new(&EncryptionKey([1; ENCRYPTION_KEY_SIZE]), 42);
+        assert!(other
+            .decrypt(Direction::ClientToServer, &encrypted)
+            .is_err());
+    }
+}
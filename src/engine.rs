@@ -7,20 +7,25 @@ use parry2d_f64::query::{Contact, Ray, TOI};
 use parry2d_f64::shape::{Ball, Cuboid, Polyline, Shape, Triangle};
 use rand::Rng;
 
+use crate::broadphase::UniformGrid;
+use crate::control::{apply_actor_action, apply_cast_action};
 use crate::generators::generate_player_actor;
+use crate::index::WorldIndex;
+use crate::protocol::{ActorAction, CastAction, WorldEvent};
 use crate::rect::Rectf;
 use crate::vec2::Vec2f;
-#[cfg(feature = "server")]
-use crate::world::PlayerId;
 use crate::world::{
     Actor, ActorId, ActorOccupation, Aura, Beam, BeamId, Body, BoundedArea, BoundedAreaId,
     CircleArc, DelayedMagick, DelayedMagickStatus, Disk, Effect, Element, Field, FieldId, Gun,
-    GunId, Magick, MaterialType, Projectile, ProjectileId, Rectangle, RingSector, Shield, ShieldId,
-    StaticArea, StaticAreaShape, StaticObject, StaticShape, TempArea, TempAreaId, TempObstacle,
+    GunId, LightningBolt, LightningBoltId, Magick, MaterialType, Mine, MineId, ObjectId, Player,
+    PlayerId, Projectile, ProjectileId, Rectangle, RingSector, Shield, ShieldId, StaticArea,
+    StaticAreaShape, StaticObject, StaticShape, TeamId, TempArea, TempAreaId, TempObstacle,
     TempObstacleId, World, WorldSettings,
 };
 
 const RESOLUTION_FACTOR: f64 = 4.0;
+const BROADPHASE_CELL_SIZE: f64 = 4.0;
+const BROADPHASE_MARGIN: f64 = 1e-2;
 const DEFAULT_AURA: Aura = Aura {
     applied: 0.0,
     power: 0.0,
@@ -30,6 +35,7 @@ const DEFAULT_AURA: Aura = Aura {
 const DEFAULT_EFFECT: Effect = Effect {
     applied: [0.0; 11],
     power: [0.0; 11],
+    sources: [None; 11],
 };
 const DEFAULT_MAGICK: Magick = Magick { power: [0.0; 11] };
 const DEFAULT_RESISTANCE: [bool; 11] = [false; 11];
@@ -84,6 +90,7 @@ impl<'a> Spell<'a> {
 
 #[derive(Default)]
 pub struct EmittedBeam {
+    pub actor_id: ActorId,
     pub origin: Vec2f,
     pub direction: Vec2f,
     pub length: f64,
@@ -95,56 +102,258 @@ pub struct EmittedBeam {
 pub struct BeamCollider {
     initial_beams: Vec<EmittedBeam>,
     reflected_beams: Vec<EmittedBeam>,
+    beam_order: Vec<usize>,
+    broadphase: Broadphase,
 }
 
 impl BeamCollider {
-    fn update(&mut self, world: &mut World, shape_cache: &ShapeCache) {
+    fn update(
+        &mut self,
+        world: &mut World,
+        shape_cache: &ShapeCache,
+        index: &WorldIndex,
+        lag_compensation: &LagCompensation,
+    ) {
         self.initial_beams.clear();
         self.reflected_beams.clear();
-        for i in 0..world.beams.len() {
-            let beam = &world.beams[i];
-            let actor = world.actors.iter().find(|v| v.id == beam.actor_id).unwrap();
-            let direction = actor.current_direction;
-            let origin =
-                actor.position + direction * (actor.body.shape.radius + world.settings.margin);
-            let magick = beam.magick.clone();
-            let mut length = world.settings.max_beam_length;
-            if let Some(r) = intersect_beam(
-                &magick,
-                origin,
-                direction,
-                0,
-                &mut length,
-                world,
-                shape_cache,
-            ) {
-                self.reflected_beams.push(r);
+        if world.beams.is_empty() {
+            return;
+        }
+        self.broadphase.build_obstacles(world, shape_cache);
+        self.broadphase.build_movables(0.0, world, shape_cache);
+        self.beam_order.clear();
+        self.beam_order.extend(0..world.beams.len());
+        self.beam_order.sort_by_key(|i| world.beams[*i].actor_id.0);
+        let mut rewound = false;
+        let mut group_begin = 0;
+        while group_begin < self.beam_order.len() {
+            let actor_id = world.beams[self.beam_order[group_begin]].actor_id;
+            let group_end = self.beam_order[group_begin..]
+                .iter()
+                .position(|i| world.beams[*i].actor_id != actor_id)
+                .map_or(self.beam_order.len(), |v| group_begin + v);
+            let positions = lag_compensation.rewind(actor_id, world);
+            if rewound || !positions.is_empty() {
+                self.broadphase.build_movables(0.0, world, shape_cache);
             }
-            self.initial_beams.push(EmittedBeam {
-                origin,
-                direction,
-                length,
-                depth: 0,
-                magick,
-            });
+            rewound = !positions.is_empty();
+            let reflected_begin = self.reflected_beams.len();
+            for i in group_begin..group_end {
+                let beam = &world.beams[self.beam_order[i]];
+                let actor = &world.actors[index.actor(actor_id).unwrap()];
+                let direction = actor.current_direction;
+                let origin =
+                    actor.position + direction * (actor.body.shape.radius + world.settings.margin);
+                let magick = beam.magick.clone();
+                let mut length = world.settings.max_beam_length;
+                if let Some(r) = intersect_beam(
+                    actor_id,
+                    &magick,
+                    origin,
+                    direction,
+                    0,
+                    &mut length,
+                    world,
+                    shape_cache,
+                    &self.broadphase,
+                ) {
+                    self.reflected_beams.push(r);
+                }
+                self.initial_beams.push(EmittedBeam {
+                    actor_id,
+                    origin,
+                    direction,
+                    length,
+                    depth: 0,
+                    magick,
+                });
+            }
+            let mut beam_index = reflected_begin;
+            while beam_index < self.reflected_beams.len() {
+                let beam = &mut self.reflected_beams[beam_index];
+                let origin = beam.origin + beam.direction * world.settings.margin;
+                if let Some(r) = intersect_beam(
+                    beam.actor_id,
+                    &beam.magick,
+                    origin,
+                    beam.direction,
+                    beam.depth,
+                    &mut beam.length,
+                    world,
+                    shape_cache,
+                    &self.broadphase,
+                ) {
+                    beam.length += world.settings.margin;
+                    self.reflected_beams.push(r);
+                }
+                beam_index += 1;
+            }
+            for (i, position) in positions {
+                world.actors[i].position = position;
+            }
+            group_begin = group_end;
         }
-        let mut beam_index = 0;
-        while beam_index < self.reflected_beams.len() {
-            let beam = &mut self.reflected_beams[beam_index];
-            let origin = beam.origin + beam.direction * world.settings.margin;
-            if let Some(r) = intersect_beam(
-                &beam.magick,
-                origin,
-                beam.direction,
-                beam.depth,
-                &mut beam.length,
-                world,
-                shape_cache,
-            ) {
-                beam.length += world.settings.margin;
-                self.reflected_beams.push(r);
+    }
+}
+
+#[derive(Default)]
+#[cfg_attr(not(feature = "client"), allow(dead_code))]
+pub struct EmittedLightningArc {
+    pub origin: Vec2f,
+    pub target: Vec2f,
+    pub magick: Magick,
+}
+
+#[derive(Default)]
+pub struct LightningCollider {
+    arcs: Vec<EmittedLightningArc>,
+}
+
+impl LightningCollider {
+    fn update(&mut self, world: &mut World, shape_cache: &ShapeCache, index: &WorldIndex) {
+        self.arcs.clear();
+        for i in 0..world.lightning_bolts.len() {
+            let bolt = &world.lightning_bolts[i];
+            let actor_id = bolt.actor_id;
+            let actor_index = index.actor(actor_id).unwrap();
+            let magick = bolt.magick.clone();
+            let mut struck = vec![Index::Actor(actor_index)];
+            if let Some(spread) = bolt.spread {
+                let actor = &world.actors[actor_index];
+                let targets = find_lightning_targets(
+                    actor.position,
+                    actor.current_direction,
+                    spread,
+                    &struck,
+                    world,
+                );
+                for (target, position) in targets {
+                    self.strike(
+                        actor_id,
+                        actor_index,
+                        target,
+                        position,
+                        &magick,
+                        &mut struck,
+                        world,
+                        shape_cache,
+                    );
+                }
+            } else {
+                let mut source = actor_index;
+                let mut angle = world.settings.lightning_angle;
+                for _ in 0..=world.settings.max_lightning_jumps {
+                    let actor = &world.actors[source];
+                    let (target, position) = match find_lightning_targets(
+                        actor.position,
+                        actor.current_direction,
+                        angle,
+                        &struck,
+                        world,
+                    )
+                    .first()
+                    {
+                        Some(v) => *v,
+                        None => break,
+                    };
+                    match self.strike(
+                        actor_id,
+                        source,
+                        target,
+                        position,
+                        &magick,
+                        &mut struck,
+                        world,
+                        shape_cache,
+                    ) {
+                        Some((v, grounded)) => {
+                            if grounded {
+                                break;
+                            }
+                            source = v;
+                        }
+                        None => break,
+                    }
+                    angle = std::f64::consts::TAU;
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn strike(
+        &mut self,
+        actor_id: ActorId,
+        source: usize,
+        target: Index,
+        position: Vec2f,
+        magick: &Magick,
+        struck: &mut Vec<Index>,
+        world: &mut World,
+        shape_cache: &ShapeCache,
+    ) -> Option<(usize, bool)> {
+        let actor = &world.actors[source];
+        let direction = (position - actor.position).safe_normalized()?;
+        let origin = actor.position + direction * (actor.body.shape.radius + world.settings.margin);
+        let mut length = world.settings.max_lightning_length;
+        let index = match find_lightning_nearest_intersection(
+            origin,
+            direction,
+            &mut length,
+            world,
+            shape_cache,
+        ) {
+            Some(v) => v,
+            None => {
+                length = origin.distance(position);
+                target
+            }
+        };
+        if index != target && struck.contains(&index) {
+            return None;
+        }
+        struck.push(index);
+        self.arcs.push(EmittedLightningArc {
+            origin,
+            target: origin + direction * length,
+            magick: magick.clone(),
+        });
+        match index {
+            Index::Actor(i) => {
+                if can_block_lightning(&world.actors[i].aura.elements) {
+                    return None;
+                }
+                let grounded = can_ground_lightning(&world.actors[i].effect);
+                world.actors[i].effect = add_magick_to_effect(
+                    world.time,
+                    &world.actors[i].effect,
+                    magick,
+                    Some(actor_id),
+                    &world.actors[i].aura.elements,
+                );
+                Some((i, grounded))
+            }
+            Index::StaticObject(i) => {
+                world.static_objects[i].effect = add_magick_to_effect(
+                    world.time,
+                    &world.static_objects[i].effect,
+                    magick,
+                    Some(actor_id),
+                    &DEFAULT_RESISTANCE,
+                );
+                None
+            }
+            Index::TempObstacle(i) => {
+                world.temp_obstacles[i].effect = add_magick_to_effect(
+                    world.time,
+                    &world.temp_obstacles[i].effect,
+                    magick,
+                    Some(actor_id),
+                    &DEFAULT_RESISTANCE,
+                );
+                None
             }
-            beam_index += 1;
+            Index::Projectile(..) | Index::Shield(..) => None,
         }
     }
 }
@@ -175,11 +384,126 @@ impl ShapeCache {
     }
 }
 
+#[derive(Default)]
+struct Broadphase {
+    movables: UniformGrid<Index>,
+    obstacles: UniformGrid<Index>,
+    temp_areas: UniformGrid<usize>,
+    static_areas: UniformGrid<usize>,
+    mines: UniformGrid<usize>,
+}
+
+impl Broadphase {
+    fn set_exhaustive(&mut self, value: bool) {
+        self.movables.set_exhaustive(value);
+        self.obstacles.set_exhaustive(value);
+        self.temp_areas.set_exhaustive(value);
+        self.static_areas.set_exhaustive(value);
+        self.mines.set_exhaustive(value);
+    }
+
+    fn build_movables(&mut self, duration: f64, world: &World, shape_cache: &ShapeCache) {
+        self.movables.reset(&world.bounds, BROADPHASE_CELL_SIZE);
+        for (i, v) in world.actors.iter().enumerate() {
+            self.movables
+                .insert(Index::Actor(i), get_swept_bounds(v, duration, shape_cache));
+        }
+        for (i, v) in world.projectiles.iter().enumerate() {
+            self.movables.insert(
+                Index::Projectile(i),
+                get_swept_bounds(v, duration, shape_cache),
+            );
+        }
+    }
+
+    fn build_obstacles(&mut self, world: &World, shape_cache: &ShapeCache) {
+        self.obstacles.reset(&world.bounds, BROADPHASE_CELL_SIZE);
+        for (i, v) in world.static_objects.iter().enumerate() {
+            self.obstacles
+                .insert(Index::StaticObject(i), get_bounds(v, shape_cache));
+        }
+        for (i, v) in world.shields.iter().enumerate() {
+            self.obstacles
+                .insert(Index::Shield(i), get_bounds(v, shape_cache));
+        }
+        for (i, v) in world.temp_obstacles.iter().enumerate() {
+            self.obstacles
+                .insert(Index::TempObstacle(i), get_bounds(v, shape_cache));
+        }
+    }
+
+    fn build_temp_areas(&mut self, world: &World, shape_cache: &ShapeCache) {
+        build_grid(
+            &world.bounds,
+            &world.temp_areas,
+            shape_cache,
+            &mut self.temp_areas,
+        );
+    }
+
+    fn build_static_areas(&mut self, world: &World, shape_cache: &ShapeCache) {
+        build_grid(
+            &world.bounds,
+            &world.static_areas,
+            shape_cache,
+            &mut self.static_areas,
+        );
+    }
+
+    fn build_mines(&mut self, world: &World, shape_cache: &ShapeCache) {
+        build_grid(&world.bounds, &world.mines, shape_cache, &mut self.mines);
+    }
+
+    fn query_segment(&self, begin: Vec2f, end: Vec2f, result: &mut Vec<Index>) {
+        let mut obstacles = Vec::new();
+        self.movables.query_segment(begin, end, result);
+        self.obstacles.query_segment(begin, end, &mut obstacles);
+        result.extend(obstacles);
+    }
+}
+
+fn build_grid<T>(
+    bounds: &Rectf,
+    objects: &[T],
+    shape_cache: &ShapeCache,
+    grid: &mut UniformGrid<usize>,
+) where
+    T: WithIsometry + WithShape,
+{
+    grid.reset(bounds, BROADPHASE_CELL_SIZE);
+    for (i, v) in objects.iter().enumerate() {
+        grid.insert(i, get_bounds(v, shape_cache));
+    }
+}
+
+fn get_bounds<T>(object: &T, shape_cache: &ShapeCache) -> Rectf
+where
+    T: WithIsometry + WithShape,
+{
+    let isometry = object.get_isometry();
+    let aabb = with_shape(object, shape_cache, |shape| shape.compute_aabb(&isometry));
+    Rectf::new(Vec2f::from(&aabb.mins), Vec2f::from(&aabb.maxs)).extended(BROADPHASE_MARGIN)
+}
+
+fn get_swept_bounds<T>(object: &T, duration: f64, shape_cache: &ShapeCache) -> Rectf
+where
+    T: WithVelocity + WithIsometry + WithShape,
+{
+    let bounds = get_bounds(object, shape_cache);
+    bounds.union(&bounds.translated(object.velocity() * duration))
+}
+
 #[derive(Default)]
 pub struct Engine {
     beam_collider: BeamCollider,
+    lightning_collider: LightningCollider,
     shape_cache: ShapeCache,
+    broadphase: Broadphase,
+    index: WorldIndex,
+    lag_compensation: LagCompensation,
     events: Vec<EngineEvent>,
+    world_events: Vec<WorldEvent>,
+    pending_world_events: Vec<WorldEvent>,
 }
 
 impl Engine {
@@ -193,24 +517,126 @@ impl Engine {
         &self.beam_collider.reflected_beams
     }
 
+    #[cfg(feature = "client")]
+    pub fn emitted_lightning_arcs(&self) -> &Vec<EmittedLightningArc> {
+        &self.lightning_collider.arcs
+    }
+
+    pub fn index(&self) -> &WorldIndex {
+        &self.index
+    }
+
+    #[doc(hidden)]
+    pub fn set_exhaustive_broadphase(&mut self, value: bool) {
+        self.broadphase.set_exhaustive(value);
+        self.beam_collider.broadphase.set_exhaustive(value);
+    }
+
+    pub fn rebuild_index(&mut self, world: &World) {
+        self.index.rebuild(world);
+    }
+
+    pub fn world_events(&self) -> &Vec<WorldEvent> {
+        &self.world_events
+    }
+
+    pub fn add_lag_compensation(&mut self, actor_id: ActorId, past_world: &World) {
+        self.lag_compensation.add(actor_id, past_world);
+    }
+
+    pub fn apply_actor_action(
+        &mut self,
+        actor_action: ActorAction,
+        actor_index: usize,
+        world: &mut World,
+    ) {
+        let begin = self.pending_world_events.len();
+        self.index.update(world);
+        apply_actor_action(
+            actor_action,
+            actor_index,
+            world,
+            &self.index,
+            &mut self.pending_world_events,
+        );
+        self.attribute_cast_events(begin, world);
+    }
+
+    pub fn apply_cast_action(
+        &mut self,
+        cast_action: CastAction,
+        actor_index: usize,
+        world: &mut World,
+    ) {
+        let begin = self.pending_world_events.len();
+        self.index.update(world);
+        apply_cast_action(
+            cast_action,
+            actor_index,
+            world,
+            &self.index,
+            &mut self.pending_world_events,
+        );
+        self.attribute_cast_events(begin, world);
+    }
+
+    fn attribute_cast_events(&mut self, begin: usize, world: &mut World) {
+        for event in self.pending_world_events[begin..].iter() {
+            if let WorldEvent::Damage {
+                source: Some(ObjectId::Actor(source)),
+                target: ObjectId::Actor(target),
+                physical,
+                ..
+            } = event
+            {
+                credit_damage(*source, *target, *physical, world, &self.index);
+            }
+        }
+    }
+
+    pub fn remove_player(&mut self, player_id: PlayerId, world: &mut World) {
+        self.index.update(world);
+        if let Some(player_index) = self.index.player(player_id) {
+            world.players[player_index].active = false;
+            if let Some(actor_index) = self.index.player_actor(player_id) {
+                world.actors[actor_index].active = false;
+            }
+        }
+    }
+
     pub fn update<R: Rng>(&mut self, duration: f64, world: &mut World, rng: &mut R) {
         world.frame += 1;
         world.time += duration;
         let now = world.time;
-        remove_intersecting_objects(world, &self.shape_cache);
+        self.world_events.clear();
+        self.world_events.append(&mut self.pending_world_events);
+        remove_intersecting_objects(world, &self.shape_cache, &mut self.broadphase);
         world.bounded_areas.retain(|v| v.deadline >= now);
         world.fields.retain(|v| v.deadline >= now);
         world.beams.retain(|v| v.deadline >= now);
+        world.projectiles.retain(|v| v.deadline >= now);
+        world.lightning_bolts.retain(|v| v.deadline >= now);
         world.temp_areas.retain(|v| v.deadline >= now);
         world.guns.retain(|v| v.shots_left > 0);
         world.shields.retain(|v| v.power > 0.0);
         world.temp_obstacles.retain(|v| v.deadline >= now);
+        world.mines.retain(|v| v.deadline >= now);
+        self.index.rebuild(world);
         self.events.clear();
-        update_actor_occupations(world);
-        spawn_player_actors(world, rng);
-        shoot_from_guns(world, rng);
-        intersect_objects_with_areas(world, &self.shape_cache);
-        intersect_objects_with_all_fields(world);
+        update_actor_occupations(world, &self.index);
+        spawn_player_actors(world, &mut self.world_events, rng);
+        self.index.update(world);
+        shoot_from_guns(world, &self.index, rng);
+        self.index.update(world);
+        intersect_objects_with_areas(
+            world,
+            &self.shape_cache,
+            &mut self.broadphase,
+            &self.index,
+            &self.lag_compensation,
+        );
+        intersect_objects_with_all_fields(world, &self.index);
+        detonate_mines(world, &self.shape_cache);
         update_actors(
             world.time,
             duration,
@@ -234,8 +660,15 @@ impl Engine {
             &mut self.events,
         );
         self.update_beams(world);
-        move_objects(duration, world, &self.shape_cache, &mut self.events);
-        handle_events(&self.events, world);
+        self.update_lightning_bolts(world);
+        move_objects(
+            duration,
+            world,
+            &self.shape_cache,
+            &mut self.broadphase,
+            &mut self.events,
+        );
+        handle_events(&self.events, world, &self.index, &mut self.world_events);
         world
             .actors
             .iter_mut()
@@ -245,10 +678,23 @@ impl Engine {
             .iter_mut()
             .for_each(|v| v.dynamic_force = Vec2f::ZERO);
         let bounds = world.bounds.clone();
-        world.actors.iter_mut().for_each(|v| {
-            v.active =
-                v.active && is_active(&bounds, &v.body.shape.as_shape(), v.position, v.health)
-        });
+        for actor in world.actors.iter_mut() {
+            if actor.active
+                && !is_active(
+                    &bounds,
+                    &actor.body.shape.as_shape(),
+                    actor.position,
+                    actor.health,
+                )
+            {
+                actor.active = false;
+                self.world_events.push(WorldEvent::Death {
+                    actor_id: actor.id,
+                    player_id: actor.player_id,
+                    killer: credit_kill(&actor.damagers, &mut world.players),
+                });
+            }
+        }
         remove_inactive_actors_occupation_results(world);
         world.players.retain(|v| v.active);
         world.actors.retain(|v| v.active);
@@ -263,17 +709,82 @@ impl Engine {
         });
         world.shields.retain(|v| v.power > 0.0);
         world.temp_obstacles.retain(|v| v.health > 0.0);
+        self.index.rebuild(world);
         handle_completed_magicks(world);
-        update_player_spawn_time(world);
+        update_player_spawn_time(world, &self.index);
+        self.lag_compensation.clear();
     }
 
     #[cfg(feature = "client")]
     pub fn update_visual(&mut self, world: &mut World) {
+        self.index.rebuild(world);
         self.update_beams(world);
+        self.update_lightning_bolts(world);
+    }
+
+    #[cfg(feature = "client")]
+    pub fn prepare_move_actor(&mut self, world: &World) {
+        self.broadphase.build_obstacles(world, &self.shape_cache);
+        self.broadphase
+            .build_movables(0.0, world, &self.shape_cache);
+    }
+
+    #[cfg(feature = "client")]
+    pub fn move_actor(&self, now: f64, duration: f64, world: &World, actor: &mut Actor) {
+        let mut effect = actor.effect.clone();
+        intersect_with_last_static_area(
+            now,
+            world.settings.gravitational_acceleration,
+            world.static_areas.iter(),
+            &mut IntersectingDynamicObject {
+                shape: &Ball::new(actor.body.shape.radius),
+                velocity: actor.velocity,
+                isometry: Isometry::translation(actor.position.x, actor.position.y),
+                movement_type: get_actor_movement_type(actor),
+                mass: actor.body.mass(),
+                resistance: &actor.aura.elements,
+                dynamic_force: &mut actor.dynamic_force,
+                effect: &mut effect,
+            },
+            &self.shape_cache,
+        );
+        update_actor_current_direction(now, duration, world.settings.max_rotation_speed, actor);
+        update_actor_dynamic_force(
+            duration,
+            world.settings.move_force,
+            world.settings.max_actor_speed,
+            actor,
+        );
+        update_velocity(
+            duration,
+            actor.body.mass(),
+            actor.dynamic_force,
+            world.settings.min_move_distance,
+            &mut actor.velocity,
+        );
+        move_actor_among_obstacles(
+            now,
+            duration,
+            world,
+            &self.shape_cache,
+            &self.broadphase,
+            actor,
+        );
+        actor.dynamic_force = Vec2f::ZERO;
     }
 
     fn update_beams(&mut self, world: &mut World) {
-        self.beam_collider.update(world, &self.shape_cache);
+        self.beam_collider.update(
+            world,
+            &self.shape_cache,
+            &self.index,
+            &self.lag_compensation,
+        );
+    }
+
+    fn update_lightning_bolts(&mut self, world: &mut World) {
+        self.lightning_collider
+            .update(world, &self.shape_cache, &self.index);
     }
 }
 
@@ -283,16 +794,6 @@ pub fn get_next_id(counter: &mut u64) -> u64 {
     result
 }
 
-#[cfg(feature = "server")]
-pub fn remove_player(player_id: PlayerId, world: &mut World) {
-    if let Some(player) = world.players.iter_mut().find(|v| v.id == player_id) {
-        player.active = false;
-        if let Some(actor) = world.actors.iter_mut().find(|v| v.player_id == player_id) {
-            actor.active = false;
-        }
-    }
-}
-
 pub fn add_actor_spell_element(actor_index: usize, element: Element, world: &mut World) {
     if !matches!(world.actors[actor_index].occupation, ActorOccupation::None)
         || is_actor_flying(&world.actors[actor_index])
@@ -313,7 +814,6 @@ pub fn add_actor_spell_element(actor_index: usize, element: Element, world: &mut
     .add(element);
 }
 
-#[allow(clippy::needless_return)]
 pub fn start_directed_magick(actor_index: usize, world: &mut World) {
     if !matches!(world.actors[actor_index].occupation, ActorOccupation::None)
         || is_actor_flying(&world.actors[actor_index])
@@ -336,7 +836,13 @@ pub fn start_directed_magick(actor_index: usize, world: &mut World) {
     {
         add_beam(magick, actor_index, world);
     } else if magick.power[Element::Lightning as usize] > 0.0 {
-        return;
+        add_lightning_bolt(
+            None,
+            world.settings.directed_magick_duration,
+            magick,
+            actor_index,
+            world,
+        );
     } else if magick.power[Element::Water as usize] > 0.0
         || magick.power[Element::Cold as usize] > 0.0
         || magick.power[Element::Fire as usize] > 0.0
@@ -353,9 +859,13 @@ pub fn start_directed_magick(actor_index: usize, world: &mut World) {
     }
 }
 
-#[allow(clippy::needless_return)]
 #[allow(clippy::if_same_then_else)]
-pub fn start_area_of_effect_magick(actor_index: usize, world: &mut World) {
+pub fn start_area_of_effect_magick(
+    actor_index: usize,
+    world: &mut World,
+    index: &WorldIndex,
+    events: &mut Vec<WorldEvent>,
+) {
     if !matches!(world.actors[actor_index].occupation, ActorOccupation::None)
         || is_actor_flying(&world.actors[actor_index])
     {
@@ -369,9 +879,9 @@ pub fn start_area_of_effect_magick(actor_index: usize, world: &mut World) {
     if magick.power[Element::Shield as usize] > 0.0 {
         cast_shield(std::f64::consts::TAU, magick, actor_index, world);
     } else if magick.power[Element::Earth as usize] > 0.0 {
-        return;
+        cast_quake(magick, actor_index, world, index, events);
     } else if magick.power[Element::Ice as usize] > 0.0 {
-        return;
+        cast_ice_spikes(magick, actor_index, world);
     } else if magick.power[Element::Arcane as usize] > 0.0
         || magick.power[Element::Life as usize] > 0.0
     {
@@ -383,7 +893,13 @@ pub fn start_area_of_effect_magick(actor_index: usize, world: &mut World) {
             world,
         );
     } else if magick.power[Element::Lightning as usize] > 0.0 {
-        return;
+        add_lightning_bolt(
+            Some(std::f64::consts::TAU),
+            world.settings.area_of_effect_magick_duration,
+            magick,
+            actor_index,
+            world,
+        );
     } else if magick.power[Element::Water as usize] > 0.0
         || magick.power[Element::Cold as usize] > 0.0
         || magick.power[Element::Fire as usize] > 0.0
@@ -424,9 +940,9 @@ fn cast_shield(angle: f64, magick: Magick, actor_index: usize, world: &mut World
     } else if magick.power[Element::Arcane as usize] > 0.0
         || magick.power[Element::Life as usize] > 0.0
     {
-        return;
+        cast_mine_based_shield(angle, magick, actor_index, world);
     } else if magick.power[Element::Lightning as usize] > 0.0 {
-        return;
+        cast_lightning_shield(angle, magick, actor_index, world);
     } else if magick.power[Element::Water as usize] > 0.0
         || magick.power[Element::Cold as usize] > 0.0
         || magick.power[Element::Fire as usize] > 0.0
@@ -476,6 +992,45 @@ fn cast_obstacle_based_shield(
     }
 }
 
+fn cast_mine_based_shield(angle: f64, mut magick: Magick, actor_index: usize, world: &mut World) {
+    let actor = &world.actors[actor_index];
+    let distance = 5.0;
+    magick.power[Element::Shield as usize] = 0.0;
+    let number = get_number_of_shield_objects(angle);
+    let half = number / 2;
+    for i in -half..number - half {
+        world.mines.push(Mine {
+            id: MineId(get_next_id(&mut world.id_counter)),
+            actor_id: actor.id,
+            body: Body {
+                shape: Disk {
+                    radius: distance * std::f64::consts::PI / (2 * 5 * 2) as f64,
+                },
+                material_type: MaterialType::None,
+            },
+            position: actor.position
+                + actor
+                    .current_direction
+                    .rotated(i as f64 * std::f64::consts::PI / (2 * 5) as f64)
+                    * (distance + 0.1),
+            magick: magick.clone(),
+            detonated: false,
+            deadline: world.time + world.settings.mine_duration,
+        });
+    }
+}
+
+fn cast_lightning_shield(angle: f64, mut magick: Magick, actor_index: usize, world: &mut World) {
+    magick.power[Element::Shield as usize] = 0.0;
+    add_lightning_bolt(
+        Some(angle),
+        world.settings.area_of_effect_magick_duration,
+        magick,
+        actor_index,
+        world,
+    );
+}
+
 fn cast_spray_based_shield(angle: f64, mut magick: Magick, actor_index: usize, world: &mut World) {
     let actor = &world.actors[actor_index];
     let distance = 5.0;
@@ -485,6 +1040,7 @@ fn cast_spray_based_shield(angle: f64, mut magick: Magick, actor_index: usize, w
     for i in -half..number - half {
         world.temp_areas.push(TempArea {
             id: TempAreaId(get_next_id(&mut world.id_counter)),
+            actor_id: actor.id,
             body: Body {
                 shape: Disk {
                     radius: distance * std::f64::consts::PI / (2 * 5 * 2) as f64,
@@ -521,6 +1077,82 @@ fn cast_reflecting_shield(length: f64, actor_index: usize, world: &mut World) {
     });
 }
 
+fn cast_quake(
+    mut magick: Magick,
+    actor_index: usize,
+    world: &mut World,
+    index: &WorldIndex,
+    events: &mut Vec<WorldEvent>,
+) {
+    if is_actor_in_panic(&world.actors[actor_index]) {
+        return;
+    }
+    let power = magick.power[Element::Earth as usize];
+    magick.power[Element::Earth as usize] = 0.0;
+    let origin = world.actors[actor_index].position;
+    let max_distance =
+        world.actors[actor_index].body.shape.radius + power * world.settings.quake_distance_factor;
+    let damage = power * world.settings.quake_damage_factor;
+    let actor_id = world.actors[actor_index].id;
+    let team = world.actors[actor_index].team;
+    for i in 0..world.actors.len() {
+        let target = &mut world.actors[i];
+        if i == actor_index
+            || is_actor_flying(target)
+            || target.position.distance(origin) - target.body.shape.radius > max_distance
+        {
+            continue;
+        }
+        target.position_z = target.body.shape.radius + world.settings.margin;
+        target.velocity_z = power * world.settings.quake_velocity_z_factor;
+        target.effect = add_magick_to_effect(
+            world.time,
+            &target.effect,
+            &magick,
+            Some(actor_id),
+            &target.aura.elements,
+        );
+        if !can_absorb_physical_damage(&target.aura.elements) {
+            let damage = if is_same_team(team, target.team) {
+                damage * world.settings.friendly_fire_factor
+            } else {
+                damage
+            };
+            events.push(WorldEvent::Damage {
+                source: Some(ObjectId::Actor(actor_id)),
+                target: ObjectId::Actor(target.id),
+                physical: damage,
+                magical: [0.0; 11],
+            });
+            damage_actor(damage, i, world, index);
+        }
+    }
+}
+
+fn cast_ice_spikes(mut magick: Magick, actor_index: usize, world: &mut World) {
+    if is_actor_in_panic(&world.actors[actor_index]) {
+        return;
+    }
+    let actor = &world.actors[actor_index];
+    let number = world.settings.ice_spikes_number as usize;
+    let force = magick.power[Element::Ice as usize] * world.settings.ice_spike_force_factor;
+    magick.power.iter_mut().for_each(|v| *v /= number as f64);
+    for i in 0..number {
+        let direction = actor
+            .current_direction
+            .rotated(i as f64 * std::f64::consts::TAU / number as f64);
+        world.projectiles.push(make_bullet(
+            ProjectileId(get_next_id(&mut world.id_counter)),
+            actor,
+            direction,
+            direction * force,
+            magick.power,
+            world.time + world.settings.ice_spike_duration,
+            &world.settings,
+        ));
+    }
+}
+
 fn add_delayed_magick(magick: Magick, actor_index: usize, world: &mut World) {
     if is_actor_in_panic(&world.actors[actor_index]) {
         return;
@@ -551,32 +1183,54 @@ fn add_beam(mut magick: Magick, actor_index: usize, world: &mut World) {
     world.actors[actor_index].occupation = ActorOccupation::Beaming(beam_id);
 }
 
-pub fn complete_directed_magick(actor_index: usize, world: &mut World) {
+fn add_lightning_bolt(
+    spread: Option<f64>,
+    duration: f64,
+    magick: Magick,
+    actor_index: usize,
+    world: &mut World,
+) {
+    if is_actor_in_panic(&world.actors[actor_index]) {
+        return;
+    }
+    let lightning_bolt_id = LightningBoltId(get_next_id(&mut world.id_counter));
+    world.lightning_bolts.push(LightningBolt {
+        id: lightning_bolt_id,
+        actor_id: world.actors[actor_index].id,
+        magick,
+        spread,
+        deadline: world.time + duration,
+    });
+    world.actors[actor_index].occupation = ActorOccupation::Striking(lightning_bolt_id);
+}
+
+pub fn complete_directed_magick(actor_index: usize, world: &mut World, index: &WorldIndex) {
     match world.actors[actor_index].occupation {
         ActorOccupation::None => (),
         ActorOccupation::Beaming(beam_id) => {
-            if let Some(v) = world.beams.iter_mut().find(|v| v.id == beam_id) {
-                v.deadline = world.time;
+            if let Some(i) = index.beam(beam_id) {
+                world.beams[i].deadline = world.time;
+            }
+        }
+        ActorOccupation::Striking(lightning_bolt_id) => {
+            if let Some(i) = index.lightning_bolt(lightning_bolt_id) {
+                world.lightning_bolts[i].deadline = world.time;
             }
         }
         ActorOccupation::Spraying {
             bounded_area_id,
             field_id,
         } => {
-            if let Some(v) = world
-                .bounded_areas
-                .iter_mut()
-                .find(|v| v.id == bounded_area_id)
-            {
-                v.deadline = world.time;
+            if let Some(i) = index.bounded_area(bounded_area_id) {
+                world.bounded_areas[i].deadline = world.time;
             }
-            if let Some(v) = world.fields.iter_mut().find(|v| v.id == field_id) {
-                v.deadline = world.time;
+            if let Some(i) = index.field(field_id) {
+                world.fields[i].deadline = world.time;
             }
         }
         ActorOccupation::Shooting(gun_id) => {
-            if let Some(v) = world.guns.iter_mut().find(|v| v.id == gun_id) {
-                v.shots_left = 0;
+            if let Some(i) = index.gun(gun_id) {
+                world.guns[i].shots_left = 0;
             }
         }
     }
@@ -605,6 +1259,7 @@ pub fn self_magick(actor_index: usize, world: &mut World) {
             world.time,
             &world.actors[actor_index].effect,
             &magick,
+            Some(world.actors[actor_index].id),
             &world.actors[actor_index].aura.elements,
         );
     } else {
@@ -846,38 +1501,71 @@ fn can_cancel_element(target: Element, element: Element) -> bool {
         || (target == Element::Poison && element == Element::Life)
 }
 
-fn remove_intersecting_objects(world: &mut World, shape_cache: &ShapeCache) {
-    if !world.temp_obstacles.is_empty() {
-        for i in 0..world.temp_obstacles.len() - 1 {
-            for j in i + 1..world.temp_obstacles.len() {
-                if intersection_test(
-                    &world.temp_obstacles[i],
-                    &world.temp_obstacles[j],
-                    shape_cache,
-                ) {
+fn remove_intersecting_objects(
+    world: &mut World,
+    shape_cache: &ShapeCache,
+    broadphase: &mut Broadphase,
+) {
+    broadphase.build_obstacles(world, shape_cache);
+    broadphase.build_temp_areas(world, shape_cache);
+    broadphase.build_mines(world, shape_cache);
+    let mut candidates = Vec::new();
+    let mut area_candidates = Vec::new();
+    for i in 0..world.temp_obstacles.len() {
+        broadphase.obstacles.query(
+            &get_bounds(&world.temp_obstacles[i], shape_cache),
+            &mut candidates,
+        );
+        for index in candidates.iter() {
+            if let Index::TempObstacle(j) = *index {
+                if j > i
+                    && intersection_test(
+                        &world.temp_obstacles[i],
+                        &world.temp_obstacles[j],
+                        shape_cache,
+                    )
+                {
                     world.temp_obstacles[i].deadline = 0.0;
                     break;
                 }
             }
         }
     }
-    if !world.shields.is_empty() {
-        for i in 0..world.shields.len() - 1 {
-            for j in i + 1..world.shields.len() {
-                if intersection_test(&world.shields[i], &world.shields[j], shape_cache) {
+    for i in 0..world.shields.len() {
+        broadphase
+            .obstacles
+            .query(&get_bounds(&world.shields[i], shape_cache), &mut candidates);
+        for index in candidates.iter() {
+            if let Index::Shield(j) = *index {
+                if j > i && intersection_test(&world.shields[i], &world.shields[j], shape_cache) {
                     world.shields[i].power = 0.0;
                     break;
                 }
             }
         }
     }
-    if !world.temp_areas.is_empty() {
-        for i in 0..world.temp_areas.len() - 1 {
-            for j in i + 1..world.temp_areas.len() {
-                if intersection_test(&world.temp_areas[i], &world.temp_areas[j], shape_cache) {
-                    world.temp_areas[i].deadline = 0.0;
-                    break;
-                }
+    for i in 0..world.temp_areas.len() {
+        broadphase.temp_areas.query(
+            &get_bounds(&world.temp_areas[i], shape_cache),
+            &mut area_candidates,
+        );
+        for j in area_candidates.iter() {
+            if *j > i && intersection_test(&world.temp_areas[i], &world.temp_areas[*j], shape_cache)
+            {
+                world.temp_areas[i].deadline = 0.0;
+                break;
+            }
+        }
+    }
+    for i in 0..world.mines.len() {
+        broadphase.mines.query(
+            &get_bounds(&world.mines[i], shape_cache),
+            &mut area_candidates,
+        );
+        for j in area_candidates.iter() {
+            if *j > i && intersection_test(&world.mines[i], &world.mines[*j], shape_cache) {
+                world.mines[i].deadline = 0.0;
+                break;
             }
         }
     }
@@ -885,34 +1573,47 @@ fn remove_intersecting_objects(world: &mut World, shape_cache: &ShapeCache) {
         if temp_obstacle.deadline == 0.0 {
             continue;
         }
-        for static_object in world.static_objects.iter() {
-            if intersection_test(temp_obstacle, static_object, shape_cache) {
-                temp_obstacle.deadline = 0.0;
-                break;
+        broadphase
+            .obstacles
+            .query(&get_bounds(temp_obstacle, shape_cache), &mut candidates);
+        for index in candidates.iter() {
+            if let Index::StaticObject(j) = *index {
+                if intersection_test(temp_obstacle, &world.static_objects[j], shape_cache) {
+                    temp_obstacle.deadline = 0.0;
+                    break;
+                }
             }
         }
         if temp_obstacle.deadline == 0.0 {
             continue;
         }
-        for shield in world.shields.iter_mut() {
-            if shield.power == 0.0 {
-                continue;
-            }
-            if intersection_test(temp_obstacle, shield, shape_cache) {
-                if shield.created
-                    >= temp_obstacle.deadline - world.settings.temp_obstacle_magick_duration
-                {
-                    temp_obstacle.deadline = 0.0;
-                } else {
-                    shield.power = 0.0;
-                    break;
+        for index in candidates.iter() {
+            if let Index::Shield(j) = *index {
+                let shield = &mut world.shields[j];
+                if shield.power == 0.0 {
+                    continue;
+                }
+                if intersection_test(temp_obstacle, shield, shape_cache) {
+                    if shield.created
+                        >= temp_obstacle.deadline - world.settings.temp_obstacle_magick_duration
+                    {
+                        temp_obstacle.deadline = 0.0;
+                    } else {
+                        shield.power = 0.0;
+                        break;
+                    }
                 }
             }
         }
         if temp_obstacle.deadline == 0.0 {
             continue;
         }
-        for temp_area in world.temp_areas.iter_mut() {
+        broadphase.temp_areas.query(
+            &get_bounds(temp_obstacle, shape_cache),
+            &mut area_candidates,
+        );
+        for j in area_candidates.iter() {
+            let temp_area = &mut world.temp_areas[*j];
             if temp_area.deadline == 0.0 {
                 continue;
             }
@@ -932,16 +1633,25 @@ fn remove_intersecting_objects(world: &mut World, shape_cache: &ShapeCache) {
         if shield.power == 0.0 {
             continue;
         }
-        for static_object in world.static_objects.iter() {
-            if intersection_test(shield, static_object, shape_cache) {
-                shield.power = 0.0;
-                break;
+        broadphase
+            .obstacles
+            .query(&get_bounds(shield, shape_cache), &mut candidates);
+        for index in candidates.iter() {
+            if let Index::StaticObject(j) = *index {
+                if intersection_test(shield, &world.static_objects[j], shape_cache) {
+                    shield.power = 0.0;
+                    break;
+                }
             }
         }
         if shield.power == 0.0 {
             continue;
         }
-        for temp_area in world.temp_areas.iter_mut() {
+        broadphase
+            .temp_areas
+            .query(&get_bounds(shield, shape_cache), &mut area_candidates);
+        for j in area_candidates.iter() {
+            let temp_area = &mut world.temp_areas[*j];
             if temp_area.deadline == 0.0 {
                 continue;
             }
@@ -959,30 +1669,52 @@ fn remove_intersecting_objects(world: &mut World, shape_cache: &ShapeCache) {
     }
 }
 
-fn intersect_objects_with_areas(world: &mut World, shape_cache: &ShapeCache) {
+fn intersect_objects_with_areas(
+    world: &mut World,
+    shape_cache: &ShapeCache,
+    broadphase: &mut Broadphase,
+    index: &WorldIndex,
+    lag_compensation: &LagCompensation,
+) {
+    broadphase.build_temp_areas(world, shape_cache);
+    broadphase.build_static_areas(world, shape_cache);
+    let temp_areas = &world.temp_areas;
+    let static_areas = &world.static_areas;
+    let mut temp_area_candidates = Vec::new();
+    let mut static_area_candidates = Vec::new();
     for i in 0..world.actors.len() {
         intersect_actor_with_all_bounded_areas(
             world.time,
             i,
             &world.bounded_areas,
+            index,
+            lag_compensation,
             &mut world.actors,
         );
         let actor = &mut world.actors[i];
+        let mut object = IntersectingDynamicObject {
+            shape: &Ball::new(actor.body.shape.radius),
+            velocity: actor.velocity,
+            isometry: Isometry::translation(actor.position.x, actor.position.y),
+            movement_type: get_actor_movement_type(actor),
+            mass: actor.body.mass(),
+            resistance: &actor.aura.elements,
+            dynamic_force: &mut actor.dynamic_force,
+            effect: &mut actor.effect,
+        };
+        let bounds = get_bounds(&object, shape_cache);
+        broadphase
+            .temp_areas
+            .query(&bounds, &mut temp_area_candidates);
+        broadphase
+            .static_areas
+            .query(&bounds, &mut static_area_candidates);
         intersect_with_temp_and_static_areas(
             world.time,
             world.settings.gravitational_acceleration,
-            &world.temp_areas,
-            &world.static_areas,
-            &mut IntersectingDynamicObject {
-                shape: &Ball::new(actor.body.shape.radius),
-                velocity: actor.velocity,
-                isometry: Isometry::translation(actor.position.x, actor.position.y),
-                movement_type: get_actor_movement_type(actor),
-                mass: actor.body.mass(),
-                resistance: &actor.aura.elements,
-                dynamic_force: &mut actor.dynamic_force,
-                effect: &mut actor.effect,
-            },
+            temp_area_candidates.iter().map(|i| &temp_areas[*i]),
+            static_area_candidates.iter().map(|i| &static_areas[*i]),
+            &mut object,
             shape_cache,
         );
     }
@@ -992,6 +1724,9 @@ fn intersect_objects_with_areas(world: &mut World, shape_cache: &ShapeCache) {
             world.time,
             &world.bounded_areas,
             &world.actors,
+            0,
+            index,
+            |v| v.position,
             &mut IntersectingStaticObject {
                 shape: &Ball::new(v.body.shape.radius),
                 isometry: Isometry::translation(v.position.x, v.position.y),
@@ -999,21 +1734,29 @@ fn intersect_objects_with_areas(world: &mut World, shape_cache: &ShapeCache) {
                 effect: &mut effect,
             },
         );
+        let mut object = IntersectingDynamicObject {
+            shape: &Ball::new(v.body.shape.radius),
+            velocity: v.velocity,
+            isometry: Isometry::translation(v.position.x, v.position.y),
+            movement_type: get_projectile_movement_type(v),
+            mass: v.body.mass(),
+            resistance: &DEFAULT_RESISTANCE,
+            dynamic_force: &mut v.dynamic_force,
+            effect: &mut effect,
+        };
+        let bounds = get_bounds(&object, shape_cache);
+        broadphase
+            .temp_areas
+            .query(&bounds, &mut temp_area_candidates);
+        broadphase
+            .static_areas
+            .query(&bounds, &mut static_area_candidates);
         intersect_with_temp_and_static_areas(
             world.time,
             world.settings.gravitational_acceleration,
-            &world.temp_areas,
-            &world.static_areas,
-            &mut IntersectingDynamicObject {
-                shape: &Ball::new(v.body.shape.radius),
-                velocity: v.velocity,
-                isometry: Isometry::translation(v.position.x, v.position.y),
-                movement_type: get_projectile_movement_type(v),
-                mass: v.body.mass(),
-                resistance: &DEFAULT_RESISTANCE,
-                dynamic_force: &mut v.dynamic_force,
-                effect: &mut effect,
-            },
+            temp_area_candidates.iter().map(|i| &temp_areas[*i]),
+            static_area_candidates.iter().map(|i| &static_areas[*i]),
+            &mut object,
             shape_cache,
         );
     }
@@ -1028,6 +1771,9 @@ fn intersect_objects_with_areas(world: &mut World, shape_cache: &ShapeCache) {
                     world.time,
                     &world.bounded_areas,
                     &world.actors,
+                    0,
+                    index,
+                    |v| v.position,
                     &mut IntersectingStaticObject {
                         shape,
                         isometry: Isometry::translation(object.position.x, object.position.y),
@@ -1042,6 +1788,9 @@ fn intersect_objects_with_areas(world: &mut World, shape_cache: &ShapeCache) {
             world.time,
             &world.bounded_areas,
             &world.actors,
+            0,
+            index,
+            |v| v.position,
             &mut IntersectingStaticObject {
                 shape: &Ball::new(temp_obstacle.body.shape.radius),
                 isometry: Isometry::translation(temp_obstacle.position.x, temp_obstacle.position.y),
@@ -1052,6 +1801,63 @@ fn intersect_objects_with_areas(world: &mut World, shape_cache: &ShapeCache) {
     }
 }
 
+fn detonate_mines(world: &mut World, shape_cache: &ShapeCache) {
+    let explosion = Ball::new(world.settings.mine_explosion_radius);
+    for mine in world.mines.iter_mut() {
+        if mine.detonated
+            || (!world
+                .actors
+                .iter()
+                .any(|v| v.id != mine.actor_id && intersection_test(&*mine, v, shape_cache))
+                && !world
+                    .projectiles
+                    .iter()
+                    .any(|v| intersection_test(&*mine, v, shape_cache)))
+        {
+            continue;
+        }
+        let isometry = mine.get_isometry();
+        for actor in world.actors.iter_mut() {
+            if query::intersection_test(
+                &isometry,
+                &explosion,
+                &actor.get_isometry(),
+                &actor.body.shape.as_shape(),
+            )
+            .unwrap()
+            {
+                actor.effect = add_magick_to_effect(
+                    world.time,
+                    &actor.effect,
+                    &mine.magick,
+                    Some(mine.actor_id),
+                    &actor.aura.elements,
+                );
+            }
+        }
+        for temp_obstacle in world.temp_obstacles.iter_mut() {
+            if query::intersection_test(
+                &isometry,
+                &explosion,
+                &temp_obstacle.get_isometry(),
+                &temp_obstacle.body.shape.as_shape(),
+            )
+            .unwrap()
+            {
+                temp_obstacle.effect = add_magick_to_effect(
+                    world.time,
+                    &temp_obstacle.effect,
+                    &mine.magick,
+                    Some(mine.actor_id),
+                    &DEFAULT_RESISTANCE,
+                );
+            }
+        }
+        mine.detonated = true;
+        mine.deadline = world.time + world.settings.mine_explosion_duration;
+    }
+}
+
 fn get_actor_movement_type(actor: &Actor) -> MovementType {
     if is_actor_flying(actor) {
         return MovementType::Flying;
@@ -1087,15 +1893,17 @@ enum MovementType {
     Walking,
 }
 
-fn intersect_with_temp_and_static_areas<T>(
+fn intersect_with_temp_and_static_areas<'a, T, TA, SA>(
     now: f64,
     gravitational_acceleration: f64,
-    temp_areas: &[TempArea],
-    static_areas: &[StaticArea],
+    temp_areas: TA,
+    static_areas: SA,
     object: &mut IntersectingDynamicObject<T>,
     shape_cache: &ShapeCache,
 ) where
     T: Default + PartialEq,
+    TA: Iterator<Item = &'a TempArea>,
+    SA: DoubleEndedIterator<Item = &'a StaticArea>,
 {
     intersect_with_temp_areas(now, temp_areas, object);
     if !matches!(object.movement_type, MovementType::Flying) {
@@ -1109,14 +1917,15 @@ fn intersect_with_temp_and_static_areas<T>(
     }
 }
 
-fn intersect_with_temp_areas<T>(
+fn intersect_with_temp_areas<'a, T, I>(
     now: f64,
-    temp_areas: &[TempArea],
+    temp_areas: I,
     object: &mut IntersectingDynamicObject<T>,
 ) where
     T: Default + PartialEq,
+    I: Iterator<Item = &'a TempArea>,
 {
-    for temp_area in temp_areas.iter() {
+    for temp_area in temp_areas {
         let isometry = Isometry::translation(temp_area.position.x, temp_area.position.y);
         if query::intersection_test(
             &object.isometry,
@@ -1126,23 +1935,28 @@ fn intersect_with_temp_areas<T>(
         )
         .unwrap()
         {
-            *object.effect =
-                add_magick_to_effect(now, object.effect, &temp_area.magick, object.resistance);
+            *object.effect = add_magick_to_effect(
+                now,
+                object.effect,
+                &temp_area.magick,
+                Some(temp_area.actor_id),
+                object.resistance,
+            );
         }
     }
 }
 
-fn intersect_with_last_static_area<T>(
+fn intersect_with_last_static_area<'a, T, I>(
     now: f64,
     gravitational_acceleration: f64,
-    static_areas: &[StaticArea],
+    static_areas: I,
     object: &mut IntersectingDynamicObject<T>,
     shape_cache: &ShapeCache,
 ) where
     T: Default + PartialEq,
+    I: DoubleEndedIterator<Item = &'a StaticArea>,
 {
     if let Some(static_area) = static_areas
-        .iter()
         .rev()
         .find(|v| intersection_test(object, *v, shape_cache))
     {
@@ -1160,6 +1974,7 @@ fn intersect_with_last_static_area<T>(
                     now,
                     object.effect,
                     &static_area.magick,
+                    None,
                     object.resistance,
                 );
             }
@@ -1175,6 +1990,7 @@ fn intersect_with_last_static_area<T>(
                     now,
                     object.effect,
                     &static_area.magick,
+                    None,
                     object.resistance,
                 );
             }
@@ -1193,13 +2009,22 @@ fn intersect_actor_with_all_bounded_areas(
     now: f64,
     actor_index: usize,
     bounded_areas: &[BoundedArea],
+    index: &WorldIndex,
+    lag_compensation: &LagCompensation,
     actors: &mut [Actor],
 ) {
+    let actor_id = actors[actor_index].id;
+    let position = actors[actor_index].position;
+    let get_owner_position =
+        |owner: &Actor| lag_compensation.get_owner_position(owner, actor_id, position);
     let (left, right) = actors.split_at_mut(actor_index);
     intersect_static_object_with_all_bounded_areas(
         now,
         bounded_areas,
         left,
+        0,
+        index,
+        get_owner_position,
         &mut IntersectingStaticObject {
             shape: &Ball::new(right[0].body.shape.radius),
             isometry: Isometry::translation(right[0].position.x, right[0].position.y),
@@ -1212,6 +2037,9 @@ fn intersect_actor_with_all_bounded_areas(
         now,
         bounded_areas,
         right,
+        actor_index + 1,
+        index,
+        get_owner_position,
         &mut IntersectingStaticObject {
             shape: &Ball::new(left[actor_index].body.shape.radius),
             isometry: Isometry::translation(
@@ -1224,30 +2052,54 @@ fn intersect_actor_with_all_bounded_areas(
     );
 }
 
-fn intersect_static_object_with_all_bounded_areas<T>(
+fn intersect_static_object_with_all_bounded_areas<T, F>(
     now: f64,
     bounded_areas: &[BoundedArea],
     actors: &[Actor],
+    actors_offset: usize,
+    index: &WorldIndex,
+    get_owner_position: F,
     object: &mut IntersectingStaticObject<T>,
 ) where
     T: Default + PartialEq,
+    F: Fn(&Actor) -> Vec2f,
 {
     for bounded_area in bounded_areas {
-        if let Some(owner) = actors.iter().find(|v| v.id == bounded_area.actor_id) {
-            intersect_static_object_with_bounded_area(now, bounded_area, owner, object);
+        if let Some(owner) = get_owner(bounded_area.actor_id, actors, actors_offset, index) {
+            let owner_position = get_owner_position(owner);
+            intersect_static_object_with_bounded_area(
+                now,
+                bounded_area,
+                owner,
+                owner_position,
+                object,
+            );
         }
     }
 }
 
+fn get_owner<'a>(
+    actor_id: ActorId,
+    actors: &'a [Actor],
+    actors_offset: usize,
+    index: &WorldIndex,
+) -> Option<&'a Actor> {
+    index
+        .actor(actor_id)
+        .and_then(|i| i.checked_sub(actors_offset))
+        .and_then(|i| actors.get(i))
+}
+
 fn intersect_static_object_with_bounded_area<T>(
     now: f64,
     area: &BoundedArea,
     owner: &Actor,
+    owner_position: Vec2f,
     object: &mut IntersectingStaticObject<T>,
 ) where
     T: Default + PartialEq,
 {
-    let isometry = Isometry::translation(owner.position.x, owner.position.y);
+    let isometry = Isometry::translation(owner_position.x, owner_position.y);
     if intersection_test_with_ring_sector(
         &object.isometry,
         object.shape,
@@ -1255,16 +2107,24 @@ fn intersect_static_object_with_bounded_area<T>(
         &area.body,
         owner.current_direction,
     ) {
-        *object.effect = add_magick_to_effect(now, object.effect, &area.magick, object.resistance);
+        *object.effect = add_magick_to_effect(
+            now,
+            object.effect,
+            &area.magick,
+            Some(area.actor_id),
+            object.resistance,
+        );
     }
 }
 
-fn intersect_objects_with_all_fields(world: &mut World) {
+fn intersect_objects_with_all_fields(world: &mut World, index: &WorldIndex) {
     for i in 0..world.actors.len() {
         let (left, right) = world.actors.split_at_mut(i);
         intersect_object_with_all_fields(
             &world.fields,
             left,
+            0,
+            index,
             &mut PushedObject {
                 shape: Ball::new(right[0].body.shape.radius),
                 position: right[0].position,
@@ -1278,6 +2138,8 @@ fn intersect_objects_with_all_fields(world: &mut World) {
         intersect_object_with_all_fields(
             &world.fields,
             right,
+            i + 1,
+            index,
             &mut PushedObject {
                 shape: Ball::new(left[i].body.shape.radius),
                 position: left[i].position,
@@ -1292,6 +2154,8 @@ fn intersect_objects_with_all_fields(world: &mut World) {
         intersect_object_with_all_fields(
             &world.fields,
             &world.actors,
+            0,
+            index,
             &mut PushedObject {
                 shape: Ball::new(v.body.shape.radius),
                 position: v.position,
@@ -1316,12 +2180,14 @@ struct PushedObject<'a, S: Shape> {
 fn intersect_object_with_all_fields<S>(
     fields: &[Field],
     actors: &[Actor],
+    actors_offset: usize,
+    index: &WorldIndex,
     object: &mut PushedObject<S>,
 ) where
     S: Shape,
 {
     for field in fields {
-        if let Some(owner) = actors.iter().find(|v| v.id == field.actor_id) {
+        if let Some(owner) = get_owner(field.actor_id, actors, actors_offset, index) {
             intersect_object_with_field(field, owner, object);
         }
     }
@@ -1356,7 +2222,7 @@ fn update_actors(
     now: f64,
     duration: f64,
     settings: &WorldSettings,
-    actors: &mut Vec<Actor>,
+    actors: &mut [Actor],
     events: &mut Vec<EngineEvent>,
 ) {
     for (index, actor) in actors.iter_mut().enumerate() {
@@ -1373,7 +2239,7 @@ fn update_actors(
             duration,
             settings.magical_damage_factor,
             actor.body.mass(),
-            &actor.effect.power,
+            &actor.effect,
             events,
         );
         decay_effect(now, &mut actor.effect);
@@ -1401,7 +2267,7 @@ fn update_actors(
     }
 }
 
-fn update_projectiles(duration: f64, settings: &WorldSettings, projectiles: &mut Vec<Projectile>) {
+fn update_projectiles(duration: f64, settings: &WorldSettings, projectiles: &mut [Projectile]) {
     for projectile in projectiles.iter_mut() {
         update_velocity(
             duration,
@@ -1430,7 +2296,7 @@ fn update_static_objects(
     now: f64,
     duration: f64,
     settings: &WorldSettings,
-    static_objects: &mut Vec<StaticObject>,
+    static_objects: &mut [StaticObject],
     events: &mut Vec<EngineEvent>,
 ) {
     for (index, object) in static_objects.iter_mut().enumerate() {
@@ -1440,13 +2306,13 @@ fn update_static_objects(
             duration,
             settings.magical_damage_factor,
             object.body.mass(),
-            &object.effect.power,
+            &object.effect,
             events,
         );
     }
 }
 
-fn update_shields(duration: f64, settings: &WorldSettings, shields: &mut Vec<Shield>) {
+fn update_shields(duration: f64, settings: &WorldSettings, shields: &mut [Shield]) {
     for shield in shields.iter_mut() {
         shield.power -= duration * settings.decay_factor;
     }
@@ -1455,7 +2321,7 @@ fn update_shields(duration: f64, settings: &WorldSettings, shields: &mut Vec<Shi
 fn update_temp_obstacles(
     duration: f64,
     settings: &WorldSettings,
-    temp_obstacles: &mut Vec<TempObstacle>,
+    temp_obstacles: &mut [TempObstacle],
     events: &mut Vec<EngineEvent>,
 ) {
     for (index, temp_obstacle) in temp_obstacles.iter_mut().enumerate() {
@@ -1465,7 +2331,7 @@ fn update_temp_obstacles(
             duration,
             settings.magical_damage_factor,
             temp_obstacle.mass(),
-            &temp_obstacle.effect.power,
+            &temp_obstacle.effect,
             events,
         );
     }
@@ -1586,12 +2452,20 @@ fn add_damage(
     duration: f64,
     damage_factor: f64,
     mass: f64,
-    power: &[f64; 11],
+    effect: &Effect,
     events: &mut Vec<EngineEvent>,
 ) {
-    let damage = get_damage(power) * damage_factor * duration / mass;
+    let factor = damage_factor * duration / mass;
+    let damage = get_damage(&effect.power) * factor;
     if damage != 0.0 {
-        events.push(EngineEvent::Damage { target, damage })
+        let mut elements = get_elements_damage(&effect.power);
+        elements.iter_mut().for_each(|v| *v *= factor);
+        events.push(EngineEvent::MagicalDamage {
+            target,
+            damage,
+            elements,
+            sources: effect.sources,
+        })
     }
 }
 
@@ -1634,6 +2508,7 @@ fn add_magick_to_effect<T>(
     now: f64,
     target: &Effect,
     magick: &Magick,
+    source: Option<ActorId>,
     resistance: &[T; 11],
 ) -> Effect
 where
@@ -1641,6 +2516,7 @@ where
 {
     let mut power = target.power;
     let mut applied = target.applied;
+    let mut sources = target.sources;
     let shield = (resistance[Element::Shield as usize] == T::default()) as i32 as f64;
     for i in 0..power.len() {
         if magick.power[i] > 0.0 {
@@ -1648,6 +2524,7 @@ where
                 * (resistance[i] == T::default()) as i32 as f64
                 * shield;
             applied[i] = now;
+            sources[i] = source;
         }
     }
     let target_power = power;
@@ -1656,6 +2533,7 @@ where
         power[Element::Fire as usize] = 0.0;
         power[Element::Steam as usize] = target_power[Element::Water as usize];
         applied[Element::Steam as usize] = now;
+        sources[Element::Steam as usize] = source;
     }
     if target_power[Element::Poison as usize] > 0.0 && target_power[Element::Life as usize] > 0.0 {
         power[Element::Poison as usize] = 0.0;
@@ -1673,10 +2551,15 @@ where
         power[Element::Ice as usize] =
             target_power[Element::Water as usize].max(target_power[Element::Cold as usize]);
         applied[Element::Ice as usize] = now;
+        sources[Element::Ice as usize] = source;
         power[Element::Water as usize] = 0.0;
         power[Element::Cold as usize] = 0.0;
     }
-    Effect { applied, power }
+    Effect {
+        applied,
+        power,
+        sources,
+    }
 }
 
 fn resist_magick<T>(resistance: &[T; 11], power: &mut [f64; 11])
@@ -1690,14 +2573,26 @@ where
 }
 
 fn get_damage(power: &[f64; 11]) -> f64 {
-    (1.0 + power[Element::Water as usize]) * power[Element::Lightning as usize]
-        / get_element_duration(Element::Lightning)
-        - power[Element::Life as usize] / get_element_duration(Element::Life)
-        + power[Element::Arcane as usize] / get_element_duration(Element::Arcane)
-        + power[Element::Cold as usize] / get_element_duration(Element::Cold)
-        + power[Element::Fire as usize] / get_element_duration(Element::Fire)
-        + power[Element::Steam as usize] / get_element_duration(Element::Steam)
-        + power[Element::Poison as usize] / get_element_duration(Element::Poison)
+    get_elements_damage(power).iter().sum()
+}
+
+fn get_elements_damage(power: &[f64; 11]) -> [f64; 11] {
+    let mut result = [0.0; 11];
+    result[Element::Lightning as usize] = (1.0 + power[Element::Water as usize])
+        * power[Element::Lightning as usize]
+        / get_element_duration(Element::Lightning);
+    for element in [
+        Element::Life,
+        Element::Arcane,
+        Element::Cold,
+        Element::Fire,
+        Element::Steam,
+        Element::Poison,
+    ] {
+        result[element as usize] = power[element as usize] / get_element_duration(element);
+    }
+    result[Element::Life as usize] = -result[Element::Life as usize];
+    result
 }
 
 fn can_absorb_physical_damage(elements: &[bool; 11]) -> bool {
@@ -1708,7 +2603,15 @@ fn can_reflect_beams(elements: &[bool; 11]) -> bool {
     elements[Element::Shield as usize]
 }
 
-fn get_current_direction(
+fn can_block_lightning(elements: &[bool; 11]) -> bool {
+    elements[Element::Shield as usize] || elements[Element::Earth as usize]
+}
+
+fn can_ground_lightning(effect: &Effect) -> bool {
+    effect.power[Element::Water as usize] > 0.0
+}
+
+pub fn get_current_direction(
     current_direction: Vec2f,
     target_direction: Vec2f,
     duration: f64,
@@ -1723,7 +2626,9 @@ pub fn normalize_angle(angle: f64) -> f64 {
     (turns - turns.floor() - 0.5) * std::f64::consts::TAU
 }
 
+#[allow(clippy::too_many_arguments)]
 fn intersect_beam(
+    actor_id: ActorId,
     magick: &Magick,
     origin: Vec2f,
     direction: Vec2f,
@@ -1731,31 +2636,64 @@ fn intersect_beam(
     length: &mut f64,
     world: &mut World,
     shape_cache: &ShapeCache,
+    broadphase: &Broadphase,
 ) -> Option<EmittedBeam> {
-    let mut nearest_hit =
-        find_beam_nearest_intersection(origin, direction, &world.actors, length, shape_cache)
-            .map(|(i, n)| (Index::Actor(i), n));
-    nearest_hit =
-        find_beam_nearest_intersection(origin, direction, &world.projectiles, length, shape_cache)
-            .map(|(i, n)| (Index::Projectile(i), n))
-            .or(nearest_hit);
+    let mut candidates = Vec::new();
+    broadphase.query_segment(origin, origin + direction * *length, &mut candidates);
+    let mut nearest_hit = find_beam_nearest_intersection(
+        origin,
+        direction,
+        candidates.iter().filter_map(|v| match *v {
+            Index::Actor(i) => Some((i, &world.actors[i])),
+            _ => None,
+        }),
+        length,
+        shape_cache,
+    )
+    .map(|(i, n)| (Index::Actor(i), n));
     nearest_hit = find_beam_nearest_intersection(
         origin,
         direction,
-        &world.static_objects,
+        candidates.iter().filter_map(|v| match *v {
+            Index::Projectile(i) => Some((i, &world.projectiles[i])),
+            _ => None,
+        }),
+        length,
+        shape_cache,
+    )
+    .map(|(i, n)| (Index::Projectile(i), n))
+    .or(nearest_hit);
+    nearest_hit = find_beam_nearest_intersection(
+        origin,
+        direction,
+        candidates.iter().filter_map(|v| match *v {
+            Index::StaticObject(i) => Some((i, &world.static_objects[i])),
+            _ => None,
+        }),
         length,
         shape_cache,
     )
     .map(|(i, n)| (Index::StaticObject(i), n))
     .or(nearest_hit);
-    nearest_hit =
-        find_beam_nearest_intersection(origin, direction, &world.shields, length, shape_cache)
-            .map(|(i, n)| (Index::Shield(i), n))
-            .or(nearest_hit);
     nearest_hit = find_beam_nearest_intersection(
         origin,
         direction,
-        &world.temp_obstacles,
+        candidates.iter().filter_map(|v| match *v {
+            Index::Shield(i) => Some((i, &world.shields[i])),
+            _ => None,
+        }),
+        length,
+        shape_cache,
+    )
+    .map(|(i, n)| (Index::Shield(i), n))
+    .or(nearest_hit);
+    nearest_hit = find_beam_nearest_intersection(
+        origin,
+        direction,
+        candidates.iter().filter_map(|v| match *v {
+            Index::TempObstacle(i) => Some((i, &world.temp_obstacles[i])),
+            _ => None,
+        }),
         length,
         shape_cache,
     )
@@ -1768,6 +2706,7 @@ fn intersect_beam(
                     world.time,
                     &world.actors[i].effect,
                     magick,
+                    Some(actor_id),
                     &world.actors[i].aura.elements,
                 );
                 can_reflect_beams(&world.actors[i].aura.elements)
@@ -1778,6 +2717,7 @@ fn intersect_beam(
                     world.time,
                     &world.static_objects[i].effect,
                     magick,
+                    Some(actor_id),
                     &DEFAULT_RESISTANCE,
                 );
                 false
@@ -1789,6 +2729,7 @@ fn intersect_beam(
             // RayCast::cast_ray_and_get_normal returns not normalized normal
             normal.normalize();
             Some(EmittedBeam {
+                actor_id,
                 origin: origin + direction * *length,
                 direction: direction - normal * 2.0 * direction.cos(normal),
                 length: world.settings.max_beam_length,
@@ -1856,6 +2797,12 @@ impl WithIsometry for TempArea {
     }
 }
 
+impl WithIsometry for Mine {
+    fn get_isometry(&self) -> Isometry<Real> {
+        Isometry::translation(self.position.x, self.position.y)
+    }
+}
+
 impl WithIsometry for StaticArea {
     fn get_isometry(&self) -> Isometry<Real> {
         Isometry::translation(self.position.x, self.position.y)
@@ -1914,6 +2861,12 @@ impl WithShape for TempArea {
     }
 }
 
+impl WithShape for Mine {
+    fn with_shape(&self, _: &ShapeCache, f: &mut dyn FnMut(&dyn Shape)) {
+        (*f)(&self.body.shape.as_shape())
+    }
+}
+
 impl WithShape for StaticArea {
     fn with_shape(&self, shape_cache: &ShapeCache, f: &mut dyn FnMut(&dyn Shape)) {
         self.body
@@ -1941,18 +2894,19 @@ where
     r.unwrap()
 }
 
-fn find_beam_nearest_intersection<T>(
+fn find_beam_nearest_intersection<'a, T, I>(
     origin: Vec2f,
     direction: Vec2f,
-    objects: &[T],
+    objects: I,
     length: &mut f64,
     shape_cache: &ShapeCache,
 ) -> Option<(usize, Vec2f)>
 where
-    T: WithIsometry + WithShape,
+    T: 'a + WithIsometry + WithShape,
+    I: Iterator<Item = (usize, &'a T)>,
 {
     let mut nearest = None;
-    for (i, object) in objects.iter().enumerate() {
+    for (i, object) in objects {
         let isometry = object.get_isometry();
         let result = with_shape(object, shape_cache, |shape| {
             shape.cast_ray_and_get_normal(
@@ -1973,147 +2927,105 @@ where
     nearest
 }
 
-fn move_objects(
-    duration: f64,
-    world: &mut World,
-    shape_cache: &ShapeCache,
-    events: &mut Vec<EngineEvent>,
-) {
-    let mut earliest_collision = None;
-    let mut duration_left = duration;
-    loop {
-        for (i, static_object) in world.static_objects.iter().enumerate() {
-            for (j, actor) in world.actors.iter().enumerate() {
-                if let Some(toi) = time_of_impact(duration_left, shape_cache, static_object, actor)
-                {
-                    update_earliest_collision(
-                        Index::StaticObject(i),
-                        Index::Actor(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-            for (j, projectile) in world.projectiles.iter().enumerate() {
-                if let Some(toi) =
-                    time_of_impact(duration_left, shape_cache, static_object, projectile)
-                {
-                    update_earliest_collision(
-                        Index::StaticObject(i),
-                        Index::Projectile(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-        }
-        for (i, shield) in world.shields.iter().enumerate() {
-            for (j, actor) in world.actors.iter().enumerate() {
-                if let Some(toi) = time_of_impact(duration_left, shape_cache, shield, actor) {
-                    update_earliest_collision(
-                        Index::Shield(i),
-                        Index::Actor(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-            for (j, projectile) in world.projectiles.iter().enumerate() {
-                if let Some(toi) = time_of_impact(duration_left, shape_cache, shield, projectile) {
-                    update_earliest_collision(
-                        Index::Shield(i),
-                        Index::Projectile(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-        }
-        for (i, temp_obstacle) in world.temp_obstacles.iter().enumerate() {
-            for (j, actor) in world.actors.iter().enumerate() {
-                if let Some(toi) = time_of_impact(duration_left, shape_cache, temp_obstacle, actor)
-                {
-                    update_earliest_collision(
-                        Index::TempObstacle(i),
-                        Index::Actor(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-            for (j, projectile) in world.projectiles.iter().enumerate() {
-                if let Some(toi) =
-                    time_of_impact(duration_left, shape_cache, temp_obstacle, projectile)
-                {
-                    update_earliest_collision(
-                        Index::TempObstacle(i),
-                        Index::Projectile(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-        }
-        if !world.actors.is_empty() {
-            for i in 0..world.actors.len() - 1 {
-                for j in i + 1..world.actors.len() {
-                    if let Some(toi) = time_of_impact(
-                        duration_left,
-                        shape_cache,
-                        &world.actors[i],
-                        &world.actors[j],
-                    ) {
-                        update_earliest_collision(
-                            Index::Actor(i),
-                            Index::Actor(j),
-                            toi,
-                            &mut earliest_collision,
-                        );
-                    }
-                }
-            }
-        }
-        for (i, projectile) in world.projectiles.iter().enumerate() {
-            for (j, actor) in world.actors.iter().enumerate() {
-                if let Some(toi) = time_of_impact(duration_left, shape_cache, projectile, actor) {
-                    update_earliest_collision(
-                        Index::Projectile(i),
-                        Index::Actor(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-            for (j, shield) in world.shields.iter().enumerate() {
-                if let Some(toi) = time_of_impact(duration_left, shape_cache, projectile, shield) {
-                    update_earliest_collision(
-                        Index::Projectile(i),
-                        Index::Shield(j),
-                        toi,
-                        &mut earliest_collision,
-                    );
-                }
-            }
-        }
-        if !world.projectiles.is_empty() {
-            for i in 0..world.projectiles.len() - 1 {
-                for j in i + 1..world.projectiles.len() {
-                    if let Some(toi) = time_of_impact(
-                        duration_left,
-                        shape_cache,
-                        &world.projectiles[i],
-                        &world.projectiles[j],
-                    ) {
-                        update_earliest_collision(
-                            Index::Projectile(i),
-                            Index::Projectile(j),
-                            toi,
-                            &mut earliest_collision,
-                        );
-                    }
-                }
-            }
+fn find_lightning_targets(
+    origin: Vec2f,
+    direction: Vec2f,
+    angle: f64,
+    struck: &[Index],
+    world: &World,
+) -> Vec<(Index, Vec2f)> {
+    let max_distance = world.settings.max_lightning_length;
+    let min_cos = (angle / 2.0).cos();
+    let mut targets = Vec::new();
+    let mut add_target = |index: Index, position: Vec2f| {
+        let to_target = position - origin;
+        let distance = to_target.norm();
+        if distance <= max_distance
+            && (angle >= std::f64::consts::TAU || direction.cos(to_target) >= min_cos)
+            && !struck.contains(&index)
+        {
+            targets.push((distance, index, position));
         }
+    };
+    for (i, v) in world.actors.iter().enumerate() {
+        add_target(Index::Actor(i), v.position);
+    }
+    for (i, v) in world.static_objects.iter().enumerate() {
+        add_target(Index::StaticObject(i), v.position);
+    }
+    for (i, v) in world.temp_obstacles.iter().enumerate() {
+        add_target(Index::TempObstacle(i), v.position);
+    }
+    targets.sort_by(|(lhs, ..), (rhs, ..)| lhs.partial_cmp(rhs).unwrap());
+    targets
+        .into_iter()
+        .map(|(_, index, position)| (index, position))
+        .collect()
+}
+
+fn find_lightning_nearest_intersection(
+    origin: Vec2f,
+    direction: Vec2f,
+    length: &mut f64,
+    world: &World,
+    shape_cache: &ShapeCache,
+) -> Option<Index> {
+    let mut nearest_hit = find_beam_nearest_intersection(
+        origin,
+        direction,
+        world.actors.iter().enumerate(),
+        length,
+        shape_cache,
+    )
+    .map(|(i, _)| Index::Actor(i));
+    nearest_hit = find_beam_nearest_intersection(
+        origin,
+        direction,
+        world.static_objects.iter().enumerate(),
+        length,
+        shape_cache,
+    )
+    .map(|(i, _)| Index::StaticObject(i))
+    .or(nearest_hit);
+    nearest_hit = find_beam_nearest_intersection(
+        origin,
+        direction,
+        world.shields.iter().enumerate(),
+        length,
+        shape_cache,
+    )
+    .map(|(i, _)| Index::Shield(i))
+    .or(nearest_hit);
+    find_beam_nearest_intersection(
+        origin,
+        direction,
+        world.temp_obstacles.iter().enumerate(),
+        length,
+        shape_cache,
+    )
+    .map(|(i, _)| Index::TempObstacle(i))
+    .or(nearest_hit)
+}
+
+fn move_objects(
+    duration: f64,
+    world: &mut World,
+    shape_cache: &ShapeCache,
+    broadphase: &mut Broadphase,
+    events: &mut Vec<EngineEvent>,
+) {
+    let mut earliest_collision = None;
+    let mut duration_left = duration;
+    broadphase.build_obstacles(world, shape_cache);
+    loop {
+        broadphase.build_movables(duration_left, world, shape_cache);
+        find_collisions(
+            duration_left,
+            world,
+            shape_cache,
+            broadphase,
+            |lhs, rhs, toi| update_earliest_collision(lhs, rhs, toi, &mut earliest_collision),
+        );
         if let Some(collision) = earliest_collision.as_ref() {
             let apply_impact = ApplyImpact {
                 now: world.time + (duration - duration_left),
@@ -2124,14 +3036,20 @@ fn move_objects(
             };
             let (lhs_damage, rhs_damage) =
                 collide_objects(collision.lhs, collision.rhs, &apply_impact, world);
+            events.push(EngineEvent::Collision {
+                lhs: collision.lhs,
+                rhs: collision.rhs,
+            });
             if lhs_damage != 0.0 {
-                events.push(EngineEvent::Damage {
+                events.push(EngineEvent::PhysicalDamage {
+                    source: collision.rhs,
                     target: collision.lhs,
                     damage: lhs_damage,
                 });
             }
             if rhs_damage != 0.0 {
-                events.push(EngineEvent::Damage {
+                events.push(EngineEvent::PhysicalDamage {
+                    source: collision.lhs,
                     target: collision.rhs,
                     damage: rhs_damage,
                 });
@@ -2169,6 +3087,203 @@ fn move_objects(
     }
 }
 
+#[cfg(feature = "client")]
+fn move_actor_among_obstacles(
+    now: f64,
+    duration: f64,
+    world: &World,
+    shape_cache: &ShapeCache,
+    broadphase: &Broadphase,
+    actor: &mut Actor,
+) {
+    let mut duration_left = duration;
+    let mut candidates = Vec::new();
+    loop {
+        let bounds = get_swept_bounds(actor, duration_left, shape_cache);
+        let mut earliest_collision = None;
+        broadphase.obstacles.query(&bounds, &mut candidates);
+        for index in candidates.iter() {
+            let toi = match *index {
+                Index::StaticObject(i) => {
+                    time_of_impact(duration_left, shape_cache, actor, &world.static_objects[i])
+                }
+                Index::Shield(i) => {
+                    time_of_impact(duration_left, shape_cache, actor, &world.shields[i])
+                }
+                Index::TempObstacle(i) => {
+                    time_of_impact(duration_left, shape_cache, actor, &world.temp_obstacles[i])
+                }
+                _ => None,
+            };
+            if let Some(toi) = toi {
+                update_earliest_collision(Index::Actor(0), *index, toi, &mut earliest_collision);
+            }
+        }
+        broadphase.movables.query(&bounds, &mut candidates);
+        for index in candidates.iter() {
+            if let Index::Actor(i) = *index {
+                if world.actors[i].id == actor.id {
+                    continue;
+                }
+                if let Some(toi) = time_of_impact(
+                    duration_left,
+                    shape_cache,
+                    actor,
+                    &Actor {
+                        velocity: Vec2f::ZERO,
+                        ..world.actors[i].clone()
+                    },
+                ) {
+                    update_earliest_collision(
+                        Index::Actor(0),
+                        *index,
+                        toi,
+                        &mut earliest_collision,
+                    );
+                }
+            }
+        }
+        if let Some(collision) = earliest_collision {
+            let apply_impact = ApplyImpact {
+                now: now + (duration - duration_left),
+                damage_factor: world.settings.physical_damage_factor,
+                shape_cache,
+                epsilon_duration: duration / 100.0,
+                toi: &collision.toi,
+            };
+            match collision.rhs {
+                Index::Actor(i) => apply_impact.call(
+                    actor,
+                    &mut Actor {
+                        velocity: Vec2f::ZERO,
+                        ..world.actors[i].clone()
+                    },
+                ),
+                Index::StaticObject(i) => {
+                    apply_impact.call(actor, &mut world.static_objects[i].clone())
+                }
+                Index::Shield(i) => apply_impact.call(actor, &mut world.shields[i].clone()),
+                Index::TempObstacle(i) => {
+                    apply_impact.call(actor, &mut world.temp_obstacles[i].clone())
+                }
+                Index::Projectile(..) => (0.0, 0.0),
+            };
+            duration_left -= collision.toi.toi.max(duration / 10.0);
+            if duration_left <= f64::EPSILON {
+                break;
+            }
+        } else {
+            update_position(duration_left, actor.velocity, &mut actor.position);
+            break;
+        }
+    }
+}
+
+fn find_collisions<F>(
+    duration: f64,
+    world: &World,
+    shape_cache: &ShapeCache,
+    broadphase: &Broadphase,
+    mut f: F,
+) where
+    F: FnMut(Index, Index, TOI),
+{
+    let mut candidates = Vec::new();
+    for (i, static_object) in world.static_objects.iter().enumerate() {
+        broadphase
+            .movables
+            .query(&get_bounds(static_object, shape_cache), &mut candidates);
+        for index in candidates.iter() {
+            if let Some(toi) =
+                time_of_impact_with_movable(duration, shape_cache, static_object, *index, world)
+            {
+                f(Index::StaticObject(i), *index, toi);
+            }
+        }
+    }
+    for (i, shield) in world.shields.iter().enumerate() {
+        broadphase
+            .movables
+            .query(&get_bounds(shield, shape_cache), &mut candidates);
+        for index in candidates.iter() {
+            if let Some(toi) =
+                time_of_impact_with_movable(duration, shape_cache, shield, *index, world)
+            {
+                f(Index::Shield(i), *index, toi);
+            }
+        }
+    }
+    for (i, temp_obstacle) in world.temp_obstacles.iter().enumerate() {
+        broadphase
+            .movables
+            .query(&get_bounds(temp_obstacle, shape_cache), &mut candidates);
+        for index in candidates.iter() {
+            if let Some(toi) =
+                time_of_impact_with_movable(duration, shape_cache, temp_obstacle, *index, world)
+            {
+                f(Index::TempObstacle(i), *index, toi);
+            }
+        }
+    }
+    for (i, actor) in world.actors.iter().enumerate() {
+        broadphase.movables.query(
+            &get_swept_bounds(actor, duration, shape_cache),
+            &mut candidates,
+        );
+        for index in candidates.iter() {
+            if let Index::Actor(j) = *index {
+                if j <= i {
+                    continue;
+                }
+                if let Some(toi) = time_of_impact(duration, shape_cache, actor, &world.actors[j]) {
+                    f(Index::Actor(i), Index::Actor(j), toi);
+                }
+            }
+        }
+    }
+    for (i, projectile) in world.projectiles.iter().enumerate() {
+        let bounds = get_swept_bounds(projectile, duration, shape_cache);
+        broadphase.movables.query(&bounds, &mut candidates);
+        for index in candidates.iter() {
+            if let Index::Actor(j) = *index {
+                if let Some(toi) =
+                    time_of_impact(duration, shape_cache, projectile, &world.actors[j])
+                {
+                    f(Index::Projectile(i), Index::Actor(j), toi);
+                }
+            }
+        }
+        broadphase.obstacles.query(&bounds, &mut candidates);
+        for index in candidates.iter() {
+            if let Index::Shield(j) = *index {
+                if let Some(toi) =
+                    time_of_impact(duration, shape_cache, projectile, &world.shields[j])
+                {
+                    f(Index::Projectile(i), Index::Shield(j), toi);
+                }
+            }
+        }
+    }
+    for (i, projectile) in world.projectiles.iter().enumerate() {
+        broadphase.movables.query(
+            &get_swept_bounds(projectile, duration, shape_cache),
+            &mut candidates,
+        );
+        for index in candidates.iter() {
+            if let Index::Projectile(j) = *index {
+                if j <= i {
+                    continue;
+                }
+                if let Some(toi) =
+                    time_of_impact(duration, shape_cache, projectile, &world.projectiles[j])
+                {
+                    f(Index::Projectile(i), Index::Projectile(j), toi);
+                }
+            }
+        }
+    }
+}
+
 trait WithVelocity {
     fn velocity(&self) -> Vec2f;
 }
@@ -2196,6 +3311,25 @@ where
     toi
 }
 
+fn time_of_impact_with_movable<T>(
+    duration: f64,
+    shape_cache: &ShapeCache,
+    object: &T,
+    index: Index,
+    world: &World,
+) -> Option<TOI>
+where
+    T: WithVelocity + WithIsometry + WithShape,
+{
+    match index {
+        Index::Actor(i) => time_of_impact(duration, shape_cache, object, &world.actors[i]),
+        Index::Projectile(i) => {
+            time_of_impact(duration, shape_cache, object, &world.projectiles[i])
+        }
+        _ => None,
+    }
+}
+
 impl WithVelocity for Actor {
     fn velocity(&self) -> Vec2f {
         self.velocity
@@ -2245,6 +3379,7 @@ trait CollidingObject<T: Default + PartialEq>: WithVelocity + WithShape + WithIs
     fn set_position(&mut self, value: Vec2f);
     fn set_velocity(&mut self, value: Vec2f);
     fn magick(&self) -> &Magick;
+    fn owner(&self) -> Option<ActorId>;
     fn resistance(&self) -> &[T; 11];
     fn effect(&self) -> &Effect;
     fn set_effect(&mut self, value: Effect);
@@ -2433,8 +3568,20 @@ where
             );
         }
     }
-    let new_lhs_effect = add_magick_to_effect(now, lhs.effect(), rhs.magick(), lhs.resistance());
-    let new_rhs_effect = add_magick_to_effect(now, rhs.effect(), lhs.magick(), rhs.resistance());
+    let new_lhs_effect = add_magick_to_effect(
+        now,
+        lhs.effect(),
+        rhs.magick(),
+        rhs.owner(),
+        lhs.resistance(),
+    );
+    let new_rhs_effect = add_magick_to_effect(
+        now,
+        rhs.effect(),
+        lhs.magick(),
+        lhs.owner(),
+        rhs.resistance(),
+    );
     lhs.set_effect(new_lhs_effect);
     rhs.set_effect(new_rhs_effect);
     let damage_energy = ((lhs_kinetic_energy + rhs_kinetic_energy)
@@ -2560,6 +3707,10 @@ impl CollidingObject<bool> for Actor {
         &DEFAULT_MAGICK
     }
 
+    fn owner(&self) -> Option<ActorId> {
+        None
+    }
+
     fn resistance(&self) -> &[bool; 11] {
         &self.aura.elements
     }
@@ -2606,6 +3757,10 @@ impl CollidingObject<f64> for Projectile {
         &self.magick
     }
 
+    fn owner(&self) -> Option<ActorId> {
+        Some(self.actor_id)
+    }
+
     fn resistance(&self) -> &[f64; 11] {
         &self.magick.power
     }
@@ -2646,6 +3801,10 @@ impl CollidingObject<bool> for StaticObject {
         &DEFAULT_MAGICK
     }
 
+    fn owner(&self) -> Option<ActorId> {
+        None
+    }
+
     fn resistance(&self) -> &[bool; 11] {
         &DEFAULT_RESISTANCE
     }
@@ -2688,6 +3847,10 @@ impl CollidingObject<bool> for Shield {
         &DEFAULT_MAGICK
     }
 
+    fn owner(&self) -> Option<ActorId> {
+        Some(self.actor_id)
+    }
+
     fn resistance(&self) -> &[bool; 11] {
         &[true; 11]
     }
@@ -2728,6 +3891,10 @@ impl CollidingObject<f64> for TempObstacle {
         &self.magick
     }
 
+    fn owner(&self) -> Option<ActorId> {
+        Some(self.actor_id)
+    }
+
     fn resistance(&self) -> &[f64; 11] {
         &self.magick.power
     }
@@ -2793,6 +3960,7 @@ fn handle_completed_magicks(world: &mut World) {
                 power[Element::Earth as usize] = 0.0;
                 world.projectiles.push(Projectile {
                     id: ProjectileId(get_next_id(&mut world.id_counter)),
+                    actor_id: actor.id,
                     body: Body {
                         shape: Disk { radius },
                         material_type: MaterialType::Stone,
@@ -2809,6 +3977,7 @@ fn handle_completed_magicks(world: &mut World) {
                             * world.settings.magic_force_multiplier),
                     position_z: 1.5 * actor.body.shape.radius,
                     velocity_z: 0.0,
+                    deadline: f64::MAX,
                 });
                 actor.delayed_magick = None;
             }
@@ -2838,24 +4007,29 @@ fn handle_completed_magicks(world: &mut World) {
     }
 }
 
-fn update_actor_occupations(world: &mut World) {
+fn update_actor_occupations(world: &mut World, index: &WorldIndex) {
     for actor in world.actors.iter_mut() {
         match actor.occupation {
             ActorOccupation::None => (),
             ActorOccupation::Shooting(gun_id) => {
-                if !world.guns.iter().any(|v| v.id == gun_id) {
+                if index.gun(gun_id).is_none() {
                     actor.occupation = ActorOccupation::None;
                 }
             }
             ActorOccupation::Spraying {
                 bounded_area_id, ..
             } => {
-                if !world.bounded_areas.iter().any(|v| v.id == bounded_area_id) {
+                if index.bounded_area(bounded_area_id).is_none() {
                     actor.occupation = ActorOccupation::None;
                 }
             }
             ActorOccupation::Beaming(beam_id) => {
-                if !world.beams.iter().any(|v| v.id == beam_id) {
+                if index.beam(beam_id).is_none() {
+                    actor.occupation = ActorOccupation::None;
+                }
+            }
+            ActorOccupation::Striking(lightning_bolt_id) => {
+                if index.lightning_bolt(lightning_bolt_id).is_none() {
                     actor.occupation = ActorOccupation::None;
                 }
             }
@@ -2922,7 +4096,7 @@ fn make_circle_arc_polyline(arc: &CircleArcKey) -> Polyline {
     Polyline::new(vertices, None)
 }
 
-fn spawn_player_actors<R: Rng>(world: &mut World, rng: &mut R) {
+fn spawn_player_actors<R: Rng>(world: &mut World, events: &mut Vec<WorldEvent>, rng: &mut R) {
     for player in world.players.iter_mut() {
         if player.active && player.actor_id.is_none() && player.spawn_time <= world.time {
             let actor_id = ActorId(get_next_id(&mut world.id_counter));
@@ -2930,18 +4104,23 @@ fn spawn_player_actors<R: Rng>(world: &mut World, rng: &mut R) {
                 actor_id,
                 player.id,
                 player.name.clone(),
+                player.team,
                 &world.bounds,
                 rng,
             ));
             player.actor_id = Some(actor_id);
+            events.push(WorldEvent::Spawn {
+                actor_id,
+                player_id: player.id,
+            });
         }
     }
 }
 
-fn update_player_spawn_time(world: &mut World) {
+fn update_player_spawn_time(world: &mut World, index: &WorldIndex) {
     for player in world.players.iter_mut() {
         if let Some(actor_id) = player.actor_id {
-            if !world.actors.iter().any(|v| v.id == actor_id) {
+            if index.actor(actor_id).is_none() {
                 player.actor_id = None;
                 player.spawn_time = world.time + world.settings.player_actor_respawn_delay;
                 player.deaths += 1;
@@ -2950,44 +4129,60 @@ fn update_player_spawn_time(world: &mut World) {
     }
 }
 
-fn shoot_from_guns<R: Rng>(world: &mut World, rng: &mut R) {
+fn shoot_from_guns<R: Rng>(world: &mut World, index: &WorldIndex, rng: &mut R) {
     for gun in world.guns.iter_mut() {
         if gun.last_shot + gun.shot_period > world.time {
             continue;
         }
-        if let Some(actor) = world.actors.iter().find(|v| v.id == gun.actor_id) {
+        if let Some(actor_index) = index.actor(gun.actor_id) {
+            let actor = &world.actors[actor_index];
             gun.last_shot = world.time;
             gun.shots_left -= 1;
-            let radius = world.settings.gun_bullet_radius;
-            world.projectiles.push(Projectile {
-                id: ProjectileId(get_next_id(&mut world.id_counter)),
-                body: Body {
-                    shape: Disk { radius },
-                    material_type: MaterialType::Ice,
-                },
-                position: actor.position
-                    + actor.current_direction
-                        * (actor.body.shape.radius + radius + world.settings.margin),
-                health: 1.0,
-                magick: Magick {
-                    power: gun.bullet_power,
-                },
-                velocity: actor.velocity,
-                dynamic_force: (actor.current_direction * gun.bullet_force_factor).rotated(
-                    rng.gen_range(
-                        -world.settings.gun_half_grouping_angle
-                            ..world.settings.gun_half_grouping_angle,
-                    ),
-                ),
-                position_z: 1.5 * actor.body.shape.radius,
-                velocity_z: 0.0,
-            });
+            world.projectiles.push(make_bullet(
+                ProjectileId(get_next_id(&mut world.id_counter)),
+                actor,
+                actor.current_direction,
+                (actor.current_direction * gun.bullet_force_factor).rotated(rng.gen_range(
+                    -world.settings.gun_half_grouping_angle..world.settings.gun_half_grouping_angle,
+                )),
+                gun.bullet_power,
+                f64::MAX,
+                &world.settings,
+            ));
         } else {
             gun.shots_left = 0;
         }
     }
 }
 
+fn make_bullet(
+    id: ProjectileId,
+    actor: &Actor,
+    direction: Vec2f,
+    dynamic_force: Vec2f,
+    power: [f64; 11],
+    deadline: f64,
+    settings: &WorldSettings,
+) -> Projectile {
+    let radius = settings.gun_bullet_radius;
+    Projectile {
+        id,
+        actor_id: actor.id,
+        body: Body {
+            shape: Disk { radius },
+            material_type: MaterialType::Ice,
+        },
+        position: actor.position + direction * (actor.body.shape.radius + radius + settings.margin),
+        health: 1.0,
+        magick: Magick { power },
+        velocity: actor.velocity,
+        dynamic_force,
+        position_z: 1.5 * actor.body.shape.radius,
+        velocity_z: 0.0,
+        deadline,
+    }
+}
+
 fn is_actor_immobilized(actor: &Actor) -> bool {
     actor.effect.power[Element::Ice as usize] > 0.0
 }
@@ -3016,38 +4211,267 @@ fn remove_inactive_actors_occupation_results(world: &mut World) {
                 world.fields.retain(|v| v.id != field_id);
             }
             ActorOccupation::Beaming(beam_id) => world.beams.retain(|v| v.id != beam_id),
+            ActorOccupation::Striking(lightning_bolt_id) => {
+                world.lightning_bolts.retain(|v| v.id != lightning_bolt_id)
+            }
+        }
+    }
+}
+
+fn get_number_of_shield_objects(angle: f64) -> i32 {
+    (angle / (std::f64::consts::FRAC_PI_2 / 5.0)).round() as i32
+}
+
+#[allow(clippy::large_enum_variant)]
+enum EngineEvent {
+    PhysicalDamage {
+        source: Index,
+        target: Index,
+        damage: f64,
+    },
+    MagicalDamage {
+        target: Index,
+        damage: f64,
+        elements: [f64; 11],
+        sources: [Option<ActorId>; 11],
+    },
+    Collision {
+        lhs: Index,
+        rhs: Index,
+    },
+}
+
+fn handle_events(
+    events: &[EngineEvent],
+    world: &mut World,
+    index: &WorldIndex,
+    world_events: &mut Vec<WorldEvent>,
+) {
+    for event in events {
+        match event {
+            EngineEvent::PhysicalDamage {
+                source,
+                target,
+                damage,
+            } => {
+                let owner = get_owner_id(*source, world);
+                let damage = match (owner, target) {
+                    (Some(owner), Index::Actor(i)) if is_teammate(owner, *i, world, index) => {
+                        *damage * world.settings.friendly_fire_factor
+                    }
+                    _ => *damage,
+                };
+                world_events.push(WorldEvent::Damage {
+                    source: Some(get_object_id(*source, world)),
+                    target: get_object_id(*target, world),
+                    physical: damage,
+                    magical: [0.0; 11],
+                });
+                if let (Some(owner), Index::Actor(i)) = (owner, target) {
+                    credit_damage(owner, world.actors[*i].id, damage, world, index);
+                }
+                damage_object(damage, *target, world, index);
+            }
+            EngineEvent::MagicalDamage {
+                target,
+                damage,
+                elements,
+                sources,
+            } => {
+                let mut damage = *damage;
+                let mut elements = *elements;
+                if let Index::Actor(i) = target {
+                    let mut friendly_fire = false;
+                    for (value, source) in elements.iter_mut().zip(sources.iter()) {
+                        if let Some(source) = source {
+                            if *value > 0.0 && is_teammate(*source, *i, world, index) {
+                                *value *= world.settings.friendly_fire_factor;
+                                friendly_fire = true;
+                            }
+                        }
+                    }
+                    if friendly_fire {
+                        damage = elements.iter().sum();
+                    }
+                }
+                for (i, source) in sources.iter().enumerate() {
+                    if elements[i] == 0.0 || sources[..i].contains(source) {
+                        continue;
+                    }
+                    let mut magical = [0.0; 11];
+                    for j in i..elements.len() {
+                        if sources[j] == *source {
+                            magical[j] = elements[j];
+                        }
+                    }
+                    world_events.push(WorldEvent::Damage {
+                        source: source.map(ObjectId::Actor),
+                        target: get_object_id(*target, world),
+                        physical: 0.0,
+                        magical,
+                    });
+                    if let (Some(source), Index::Actor(target)) = (source, target) {
+                        let target_id = world.actors[*target].id;
+                        for damage in [
+                            magical.iter().filter(|v| **v > 0.0).sum(),
+                            magical.iter().filter(|v| **v < 0.0).sum(),
+                        ] {
+                            credit_damage(*source, target_id, damage, world, index);
+                        }
+                    }
+                }
+                damage_object(damage, *target, world, index);
+            }
+            EngineEvent::Collision { lhs, rhs } => world_events.push(WorldEvent::Collision {
+                lhs: get_object_id(*lhs, world),
+                rhs: get_object_id(*rhs, world),
+            }),
+        }
+    }
+}
+
+fn is_teammate(source: ActorId, target: usize, world: &World, index: &WorldIndex) -> bool {
+    let target = &world.actors[target];
+    if target.id == source {
+        return false;
+    }
+    match index.actor(source) {
+        Some(i) => is_same_team(world.actors[i].team, target.team),
+        None => false,
+    }
+}
+
+fn is_same_team(lhs: Option<TeamId>, rhs: Option<TeamId>) -> bool {
+    lhs.is_some() && lhs == rhs
+}
+
+fn get_owner_id(index: Index, world: &World) -> Option<ActorId> {
+    match index {
+        Index::Actor(i) => Some(world.actors[i].id),
+        Index::Projectile(i) => Some(world.projectiles[i].actor_id),
+        Index::StaticObject(..) => None,
+        Index::Shield(i) => Some(world.shields[i].actor_id),
+        Index::TempObstacle(i) => Some(world.temp_obstacles[i].actor_id),
+    }
+}
+
+fn get_object_id(index: Index, world: &World) -> ObjectId {
+    match index {
+        Index::Actor(i) => ObjectId::Actor(world.actors[i].id),
+        Index::Projectile(i) => ObjectId::Projectile(world.projectiles[i].id),
+        Index::StaticObject(i) => ObjectId::StaticObject(world.static_objects[i].id),
+        Index::Shield(i) => ObjectId::Shield(world.shields[i].id),
+        Index::TempObstacle(i) => ObjectId::TempObstacle(world.temp_obstacles[i].id),
+    }
+}
+
+fn damage_object(damage: f64, target: Index, world: &mut World, index: &WorldIndex) {
+    match target {
+        Index::Actor(i) => damage_actor(damage, i, world, index),
+        Index::Projectile(i) => damage_health(damage, &mut world.projectiles[i].health),
+        Index::StaticObject(i) => damage_health(damage, &mut world.static_objects[i].health),
+        Index::Shield(..) => (),
+        Index::TempObstacle(i) => damage_health(damage, &mut world.temp_obstacles[i].health),
+    }
+}
+
+#[derive(Default)]
+struct LagCompensation {
+    actors: Vec<(ActorId, Vec<(ActorId, Vec2f)>)>,
+}
+
+impl LagCompensation {
+    fn add(&mut self, actor_id: ActorId, past_world: &World) {
+        let mut positions: Vec<(ActorId, Vec2f)> = past_world
+            .actors
+            .iter()
+            .filter(|v| v.id != actor_id)
+            .map(|v| (v.id, v.position))
+            .collect();
+        positions.sort_by_key(|v| v.0 .0);
+        self.actors.retain(|v| v.0 != actor_id);
+        self.actors.push((actor_id, positions));
+    }
+
+    fn clear(&mut self) {
+        self.actors.clear();
+    }
+
+    fn get_past_position(&self, source: ActorId, target: ActorId) -> Option<Vec2f> {
+        let (_, positions) = self.actors.iter().find(|v| v.0 == source)?;
+        positions
+            .binary_search_by_key(&target.0, |v| v.0 .0)
+            .ok()
+            .map(|i| positions[i].1)
+    }
+
+    fn get_owner_position(&self, owner: &Actor, target: ActorId, position: Vec2f) -> Vec2f {
+        match self.get_past_position(owner.id, target) {
+            Some(past_position) => owner.position + position - past_position,
+            None => owner.position,
+        }
+    }
+
+    fn rewind(&self, source: ActorId, world: &mut World) -> Vec<(usize, Vec2f)> {
+        let mut positions = Vec::new();
+        for (i, actor) in world.actors.iter_mut().enumerate() {
+            if let Some(past_position) = self.get_past_position(source, actor.id) {
+                positions.push((i, actor.position));
+                actor.position = past_position;
+            }
         }
+        positions
+    }
+}
+
+fn credit_damage(
+    source: ActorId,
+    target: ActorId,
+    damage: f64,
+    world: &mut World,
+    index: &WorldIndex,
+) {
+    if damage == 0.0 {
+        return;
+    }
+    let (source_index, target_index) = match (index.actor(source), index.actor(target)) {
+        (Some(source_index), Some(target_index)) => (source_index, target_index),
+        _ => return,
+    };
+    if damage > 0.0 && (source == target || is_teammate(source, target_index, world, index)) {
+        return;
+    }
+    let player_id = world.actors[source_index].player_id;
+    let player = match index.player(player_id) {
+        Some(v) => &mut world.players[v],
+        None => return,
+    };
+    if damage > 0.0 {
+        player.damage_dealt += damage;
+        let damagers = &mut world.actors[target_index].damagers;
+        damagers.retain(|v| *v != player_id);
+        damagers.push(player_id);
+    } else {
+        player.damage_healed -= damage;
     }
 }
 
-fn get_number_of_shield_objects(angle: f64) -> i32 {
-    (angle / (std::f64::consts::FRAC_PI_2 / 5.0)).round() as i32
-}
-
-enum EngineEvent {
-    Damage { target: Index, damage: f64 },
-}
-
-fn handle_events(events: &[EngineEvent], world: &mut World) {
-    for event in events {
-        match event {
-            EngineEvent::Damage { target, damage } => match target {
-                Index::Actor(i) => {
-                    world.actors[*i].delayed_magick = None;
-                    complete_directed_magick(*i, world);
-                    damage_health(*damage, &mut world.actors[*i].health)
-                }
-                Index::Projectile(i) => damage_health(*damage, &mut world.projectiles[*i].health),
-                Index::StaticObject(i) => {
-                    damage_health(*damage, &mut world.static_objects[*i].health)
-                }
-                Index::Shield(..) => (),
-                Index::TempObstacle(i) => {
-                    damage_health(*damage, &mut world.temp_obstacles[*i].health)
-                }
-            },
+fn credit_kill(damagers: &[PlayerId], players: &mut [Player]) -> Option<PlayerId> {
+    let killer = *damagers.last()?;
+    for player in players.iter_mut() {
+        if player.id == killer {
+            player.kills += 1;
+        } else if damagers.contains(&player.id) {
+            player.assists += 1;
         }
     }
+    Some(killer)
+}
+
+fn damage_actor(damage: f64, actor_index: usize, world: &mut World, index: &WorldIndex) {
+    world.actors[actor_index].delayed_magick = None;
+    complete_directed_magick(actor_index, world, index);
+    damage_health(damage, &mut world.actors[actor_index].health)
 }
 
 fn damage_health(damage: f64, health: &mut f64) {
@@ -3059,6 +4483,7 @@ mod tests {
     use nalgebra::distance;
     use parry2d_f64::na::Unit;
     use parry2d_f64::query::TOIStatus;
+    use rand::SeedableRng;
 
     use crate::engine::*;
     use crate::world::StaticObjectId;
@@ -3107,6 +4532,7 @@ mod tests {
         };
         let projectile = Projectile {
             id: ProjectileId(2),
+            actor_id: ActorId(0),
             body: Body {
                 shape: Disk { radius: 0.2 },
                 material_type: MaterialType::Stone,
@@ -3118,6 +4544,7 @@ mod tests {
             dynamic_force: Vec2f::ZERO,
             position_z: 1.5,
             velocity_z: -0.08166666666666667,
+            deadline: f64::MAX,
         };
         let toi = time_of_impact(duration, &shape_cache, &shield, &projectile).unwrap();
         assert!(
@@ -3159,6 +4586,7 @@ mod tests {
             player_id: PlayerId(2),
             active: true,
             name: String::new(),
+            team: None,
             body: Body {
                 shape: Disk { radius: 1.0 },
                 material_type: MaterialType::Flesh,
@@ -3177,9 +4605,11 @@ mod tests {
             position_z: 1.0,
             velocity_z: 0.0,
             occupation: ActorOccupation::None,
+            damagers: Vec::new(),
         };
         let mut projectile = Projectile {
             id: Default::default(),
+            actor_id: ActorId(0),
             body: Body {
                 shape: Disk { radius: 0.1 },
                 material_type: MaterialType::Stone,
@@ -3191,6 +4621,7 @@ mod tests {
             dynamic_force: Vec2f::ZERO,
             position_z: 1.0,
             velocity_z: 0.0,
+            deadline: f64::MAX,
         };
         let toi = time_of_impact(duration, &shape_cache, &actor, &projectile);
         assert!(toi.is_some());
@@ -3210,6 +4641,7 @@ mod tests {
                 player_id: PlayerId(2),
                 active: true,
                 name: String::new(),
+                team: None,
                 body: Body {
                     shape: Disk { radius: 1.0 },
                     material_type: MaterialType::Flesh,
@@ -3228,12 +4660,14 @@ mod tests {
                 position_z: 1.0,
                 velocity_z: 0.0,
                 occupation: ActorOccupation::None,
+                damagers: Vec::new(),
             }
         );
         assert_eq!(
             projectile,
             Projectile {
                 id: Default::default(),
+                actor_id: ActorId(0),
                 body: Body {
                     shape: Disk { radius: 0.1 },
                     material_type: MaterialType::Stone,
@@ -3245,6 +4679,7 @@ mod tests {
                 dynamic_force: Vec2f::ZERO,
                 position_z: 1.0,
                 velocity_z: 0.0,
+                deadline: f64::MAX,
             }
         );
     }
@@ -3255,6 +4690,7 @@ mod tests {
         let shape_cache = ShapeCache::default();
         let mut projectile = Projectile {
             id: Default::default(),
+            actor_id: ActorId(0),
             body: Body {
                 shape: Disk { radius: 1.0 },
                 material_type: MaterialType::Stone,
@@ -3266,6 +4702,7 @@ mod tests {
             dynamic_force: Vec2f::ZERO,
             position_z: 1.0,
             velocity_z: 0.0,
+            deadline: f64::MAX,
         };
         let mut static_object = StaticObject {
             id: StaticObjectId(1),
@@ -3296,6 +4733,7 @@ mod tests {
             projectile,
             Projectile {
                 id: Default::default(),
+                actor_id: ActorId(0),
                 body: Body {
                     shape: Disk { radius: 1.0 },
                     material_type: MaterialType::Stone,
@@ -3307,6 +4745,7 @@ mod tests {
                 dynamic_force: Vec2f::ZERO,
                 position_z: 1.0,
                 velocity_z: 0.0,
+                deadline: f64::MAX,
             }
         );
         assert_eq!(
@@ -3334,6 +4773,7 @@ mod tests {
         let shape_cache = ShapeCache::default();
         let projectile1 = Projectile {
             id: Default::default(),
+            actor_id: ActorId(0),
             body: Body {
                 shape: Disk { radius: 1.0 },
                 material_type: MaterialType::Stone,
@@ -3345,9 +4785,11 @@ mod tests {
             dynamic_force: Vec2f::ZERO,
             position_z: 1.0,
             velocity_z: 0.0,
+            deadline: f64::MAX,
         };
         let projectile2 = Projectile {
             id: Default::default(),
+            actor_id: ActorId(0),
             body: Body {
                 shape: Disk { radius: 2.0 },
                 material_type: MaterialType::Stone,
@@ -3359,6 +4801,7 @@ mod tests {
             dynamic_force: Vec2f::ZERO,
             position_z: 1.0,
             velocity_z: 0.0,
+            deadline: f64::MAX,
         };
         let toi = time_of_impact(duration, &shape_cache, &projectile1, &projectile2);
         assert!(toi.is_some());
@@ -3404,4 +4847,719 @@ mod tests {
             )
         }
     }
+
+    fn make_test_actor(id: u64, position: Vec2f) -> Actor {
+        Actor {
+            id: ActorId(id),
+            player_id: PlayerId(0),
+            active: true,
+            name: String::new(),
+            team: None,
+            body: Body {
+                shape: Disk { radius: 1.0 },
+                material_type: MaterialType::Flesh,
+            },
+            position,
+            health: 1.0,
+            effect: Effect::default(),
+            aura: Aura::default(),
+            velocity: Vec2f::ZERO,
+            dynamic_force: Vec2f::ZERO,
+            current_direction: Vec2f::only_x(1.0),
+            target_direction: Vec2f::only_x(1.0),
+            spell_elements: Vec::new(),
+            moving: false,
+            delayed_magick: None,
+            position_z: 1.0,
+            velocity_z: 0.0,
+            occupation: ActorOccupation::None,
+            damagers: Vec::new(),
+        }
+    }
+
+    fn make_lightning_test_world(spread: Option<f64>, positions: &[Vec2f]) -> World {
+        let mut magick = Magick::default();
+        magick.power[Element::Lightning as usize] = 1.0;
+        World {
+            actors: positions
+                .iter()
+                .enumerate()
+                .map(|(i, v)| make_test_actor(i as u64 + 1, *v))
+                .collect(),
+            lightning_bolts: vec![LightningBolt {
+                id: LightningBoltId(1),
+                actor_id: ActorId(1),
+                magick,
+                spread,
+                deadline: 1.0,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lightning_collider_should_chain_directed_lightning_through_actors_in_range() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lightning_test_world(
+            None,
+            &[Vec2f::ZERO, Vec2f::only_x(4.0), Vec2f::new(4.0, 4.0)],
+        );
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = LightningCollider::default();
+        collider.update(&mut world, &shape_cache, &index);
+        assert_eq!(collider.arcs.len(), 2);
+        assert_eq!(world.actors[0].effect.power, [0.0; 11]);
+        assert_eq!(
+            world.actors[1].effect.power[Element::Lightning as usize],
+            1.0
+        );
+        assert_eq!(
+            world.actors[2].effect.power[Element::Lightning as usize],
+            1.0
+        );
+    }
+
+    #[test]
+    fn lightning_collider_should_not_strike_actors_outside_of_cone() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lightning_test_world(None, &[Vec2f::ZERO, Vec2f::only_y(4.0)]);
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = LightningCollider::default();
+        collider.update(&mut world, &shape_cache, &index);
+        assert!(collider.arcs.is_empty());
+        assert_eq!(world.actors[1].effect.power, [0.0; 11]);
+    }
+
+    #[test]
+    fn lightning_collider_should_stop_chain_at_actor_with_earth_aura() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lightning_test_world(
+            None,
+            &[Vec2f::ZERO, Vec2f::only_x(4.0), Vec2f::new(4.0, 4.0)],
+        );
+        world.actors[1].aura.elements[Element::Earth as usize] = true;
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = LightningCollider::default();
+        collider.update(&mut world, &shape_cache, &index);
+        assert_eq!(collider.arcs.len(), 1);
+        assert_eq!(world.actors[1].effect.power, [0.0; 11]);
+        assert_eq!(world.actors[2].effect.power, [0.0; 11]);
+    }
+
+    #[test]
+    fn lightning_collider_should_not_strike_actor_with_shield_aura() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lightning_test_world(None, &[Vec2f::ZERO, Vec2f::only_x(4.0)]);
+        world.actors[1].aura.elements[Element::Shield as usize] = true;
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = LightningCollider::default();
+        collider.update(&mut world, &shape_cache, &index);
+        assert_eq!(collider.arcs.len(), 1);
+        assert_eq!(world.actors[1].effect.power, [0.0; 11]);
+    }
+
+    #[test]
+    fn lightning_collider_should_ground_chain_at_wet_actor() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lightning_test_world(
+            None,
+            &[Vec2f::ZERO, Vec2f::only_x(4.0), Vec2f::new(4.0, 4.0)],
+        );
+        world.actors[1].effect.power[Element::Water as usize] = 1.0;
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = LightningCollider::default();
+        collider.update(&mut world, &shape_cache, &index);
+        assert_eq!(collider.arcs.len(), 1);
+        assert_eq!(
+            world.actors[1].effect.power[Element::Lightning as usize],
+            1.0
+        );
+        assert_eq!(world.actors[2].effect.power, [0.0; 11]);
+    }
+
+    #[test]
+    fn lightning_collider_should_strike_all_actors_around_for_area_of_effect_lightning() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lightning_test_world(
+            Some(std::f64::consts::TAU),
+            &[
+                Vec2f::ZERO,
+                Vec2f::only_x(4.0),
+                Vec2f::only_x(-4.0),
+                Vec2f::only_x(20.0),
+            ],
+        );
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = LightningCollider::default();
+        collider.update(&mut world, &shape_cache, &index);
+        assert_eq!(collider.arcs.len(), 2);
+        assert_eq!(
+            world.actors[1].effect.power[Element::Lightning as usize],
+            1.0
+        );
+        assert_eq!(
+            world.actors[2].effect.power[Element::Lightning as usize],
+            1.0
+        );
+        assert_eq!(world.actors[3].effect.power, [0.0; 11]);
+    }
+
+    fn make_lag_compensation_test_world(positions: &[Vec2f]) -> World {
+        let mut magick = Magick::default();
+        magick.power[Element::Arcane as usize] = 1.0;
+        World {
+            bounds: Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0)),
+            actors: positions
+                .iter()
+                .enumerate()
+                .map(|(i, v)| make_test_actor(i as u64 + 1, *v))
+                .collect(),
+            beams: vec![Beam {
+                id: BeamId(3),
+                actor_id: ActorId(1),
+                magick: magick.clone(),
+                deadline: 1.0,
+            }],
+            bounded_areas: vec![BoundedArea {
+                id: BoundedAreaId(4),
+                actor_id: ActorId(1),
+                body: RingSector {
+                    min_radius: 1.0,
+                    max_radius: 6.0,
+                    angle: std::f64::consts::FRAC_PI_4,
+                },
+                magick,
+                deadline: 1.0,
+            }],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn beam_collider_should_hit_actor_at_lag_compensated_position() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lag_compensation_test_world(&[Vec2f::ZERO, Vec2f::only_y(5.0)]);
+        let mut past_world = world.clone();
+        past_world.actors[1].position = Vec2f::only_x(5.0);
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = BeamCollider::default();
+        let mut lag_compensation = LagCompensation::default();
+        collider.update(&mut world, &shape_cache, &index, &lag_compensation);
+        assert_eq!(world.actors[1].effect.power, [0.0; 11]);
+        lag_compensation.add(ActorId(1), &past_world);
+        collider.update(&mut world, &shape_cache, &index, &lag_compensation);
+        assert!(world.actors[1].effect.power[Element::Arcane as usize] > 0.0);
+        assert_eq!(
+            world.actors[1].effect.sources[Element::Arcane as usize],
+            Some(ActorId(1))
+        );
+        assert_eq!(world.actors[1].position, Vec2f::only_y(5.0));
+    }
+
+    #[test]
+    fn beam_collider_should_rewind_only_for_compensated_beam_owner() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_lag_compensation_test_world(&[
+            Vec2f::ZERO,
+            Vec2f::only_y(10.0),
+            Vec2f::new(5.0, 10.0),
+        ]);
+        world.beams.push(Beam {
+            id: BeamId(5),
+            actor_id: ActorId(2),
+            magick: world.beams[0].magick.clone(),
+            deadline: 1.0,
+        });
+        let mut past_world = world.clone();
+        past_world.actors[2].position = Vec2f::only_x(5.0);
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = BeamCollider::default();
+        let mut lag_compensation = LagCompensation::default();
+        lag_compensation.add(ActorId(1), &past_world);
+        collider.update(&mut world, &shape_cache, &index, &lag_compensation);
+        assert_eq!(collider.initial_beams.len(), 2);
+        for beam in collider.initial_beams.iter() {
+            assert!(beam.length < 5.0, "{:?} {}", beam.actor_id, beam.length);
+        }
+        assert_eq!(world.actors[2].position, Vec2f::new(5.0, 10.0));
+    }
+
+    #[test]
+    fn bounded_area_should_hit_actor_at_lag_compensated_position() {
+        let mut world = make_lag_compensation_test_world(&[Vec2f::ZERO, Vec2f::only_y(3.0)]);
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut past_world = world.clone();
+        past_world.actors[1].position = Vec2f::only_x(3.0);
+        let mut lag_compensation = LagCompensation::default();
+        intersect_actor_with_all_bounded_areas(
+            world.time,
+            1,
+            &world.bounded_areas,
+            &index,
+            &lag_compensation,
+            &mut world.actors,
+        );
+        assert_eq!(world.actors[1].effect.power, [0.0; 11]);
+        lag_compensation.add(ActorId(1), &past_world);
+        intersect_actor_with_all_bounded_areas(
+            world.time,
+            1,
+            &world.bounded_areas,
+            &index,
+            &lag_compensation,
+            &mut world.actors,
+        );
+        assert!(world.actors[1].effect.power[Element::Arcane as usize] > 0.0);
+        assert_eq!(
+            world.actors[1].effect.sources[Element::Arcane as usize],
+            Some(ActorId(1))
+        );
+    }
+
+    fn make_area_of_effect_test_world(element: Element, positions: &[Vec2f]) -> World {
+        let mut world = World {
+            actors: positions
+                .iter()
+                .enumerate()
+                .map(|(i, v)| make_test_actor(i as u64 + 1, *v))
+                .collect(),
+            ..Default::default()
+        };
+        world.actors[0].spell_elements.push(element);
+        world
+    }
+
+    #[test]
+    fn quake_should_lift_and_damage_actors_in_range() {
+        let mut world = make_area_of_effect_test_world(
+            Element::Earth,
+            &[Vec2f::ZERO, Vec2f::only_x(3.0), Vec2f::only_x(10.0)],
+        );
+        let mut events = Vec::new();
+        start_area_of_effect_magick(0, &mut world, &WorldIndex::default(), &mut events);
+        assert_eq!(world.actors[0].health, 1.0);
+        assert_eq!(world.actors[0].velocity_z, 0.0);
+        assert!(world.actors[1].health < 1.0);
+        assert!(world.actors[1].velocity_z > 0.0);
+        assert_eq!(world.actors[2].health, 1.0);
+        assert_eq!(world.actors[2].velocity_z, 0.0);
+        assert!(matches!(
+            events.as_slice(),
+            [WorldEvent::Damage {
+                source: Some(ObjectId::Actor(ActorId(1))),
+                target: ObjectId::Actor(ActorId(2)),
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn rebuild_index_should_replace_index_of_previous_world() {
+        let mut engine = Engine::default();
+        engine.rebuild_index(&World {
+            actors: vec![make_test_actor(1, Vec2f::ZERO)],
+            ..Default::default()
+        });
+        engine.rebuild_index(&World {
+            actors: vec![make_test_actor(2, Vec2f::ZERO)],
+            ..Default::default()
+        });
+        assert_eq!(engine.index().actor(ActorId(1)), None);
+        assert_eq!(engine.index().actor(ActorId(2)), Some(0));
+    }
+
+    #[test]
+    fn engine_update_should_report_casts_spawns_and_deaths() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut world = World {
+            bounds: Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0)),
+            players: vec![Player {
+                id: PlayerId(1),
+                active: true,
+                name: String::new(),
+                team: None,
+                actor_id: None,
+                spawn_time: 0.0,
+                deaths: 0,
+                kills: 0,
+                assists: 0,
+                damage_dealt: 0.0,
+                damage_healed: 0.0,
+            }],
+            actors: vec![make_test_actor(2, Vec2f::ZERO)],
+            id_counter: 3,
+            ..Default::default()
+        };
+        let mut engine = Engine::default();
+        engine.apply_cast_action(CastAction::AddSpellElement(Element::Fire), 0, &mut world);
+        world.actors[0].health = 0.0;
+        engine.update(0.01, &mut world, &mut rng);
+        assert_eq!(
+            engine.world_events(),
+            &vec![
+                WorldEvent::Cast {
+                    actor_id: ActorId(2),
+                    cast_action: CastAction::AddSpellElement(Element::Fire),
+                },
+                WorldEvent::Spawn {
+                    actor_id: ActorId(3),
+                    player_id: PlayerId(1),
+                },
+                WorldEvent::Death {
+                    actor_id: ActorId(2),
+                    player_id: PlayerId(0),
+                    killer: None,
+                },
+            ]
+        );
+        engine.update(0.01, &mut world, &mut rng);
+        assert!(engine.world_events().is_empty());
+    }
+
+    fn make_kill_attribution_test_world() -> World {
+        let mut world = make_area_of_effect_test_world(
+            Element::Earth,
+            &[Vec2f::ZERO, Vec2f::only_x(3.0), Vec2f::only_x(-3.0)],
+        );
+        world.bounds = Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0));
+        for (i, actor) in world.actors.iter_mut().enumerate() {
+            actor.player_id = PlayerId(i as u64 + 11);
+            world.players.push(Player {
+                id: actor.player_id,
+                active: true,
+                name: String::new(),
+                team: None,
+                actor_id: Some(actor.id),
+                spawn_time: 0.0,
+                deaths: 0,
+                kills: 0,
+                assists: 0,
+                damage_dealt: 0.0,
+                damage_healed: 0.0,
+            });
+        }
+        world
+    }
+
+    #[test]
+    fn engine_update_should_credit_kill_to_last_damaging_player_and_assists_to_others() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut world = make_kill_attribution_test_world();
+        world.actors[1].health = 0.01;
+        world.actors[1].effect.power[Element::Poison as usize] = 1e-3;
+        world.actors[1].effect.sources[Element::Poison as usize] = Some(ActorId(3));
+        let mut engine = Engine::default();
+        engine.update(0.01, &mut world, &mut rng);
+        assert_eq!(world.actors[1].damagers, vec![PlayerId(13)]);
+        world.actors[1].effect.power[Element::Poison as usize] = 0.0;
+        engine.apply_cast_action(CastAction::StartAreaOfEffectMagick, 0, &mut world);
+        engine.update(0.01, &mut world, &mut rng);
+        assert!(engine.world_events().contains(&WorldEvent::Death {
+            actor_id: ActorId(2),
+            player_id: PlayerId(12),
+            killer: Some(PlayerId(11)),
+        }));
+        assert_eq!(world.players[0].kills, 1);
+        assert!(world.players[0].damage_dealt > 0.0);
+        assert_eq!(world.players[1].deaths, 1);
+        assert_eq!(world.players[2].kills, 0);
+        assert_eq!(world.players[2].assists, 1);
+        assert!(world.players[2].damage_dealt > 0.0);
+    }
+
+    #[test]
+    fn engine_update_should_credit_damage_over_time_kill_to_status_applier() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut world = make_kill_attribution_test_world();
+        world.actors[1].health = 1e-6;
+        world.actors[1].effect.power[Element::Poison as usize] = 1.0;
+        world.actors[1].effect.sources[Element::Poison as usize] = Some(ActorId(3));
+        world.actors[1].effect.power[Element::Water as usize] = 1.0;
+        world.actors[1].effect.applied[Element::Water as usize] = world.time;
+        world.actors[1].effect.sources[Element::Water as usize] = Some(ActorId(1));
+        let mut engine = Engine::default();
+        engine.update(0.01, &mut world, &mut rng);
+        assert!(engine.world_events().contains(&WorldEvent::Death {
+            actor_id: ActorId(2),
+            player_id: PlayerId(12),
+            killer: Some(PlayerId(13)),
+        }));
+        assert_eq!(world.players[0].kills, 0);
+        assert_eq!(world.players[0].assists, 0);
+        assert_eq!(world.players[2].kills, 1);
+    }
+
+    #[test]
+    fn quake_should_not_damage_actor_with_earth_aura() {
+        let mut world =
+            make_area_of_effect_test_world(Element::Earth, &[Vec2f::ZERO, Vec2f::only_x(3.0)]);
+        world.actors[1].aura.elements[Element::Earth as usize] = true;
+        start_area_of_effect_magick(0, &mut world, &WorldIndex::default(), &mut Vec::new());
+        assert_eq!(world.actors[1].health, 1.0);
+        assert!(world.actors[1].velocity_z > 0.0);
+    }
+
+    #[test]
+    fn quake_should_apply_friendly_fire_factor_to_teammates() {
+        let mut world = make_area_of_effect_test_world(
+            Element::Earth,
+            &[Vec2f::ZERO, Vec2f::only_x(3.0), Vec2f::only_x(-3.0)],
+        );
+        world.actors[0].team = Some(TeamId(0));
+        world.actors[1].team = Some(TeamId(0));
+        world.actors[2].team = Some(TeamId(1));
+        start_area_of_effect_magick(0, &mut world, &WorldIndex::default(), &mut Vec::new());
+        assert_eq!(world.actors[1].health, 1.0);
+        assert!(world.actors[2].health < 1.0);
+    }
+
+    #[test]
+    fn handle_events_should_not_apply_friendly_fire_factor_to_healing() {
+        let mut world =
+            make_area_of_effect_test_world(Element::Earth, &[Vec2f::ZERO, Vec2f::only_x(3.0)]);
+        world.actors[0].team = Some(TeamId(0));
+        world.actors[1].team = Some(TeamId(0));
+        world.actors[1].health = 0.5;
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut elements = [0.0; 11];
+        elements[Element::Fire as usize] = 0.2;
+        elements[Element::Life as usize] = -0.1;
+        let events = [EngineEvent::MagicalDamage {
+            target: Index::Actor(1),
+            damage: 0.1,
+            elements,
+            sources: [Some(world.actors[0].id); 11],
+        }];
+        handle_events(&events, &mut world, &index, &mut Vec::new());
+        assert_eq!(world.actors[1].health, 0.6);
+    }
+
+    #[test]
+    fn ice_spikes_should_be_shot_in_all_directions() {
+        let mut world = make_area_of_effect_test_world(Element::Ice, &[Vec2f::ZERO]);
+        start_area_of_effect_magick(0, &mut world, &WorldIndex::default(), &mut Vec::new());
+        assert_eq!(
+            world.projectiles.len(),
+            world.settings.ice_spikes_number as usize
+        );
+        for projectile in world.projectiles.iter() {
+            assert_eq!(
+                projectile.deadline,
+                world.time + world.settings.ice_spike_duration
+            );
+            assert!(projectile.dynamic_force.dot(projectile.position) > 0.0);
+        }
+        let sum = world
+            .projectiles
+            .iter()
+            .fold(Vec2f::ZERO, |r, v| r + v.dynamic_force);
+        assert!(sum.norm() < 1e-6, "{:?}", sum);
+    }
+
+    #[test]
+    fn arcane_shield_should_place_mines_in_front_of_actor() {
+        let mut world = make_area_of_effect_test_world(Element::Shield, &[Vec2f::ZERO]);
+        world.actors[0].spell_elements.push(Element::Arcane);
+        start_directed_magick(0, &mut world);
+        assert_eq!(world.mines.len(), 5);
+        for mine in world.mines.iter() {
+            assert!(mine.position.x > 0.0);
+            assert!(!mine.detonated);
+            assert_eq!(mine.magick.power[Element::Shield as usize], 0.0);
+            assert!(mine.magick.power[Element::Arcane as usize] > 0.0);
+        }
+    }
+
+    #[test]
+    fn lightning_shield_should_strike_actors_within_shield_angle() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_area_of_effect_test_world(
+            Element::Shield,
+            &[Vec2f::ZERO, Vec2f::only_x(4.0), Vec2f::only_x(-4.0)],
+        );
+        world.actors[0].spell_elements.push(Element::Lightning);
+        start_directed_magick(0, &mut world);
+        assert!(world.temp_areas.is_empty());
+        assert_eq!(world.lightning_bolts.len(), 1);
+        assert_eq!(
+            world.lightning_bolts[0].spread,
+            Some(std::f64::consts::FRAC_PI_2)
+        );
+        assert_eq!(
+            world.lightning_bolts[0].magick.power[Element::Shield as usize],
+            0.0
+        );
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        let mut collider = LightningCollider::default();
+        collider.update(&mut world, &shape_cache, &index);
+        assert!(world.actors[1].effect.power[Element::Lightning as usize] > 0.0);
+        assert_eq!(world.actors[2].effect.power, [0.0; 11]);
+    }
+
+    #[test]
+    fn mine_should_detonate_when_actor_enters_it() {
+        let shape_cache = ShapeCache::default();
+        let mut world = make_area_of_effect_test_world(
+            Element::Shield,
+            &[Vec2f::ZERO, Vec2f::only_x(6.0), Vec2f::only_x(20.0)],
+        );
+        world.actors[0].spell_elements.push(Element::Life);
+        start_directed_magick(0, &mut world);
+        detonate_mines(&mut world, &shape_cache);
+        assert_eq!(world.mines.iter().filter(|v| v.detonated).count(), 1);
+        assert!(world.actors[1].effect.power[Element::Life as usize] > 0.0);
+        assert_eq!(world.actors[2].effect.power, [0.0; 11]);
+        let deadline = world.mines.iter().find(|v| v.detonated).unwrap().deadline;
+        detonate_mines(&mut world, &shape_cache);
+        assert_eq!(
+            world.mines.iter().find(|v| v.detonated).unwrap().deadline,
+            deadline
+        );
+    }
+
+    #[test]
+    fn mine_should_not_detonate_when_caster_stands_on_it() {
+        let shape_cache = ShapeCache::default();
+        let mut world =
+            make_area_of_effect_test_world(Element::Shield, &[Vec2f::ZERO, Vec2f::only_x(20.0)]);
+        world.actors[0].spell_elements.push(Element::Arcane);
+        start_directed_magick(0, &mut world);
+        world.actors[0].position = world.mines[2].position;
+        detonate_mines(&mut world, &shape_cache);
+        assert!(world.mines.iter().all(|v| !v.detonated));
+        assert_eq!(world.actors[0].effect.power, [0.0; 11]);
+        world.actors[1].position = world.mines[2].position;
+        detonate_mines(&mut world, &shape_cache);
+        assert!(world.mines[2].detonated);
+    }
+
+    fn find_collisions_exhaustively<F>(
+        duration: f64,
+        world: &World,
+        shape_cache: &ShapeCache,
+        mut f: F,
+    ) where
+        F: FnMut(Index, Index, TOI),
+    {
+        let movables = (0..world.actors.len())
+            .map(Index::Actor)
+            .chain((0..world.projectiles.len()).map(Index::Projectile))
+            .collect::<Vec<_>>();
+        for index in movables.iter() {
+            for (i, v) in world.static_objects.iter().enumerate() {
+                if let Some(toi) =
+                    time_of_impact_with_movable(duration, shape_cache, v, *index, world)
+                {
+                    f(Index::StaticObject(i), *index, toi);
+                }
+            }
+            for (i, v) in world.shields.iter().enumerate() {
+                if let Some(toi) =
+                    time_of_impact_with_movable(duration, shape_cache, v, *index, world)
+                {
+                    f(Index::Shield(i), *index, toi);
+                }
+            }
+            for (i, v) in world.temp_obstacles.iter().enumerate() {
+                if let Some(toi) =
+                    time_of_impact_with_movable(duration, shape_cache, v, *index, world)
+                {
+                    f(Index::TempObstacle(i), *index, toi);
+                }
+            }
+        }
+        for i in 0..world.actors.len() {
+            for j in i + 1..world.actors.len() {
+                if let Some(toi) =
+                    time_of_impact(duration, shape_cache, &world.actors[i], &world.actors[j])
+                {
+                    f(Index::Actor(i), Index::Actor(j), toi);
+                }
+            }
+        }
+        for (i, projectile) in world.projectiles.iter().enumerate() {
+            for (j, actor) in world.actors.iter().enumerate() {
+                if let Some(toi) = time_of_impact(duration, shape_cache, projectile, actor) {
+                    f(Index::Projectile(i), Index::Actor(j), toi);
+                }
+            }
+            for (j, shield) in world.shields.iter().enumerate() {
+                if let Some(toi) = time_of_impact(duration, shape_cache, projectile, shield) {
+                    f(Index::Projectile(i), Index::Shield(j), toi);
+                }
+            }
+        }
+        for i in 0..world.projectiles.len() {
+            for j in i + 1..world.projectiles.len() {
+                if let Some(toi) = time_of_impact(
+                    duration,
+                    shape_cache,
+                    &world.projectiles[i],
+                    &world.projectiles[j],
+                ) {
+                    f(Index::Projectile(i), Index::Projectile(j), toi);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn find_collisions_should_return_same_pairs_as_exhaustive_search() {
+        use crate::generators::generate_world;
+        use rand::Rng;
+        let shape_cache = ShapeCache::default();
+        let mut broadphase = Broadphase::default();
+        for seed in 0..10 {
+            let mut rng = rand::rngs::SmallRng::seed_from_u64(seed);
+            let mut world =
+                generate_world(Rectf::new(Vec2f::both(-1e2), Vec2f::both(1e2)), &mut rng);
+            for actor in world.actors.iter_mut() {
+                actor.velocity = Vec2f::new(rng.gen_range(-20.0..20.0), rng.gen_range(-20.0..20.0));
+            }
+            for i in 0..200 {
+                world.projectiles.push(Projectile {
+                    id: ProjectileId(world.id_counter + i),
+                    actor_id: ActorId(0),
+                    body: Body {
+                        shape: Disk { radius: 0.2 },
+                        material_type: MaterialType::Ice,
+                    },
+                    position: Vec2f::new(rng.gen_range(-95.0..95.0), rng.gen_range(-95.0..95.0)),
+                    health: 1.0,
+                    magick: Magick::default(),
+                    velocity: Vec2f::new(rng.gen_range(-20.0..20.0), rng.gen_range(-20.0..20.0)),
+                    dynamic_force: Vec2f::ZERO,
+                    position_z: 1.0,
+                    velocity_z: 0.0,
+                    deadline: f64::MAX,
+                });
+            }
+            let duration = 0.5;
+            broadphase.build_obstacles(&world, &shape_cache);
+            broadphase.build_movables(duration, &world, &shape_cache);
+            let mut pairs = Vec::new();
+            find_collisions(duration, &world, &shape_cache, &broadphase, |l, r, toi| {
+                pairs.push((l, r, toi.toi))
+            });
+            let mut expected = Vec::new();
+            find_collisions_exhaustively(duration, &world, &shape_cache, |l, r, toi| {
+                expected.push((l, r, toi.toi))
+            });
+            pairs.sort_by(|l, r| l.partial_cmp(r).unwrap());
+            expected.sort_by(|l, r| l.partial_cmp(r).unwrap());
+            assert!(!expected.is_empty());
+            assert_eq!(pairs, expected);
+        }
+    }
 }
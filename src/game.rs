@@ -10,31 +10,35 @@ use egui::{Color32, CtxRef};
 use macroquad::prelude::{
     clear_background, draw_line, draw_rectangle, draw_rectangle_lines, draw_text_ex,
     get_internal_gl, gl_use_default_material, gl_use_material, is_key_down, is_key_pressed,
-    is_mouse_button_down, load_material, load_string, load_ttf_font, measure_text,
-    mouse_position_local, mouse_wheel, next_frame, screen_height, screen_width, set_camera,
-    set_default_camera, vec2, vec4, Camera2D, Color, DrawMode, Font, KeyCode, Mat4, Material,
-    MaterialParams, MouseButton, PipelineParams, Quat, TextParams, UniformType, Vec3, Vertex,
-    BLACK, WHITE,
+    is_mouse_button_down, is_mouse_button_pressed, load_material, load_string, load_ttf_font,
+    measure_text, mouse_position_local, mouse_wheel, next_frame, screen_height, screen_width,
+    set_camera, set_default_camera, vec2, vec4, Camera2D, Color, DrawMode, Font, KeyCode, Mat4,
+    Material, MaterialParams, MouseButton, PipelineParams, Quat, TextParams, UniformType, Vec3,
+    Vertex, BLACK, BLUE, GREEN, RED, WHITE, YELLOW,
 };
 use rand::prelude::SmallRng;
 use rand::Rng;
 use yata::methods::{StDev, SMA};
 use yata::prelude::Method;
 
+use crate::bot::{get_bot_name, update_bots, Bot};
 use crate::client::{Client, GameClientSettings, UdpClientSettings};
-use crate::control::{apply_actor_action, apply_cast_action};
-use crate::engine::{get_next_id, normalize_angle, Engine};
+use crate::encryption::EncryptionKey;
+use crate::engine::{get_next_id, normalize_angle, EmittedLightningArc, Engine};
 use crate::generators::{generate_world, make_rng};
+use crate::interpolation::Interpolation;
 use crate::meters::{measure, DurationMovingAverage, FpsMovingAverage};
+use crate::prediction::Prediction;
 use crate::protocol::{
-    apply_world_update, is_valid_player_name, ActorAction, CastAction, GameUpdate, PlayerControl,
-    WorldUpdate, MAX_PLAYER_NAME_LEN, MIN_PLAYER_NAME_LEN,
+    apply_world_update, format_kill_feed_message, is_valid_player_name, ActorAction, Capabilities,
+    CastAction, ChatMessage, GameUpdate, PlayerControl, RoundResult, WorldUpdate,
+    MAX_CHAT_MESSAGE_LEN, MAX_PLAYER_NAME_LEN, MIN_PLAYER_NAME_LEN,
 };
 use crate::rect::Rectf;
 use crate::vec2::Vec2f;
 use crate::world::{
     load_world, Actor, ActorId, Aura, DelayedMagickStatus, Disk, Element, MaterialType, Player,
-    PlayerId, Rectangle, RingSector, StaticAreaShape, StaticShape, World,
+    PlayerId, Rectangle, RingSector, StaticAreaShape, StaticShape, TeamId, World,
 };
 use macroquad::prelude::miniquad::{BlendFactor, BlendState, BlendValue, Equation};
 
@@ -52,6 +56,11 @@ const HUD_FONT_SIZE: u16 = 18;
 const MESSAGE_FONT_SIZE: u16 = 32;
 const DEBUG_INFO_FONT_SIZE: u16 = 24;
 const DEBUG_INFO_FONT_SCALE: f32 = 0.01;
+const TEAM_COLORS: [Color; 4] = [RED, BLUE, GREEN, YELLOW];
+const MAX_CHAT_HISTORY: usize = 50;
+const CHAT_MESSAGE_SHOW_DURATION: Duration = Duration::from_secs(10);
+const MAX_KILL_FEED_SIZE: usize = 5;
+const KILL_FEED_SHOW_DURATION: f64 = 5.0;
 
 #[derive(Clap, Debug)]
 pub struct GameSettings {
@@ -63,16 +72,30 @@ pub struct GameSettings {
     pub default_server_port: u16,
     #[clap(long, default_value = "Player")]
     pub default_player_name: String,
+    #[clap(long)]
+    pub default_team: Option<u8>,
     #[clap(long, default_value = "3")]
     pub connect_timeout: f64,
     #[clap(long, default_value = "3")]
     pub read_timeout: f64,
+    #[clap(long, default_value = "1")]
+    pub reconnect_timeout: f64,
     #[clap(long, default_value = "0.25")]
     pub retry_period: f64,
     #[clap(long, default_value = "15")]
     pub max_world_frame_delay: u64,
     #[clap(long, default_value = "0")]
     pub world_updates_delay: usize,
+    #[clap(long, default_value = "0.1")]
+    pub interpolation_delay: f64,
+    #[clap(long, default_value = "0.25")]
+    pub max_extrapolation: f64,
+    #[clap(long, default_value = "0")]
+    pub bots: usize,
+    #[clap(long)]
+    pub quantized_world_updates: bool,
+    #[clap(long)]
+    pub encryption_key: Option<EncryptionKey>,
 }
 
 struct GameState {
@@ -91,14 +114,21 @@ struct GameState {
     server_address: String,
     server_port: u16,
     player_name: String,
+    team: Option<TeamId>,
     connect_timeout: Duration,
     read_timeout: Duration,
+    reconnect_timeout: Duration,
     retry_period: Duration,
     client_dropper: Dropper<Client>,
     debug_hud_font: Font,
     name_font: Font,
     max_world_frame_delay: u64,
     world_updates_delay: usize,
+    interpolation_delay: f64,
+    max_extrapolation: f64,
+    bots: usize,
+    quantized_world_updates: bool,
+    encryption_key: Option<EncryptionKey>,
     control_hud_font: Font,
     show_control_hud: bool,
     message_font: Font,
@@ -141,13 +171,18 @@ struct Scene {
     player_id: Option<PlayerId>,
     actor_id: Option<ActorId>,
     actor_index: Option<usize>,
+    spectated_actor_id: Option<ActorId>,
     camera_zoom: f64,
     camera_target: Vec2f,
     pointer: Vec2f,
+    round_result: Option<RoundResult>,
+    bots: Vec<Bot>,
+    kill_feed: VecDeque<(f64, String)>,
 }
 
 struct Multiplayer {
     client: AsyncDrop<Client>,
+    spectator: bool,
     scene: Scene,
     local_world_frame: u64,
     local_world_time: f64,
@@ -160,6 +195,11 @@ struct Multiplayer {
     ack_cast_action_world_frame: u64,
     actor_action: ActorAction,
     delayed_cast_actions: VecDeque<CastAction>,
+    prediction: Prediction,
+    interpolation: Interpolation,
+    chat_messages: VecDeque<(Instant, ChatMessage)>,
+    chat_input: Option<String>,
+    chat_team_only: bool,
 }
 
 struct WorldView {
@@ -189,8 +229,10 @@ pub async fn run_game(settings: GameSettings) {
         server_address: settings.default_server_address,
         server_port: settings.default_server_port,
         player_name: settings.default_player_name,
+        team: settings.default_team.map(TeamId),
         connect_timeout: Duration::from_secs_f64(settings.connect_timeout),
         read_timeout: Duration::from_secs_f64(settings.read_timeout),
+        reconnect_timeout: Duration::from_secs_f64(settings.reconnect_timeout),
         retry_period: Duration::from_secs_f64(settings.retry_period),
         client_dropper: {
             let (sender, receiver) = channel();
@@ -203,6 +245,11 @@ pub async fn run_game(settings: GameSettings) {
         name_font: ubuntu_mono,
         max_world_frame_delay: settings.max_world_frame_delay,
         world_updates_delay: settings.world_updates_delay,
+        interpolation_delay: settings.interpolation_delay,
+        max_extrapolation: settings.max_extrapolation,
+        bots: settings.bots,
+        quantized_world_updates: settings.quantized_world_updates,
+        encryption_key: settings.encryption_key,
         control_hud_font: ubuntu_mono,
         show_control_hud: true,
         message_font: ubuntu_mono,
@@ -440,18 +487,30 @@ fn prepare_frame(game_state: &mut GameState, frame_type: &mut FrameType) {
 }
 
 fn handle_input(game_state: &mut GameState, frame_type: &mut FrameType) {
+    if let FrameType::Multiplayer(v) = frame_type {
+        if matches!(game_state.menu, Menu::None) && handle_chat_input(v) {
+            return;
+        }
+    }
     match frame_type {
         FrameType::SinglePlayer(v) => {
             let mut actor_action = ActorAction::default();
             let mut cast_actions = Vec::new();
             handle_scene_input(game_state, v, &mut actor_action, |v| cast_actions.push(v));
             if let Some(actor_index) = v.actor_index {
-                apply_actor_action(actor_action, actor_index, &mut v.world);
+                v.engine
+                    .apply_actor_action(actor_action, actor_index, &mut v.world);
                 for cast_action in cast_actions {
-                    apply_cast_action(cast_action, actor_index, &mut v.world);
+                    v.engine
+                        .apply_cast_action(cast_action, actor_index, &mut v.world);
                 }
             }
         }
+        FrameType::Multiplayer(v) if v.spectator => {
+            if matches!(game_state.menu, Menu::None) {
+                handle_spectator_input(&mut v.scene);
+            }
+        }
         FrameType::Multiplayer(v) => {
             let scene = &mut v.scene;
             let actor_action = &mut v.actor_action;
@@ -528,8 +587,35 @@ where
     for_each_cast_action(apply_cast_action);
 }
 
+fn handle_chat_input(data: &mut Multiplayer) -> bool {
+    if data.chat_input.is_some() {
+        if is_key_pressed(KeyCode::Escape) {
+            data.chat_input = None;
+        }
+        data.actor_action.moving = false;
+        return true;
+    }
+    if is_key_pressed(KeyCode::Enter) {
+        data.chat_input = Some(String::new());
+        return true;
+    }
+    false
+}
+
+fn handle_spectator_input(scene: &mut Scene) {
+    scene.camera_zoom *= 1.0 + mouse_wheel().1 as f64 * 0.1;
+    if is_mouse_button_pressed(MouseButton::Left) {
+        cycle_spectated_actor(scene, 1);
+    }
+    if is_mouse_button_pressed(MouseButton::Right) {
+        cycle_spectated_actor(scene, -1);
+    }
+}
+
 fn update_ui(game_state: &mut GameState, frame_type: &mut FrameType) {
-    if matches!(game_state.menu, Menu::None) {
+    if matches!(game_state.menu, Menu::None)
+        && !matches!(frame_type, FrameType::Multiplayer(v) if is_chat_visible(v))
+    {
         return;
     }
     egui_macroquad::ui(|ctx| {
@@ -539,7 +625,11 @@ fn update_ui(game_state: &mut GameState, frame_type: &mut FrameType) {
             Color32::from_rgba_premultiplied(bg_fill.r(), bg_fill.g(), bg_fill.b(), 127);
         ctx.set_visuals(visuals);
         match &game_state.menu {
-            Menu::None => (),
+            Menu::None => {
+                if let FrameType::Multiplayer(v) = frame_type {
+                    chat_window(ctx, v);
+                }
+            }
             Menu::Main => main_menu(ctx, game_state, frame_type),
             Menu::Multiplayer => multiplayer_menu(ctx, game_state, frame_type),
             Menu::Joining => joining_menu(ctx, game_state, frame_type),
@@ -551,6 +641,91 @@ fn update_ui(game_state: &mut GameState, frame_type: &mut FrameType) {
     game_state.draw_ui = true;
 }
 
+fn is_chat_visible(data: &Multiplayer) -> bool {
+    data.chat_input.is_some()
+        || matches!(data.chat_messages.back(), Some((time, _)) if time.elapsed() < CHAT_MESSAGE_SHOW_DURATION)
+}
+
+fn chat_window(ctx: &CtxRef, data: &mut Multiplayer) {
+    egui::Window::new("Chat")
+        .anchor(
+            egui::Align2::RIGHT_TOP,
+            [-HUD_MARGIN as f32, HUD_MARGIN as f32],
+        )
+        .title_bar(false)
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            let typing = data.chat_input.is_some();
+            for (time, message) in data.chat_messages.iter() {
+                if !typing && time.elapsed() >= CHAT_MESSAGE_SHOW_DURATION {
+                    continue;
+                }
+                let text = format_chat_message(message, &data.scene.world.players);
+                if message.sender.is_none() {
+                    ui.colored_label(Color32::from_rgb(255, 255, 0), text);
+                } else {
+                    ui.label(text);
+                }
+            }
+            let mut submit = false;
+            if let Some(text) = data.chat_input.as_mut() {
+                ui.checkbox(&mut data.chat_team_only, "Team only");
+                let response = ui.text_edit_singleline(text);
+                submit = response.lost_focus() && ui.input().key_pressed(egui::Key::Enter);
+                response.request_focus();
+            }
+            if submit {
+                if let Some(text) = data.chat_input.take() {
+                    send_chat_message(text, data);
+                }
+            }
+        });
+}
+
+fn send_chat_message(text: String, data: &mut Multiplayer) {
+    let mut text = String::from(text.trim());
+    while text.len() > MAX_CHAT_MESSAGE_LEN {
+        text.pop();
+    }
+    if text.is_empty() {
+        return;
+    }
+    if let Err(e) = data.client.send_chat_message(text, data.chat_team_only) {
+        add_chat_message(
+            ChatMessage {
+                sender: None,
+                team: None,
+                text: e,
+            },
+            data,
+        );
+    }
+}
+
+fn add_chat_message(message: ChatMessage, data: &mut Multiplayer) {
+    data.chat_messages.push_back((Instant::now(), message));
+    while data.chat_messages.len() > MAX_CHAT_HISTORY {
+        data.chat_messages.pop_front();
+    }
+}
+
+fn format_chat_message(message: &ChatMessage, players: &[Player]) -> String {
+    let sender_id = match message.sender {
+        Some(v) => v,
+        None => return format!("[server] {}", message.text),
+    };
+    let scope = if message.team.is_some() {
+        "[team] "
+    } else {
+        ""
+    };
+    match players.iter().find(|v| v.id == sender_id) {
+        Some(player) => format!("{}{}: {}", scope, player.name, message.text),
+        None => format!("{}player {}: {}", scope, sender_id.0, message.text),
+    }
+}
+
 fn main_menu(ctx: &CtxRef, game_state: &mut GameState, frame_type: &mut FrameType) {
     egui::CentralPanel::default().show(ctx, |ui| {
         ui.vertical_centered(|ui| {
@@ -601,12 +776,21 @@ fn multiplayer_menu(ctx: &CtxRef, game_state: &mut GameState, frame_type: &mut F
             if !valid_player_name {
                 ui.label(format!("Player name should contain only alphabetic characters, be at least {} and not longer than {} symbols", MIN_PLAYER_NAME_LEN, MAX_PLAYER_NAME_LEN));
             }
+            ui.label("Team:");
+            ui.horizontal(|ui| {
+                ui.radio_value(&mut game_state.team, None, "auto");
+                for team in 0..TEAM_COLORS.len() as u8 {
+                    ui.radio_value(&mut game_state.team, Some(TeamId(team)), format!("{}", team + 1));
+                }
+            });
             ui.label("Server address:");
             ui.text_edit_singleline(&mut game_state.server_address);
             if server_address.is_none() {
                 ui.label("Server address should be IPv4 or IPv6 address with or without a port");
             }
-            if ui.button("Join").clicked() {
+            let join = ui.button("Join").clicked();
+            let spectate = ui.button("Spectate").clicked();
+            if join || spectate {
                 if let (true, Some(server_address)) = (valid_player_name, server_address) {
                     *frame_type = FrameType::Multiplayer(Box::new(Multiplayer {
                         client: AsyncDrop::new(
@@ -617,13 +801,22 @@ fn multiplayer_menu(ctx: &CtxRef, game_state: &mut GameState, frame_type: &mut F
                                     connect_timeout: game_state.connect_timeout,
                                     retry_period: game_state.retry_period,
                                     player_name: game_state.player_name.clone(),
+                                    team: game_state.team,
+                                    spectator: spectate,
+                                    capabilities: Capabilities {
+                                        quantized_world_updates: game_state
+                                            .quantized_world_updates,
+                                    },
                                 },
                                 UdpClientSettings {
                                     id: game_state.next_client_id,
                                     server_address,
                                     read_timeout: game_state.read_timeout,
+                                    reconnect_timeout: game_state.reconnect_timeout,
+                                    encryption_key: game_state.encryption_key.clone(),
                                 },
                             )),
+                        spectator: spectate,
                         scene: make_empty_scene(),
                         local_world_frame: 0,
                         local_world_time: 0.0,
@@ -636,10 +829,18 @@ fn multiplayer_menu(ctx: &CtxRef, game_state: &mut GameState, frame_type: &mut F
                         ack_cast_action_world_frame: 0,
                         actor_action: ActorAction::default(),
                         delayed_cast_actions: VecDeque::new(),
+                        prediction: Prediction::default(),
+                        interpolation: Interpolation::new(
+                            game_state.interpolation_delay,
+                            game_state.max_extrapolation,
+                        ),
+                        chat_messages: VecDeque::new(),
+                        chat_input: None,
+                        chat_team_only: false,
                     }));
                     game_state.next_client_id += 1;
                     game_state.menu = Menu::Joining;
-                    game_state.show_control_hud = true;
+                    game_state.show_control_hud = !spectate;
                 }
             }
             if ui.button("Back").clicked() {
@@ -677,12 +878,15 @@ fn single_player_menu(ctx: &CtxRef, game_state: &mut GameState, frame_type: &mut
         ui.vertical_centered(|ui| {
             ui.heading("Single player");
             ui.separator();
+            ui.add(egui::Slider::new(&mut game_state.bots, 0..=10).text("Bots"));
             if ui.button("Generate map").clicked() {
-                *frame_type =
-                    FrameType::SinglePlayer(Box::new(make_single_player_scene(generate_world(
+                *frame_type = FrameType::SinglePlayer(Box::new(make_single_player_scene(
+                    generate_world(
                         Rectf::new(Vec2f::both(-1e2), Vec2f::both(1e2)),
                         &mut game_state.rng,
-                    ))));
+                    ),
+                    game_state.bots,
+                )));
                 game_state.menu = Menu::None;
                 game_state.show_control_hud = true;
             }
@@ -696,8 +900,9 @@ fn single_player_menu(ctx: &CtxRef, game_state: &mut GameState, frame_type: &mut
                 {
                     match load_world(world_path) {
                         Ok(v) => {
-                            *frame_type =
-                                FrameType::SinglePlayer(Box::new(make_single_player_scene(v)));
+                            *frame_type = FrameType::SinglePlayer(Box::new(
+                                make_single_player_scene(v, game_state.bots),
+                            ));
                             game_state.menu = Menu::None;
                             game_state.show_control_hud = true;
                         }
@@ -786,29 +991,46 @@ fn draw(game_state: &GameState, frame_type: &mut FrameType) {
     }
 }
 
-fn make_single_player_scene(mut world: World) -> Scene {
-    let player_id = PlayerId(get_next_id(&mut world.id_counter));
-    world.players.push(Player {
-        id: player_id,
-        active: true,
-        name: "Player".to_string(),
-        actor_id: None,
-        spawn_time: world.time,
-        deaths: 0,
-    });
+fn make_single_player_scene(mut world: World, bots_number: usize) -> Scene {
+    let player_id = add_single_player(String::from("Player"), &mut world);
+    let bots = (0..bots_number)
+        .map(|i| Bot::new(add_single_player(get_bot_name(i), &mut world)))
+        .collect();
     Scene {
         time_step: 1.0 / 60.0,
         engine: Engine::default(),
         player_id: Some(player_id),
         actor_id: None,
         actor_index: None,
+        spectated_actor_id: None,
         camera_zoom: 0.05,
         camera_target: Vec2f::ZERO,
         pointer: Vec2f::ZERO,
+        round_result: None,
+        bots,
+        kill_feed: VecDeque::new(),
         world: Box::new(world),
     }
 }
 
+fn add_single_player(name: String, world: &mut World) -> PlayerId {
+    let player_id = PlayerId(get_next_id(&mut world.id_counter));
+    world.players.push(Player {
+        id: player_id,
+        active: true,
+        name,
+        team: None,
+        actor_id: None,
+        spawn_time: world.time,
+        deaths: 0,
+        kills: 0,
+        assists: 0,
+        damage_dealt: 0.0,
+        damage_healed: 0.0,
+    });
+    player_id
+}
+
 fn make_world_view_scene(world: World) -> Scene {
     Scene {
         time_step: 1.0 / 60.0,
@@ -816,9 +1038,13 @@ fn make_world_view_scene(world: World) -> Scene {
         player_id: None,
         actor_id: None,
         actor_index: None,
+        spectated_actor_id: None,
         camera_zoom: 0.05,
         camera_target: Vec2f::ZERO,
         pointer: Vec2f::ZERO,
+        round_result: None,
+        bots: Vec::new(),
+        kill_feed: VecDeque::new(),
         world: Box::new(world),
     }
 }
@@ -830,22 +1056,43 @@ fn make_empty_scene() -> Scene {
         player_id: None,
         actor_id: None,
         actor_index: None,
+        spectated_actor_id: None,
         camera_zoom: 0.05,
         camera_target: Vec2f::ZERO,
         pointer: Vec2f::ZERO,
+        round_result: None,
+        bots: Vec::new(),
+        kill_feed: VecDeque::new(),
         world: Box::new(World::default()),
     }
 }
 
 fn update_single_player<R: Rng>(scene: &mut Scene, rng: &mut R) {
+    update_bots(&mut scene.bots, &mut scene.engine, &mut scene.world, rng);
     scene.engine.update(scene.time_step, &mut scene.world, rng);
+    update_kill_feed(scene);
     update_scene_actor_index(scene);
 }
 
+fn update_kill_feed(scene: &mut Scene) {
+    for event in scene.engine.world_events().iter() {
+        if let Some(text) = format_kill_feed_message(event, &scene.world.players) {
+            scene.kill_feed.push_back((scene.world.time, text));
+        }
+    }
+    while scene.kill_feed.len() > MAX_KILL_FEED_SIZE
+        || matches!(scene.kill_feed.front(), Some((time, _)) if *time + KILL_FEED_SHOW_DURATION < scene.world.time)
+    {
+        scene.kill_feed.pop_front();
+    }
+}
+
 fn update_multiplayer(game_state: &mut GameState, data: &mut Multiplayer) -> Option<FrameType> {
+    data.interpolation.restore(&mut data.scene.world);
+    data.prediction.restore(&mut data.scene.world);
     let world_frame = data.scene.world.frame;
     let mut apply_all_updates = false;
-    while let Some(update) = data.client.receiver().try_recv().ok() {
+    while let Ok(update) = data.client.receiver().try_recv() {
         match update {
             GameUpdate::GameOver(message) => {
                 data.scene.player_id = None;
@@ -866,7 +1113,11 @@ fn update_multiplayer(game_state: &mut GameState, data: &mut Multiplayer) -> Opt
                     game_state.menu = Menu::None;
                 }
                 data.world_updates.clear();
+                data.prediction
+                    .ack_actor_action(world.frame, ack_actor_action_world_frame);
                 data.scene.world = world;
+                data.interpolation.clear();
+                data.interpolation.add_sample(&data.scene.world);
                 update_scene_actor_index(&mut data.scene);
                 ack_actor_action(
                     ack_actor_action_world_frame,
@@ -881,6 +1132,8 @@ fn update_multiplayer(game_state: &mut GameState, data: &mut Multiplayer) -> Opt
             } => {
                 data.world_frame_delay
                     .next((world_update.after_frame - world_update.before_frame) as f64);
+                data.prediction
+                    .ack_actor_action(world_update.after_frame, ack_actor_action_world_frame);
                 data.world_updates.push_back(world_update);
                 ack_actor_action(
                     ack_actor_action_world_frame,
@@ -888,6 +1141,10 @@ fn update_multiplayer(game_state: &mut GameState, data: &mut Multiplayer) -> Opt
                     data,
                 );
             }
+            GameUpdate::RoundResult(v) => {
+                data.scene.round_result = Some(v);
+            }
+            GameUpdate::ChatMessage(v) => add_chat_message(v, data),
         }
     }
     if !data.client.is_done() && !data.client.is_running() {
@@ -923,12 +1180,16 @@ fn update_multiplayer(game_state: &mut GameState, data: &mut Multiplayer) -> Opt
             }
             if let Some(world_update) = data.world_updates.pop_front() {
                 apply_world_update(*world_update, &mut data.scene.world);
+                data.interpolation.add_sample(&data.scene.world);
                 update_scene_actor_index(&mut data.scene);
             } else {
                 break;
             }
         }
     }
+    if data.spectator {
+        cycle_spectated_actor(&mut data.scene, 0);
+    }
     data.client
         .sender()
         .send(PlayerControl {
@@ -937,6 +1198,8 @@ fn update_multiplayer(game_state: &mut GameState, data: &mut Multiplayer) -> Opt
             ack_world_frame: data.scene.world.frame,
         })
         .ok();
+    data.prediction
+        .add_actor_action(data.scene.world.frame, data.actor_action.clone());
     let frame_diff = (data.scene.world.frame - world_frame) as f64;
     data.world_frame_diff.next(frame_diff);
     data.last_world_frame_st_dev = data.world_frame_st_dev.next(frame_diff);
@@ -945,6 +1208,16 @@ fn update_multiplayer(game_state: &mut GameState, data: &mut Multiplayer) -> Opt
     if data.local_world_time < data.scene.world.time {
         data.local_world_time = data.scene.world.time;
     }
+    data.prediction.predict(
+        data.scene.time_step,
+        data.scene.actor_index,
+        &mut data.scene.world,
+    );
+    data.interpolation.interpolate(
+        data.scene.time_step,
+        data.scene.actor_id,
+        &mut data.scene.world,
+    );
     data.scene.engine.update_visual(&mut data.scene.world);
     None
 }
@@ -964,7 +1237,7 @@ fn update_world_view(world_view: &mut WorldView) {
             .unwrap_or(true);
         if should_load {
             match load_world(&world_view.world_path) {
-                Ok(v) => world_view.scene.world = Box::new(v),
+                Ok(v) => *world_view.scene.world = v,
                 Err(e) => error!(
                     "Failed to load world from file {:?}: {}",
                     world_view.world_path, e
@@ -990,6 +1263,14 @@ fn ack_actor_action(
 fn draw_scene(game_state: &GameState, scene: &mut Scene) {
     if let Some(actor_index) = scene.actor_index {
         scene.camera_target = scene.world.actors[actor_index].position;
+    } else if let Some(position) = scene
+        .world
+        .actors
+        .iter()
+        .find(|v| Some(v.id) == scene.spectated_actor_id)
+        .map(|v| v.position)
+    {
+        scene.camera_target = position;
     }
 
     set_camera(&Camera2D {
@@ -1036,6 +1317,33 @@ fn draw_scene(game_state: &GameState, scene: &mut Scene) {
         );
     }
 
+    for v in scene.world.mines.iter() {
+        if v.detonated {
+            draw_disk_body_and_magick(
+                &Disk {
+                    radius: scene.world.settings.mine_explosion_radius,
+                },
+                v.body.material_type,
+                &v.magick.power,
+                v.position,
+                0.0,
+                game_state,
+                v.deadline - scene.world.time + v.id.0 as f64,
+            );
+        } else {
+            draw_ring_sector_body(
+                &RingSector {
+                    min_radius: v.body.shape.radius - scene.world.settings.border_width,
+                    max_radius: v.body.shape.radius + scene.world.settings.border_width,
+                    angle: std::f64::consts::TAU,
+                },
+                get_magick_power_color(&v.magick.power),
+                v.position,
+                0.0,
+            );
+        }
+    }
+
     for area in scene.world.bounded_areas.iter() {
         let owner = scene
             .world
@@ -1070,6 +1378,10 @@ fn draw_scene(game_state: &GameState, scene: &mut Scene) {
         );
     }
 
+    for arc in scene.engine.emitted_lightning_arcs().iter() {
+        draw_lightning_arc(arc, scene.world.time);
+    }
+
     for v in scene.world.actors.iter() {
         draw_actor_staff(game_state, v);
     }
@@ -1252,6 +1564,21 @@ fn draw_scene(game_state: &GameState, scene: &mut Scene) {
         }
     }
 
+    if let Some(round_result) = scene.round_result.as_ref() {
+        if scene.world.time < round_result.next_round_time {
+            draw_round_result_message(
+                round_result,
+                &scene.world.players,
+                scene.world.time,
+                game_state.message_font,
+            );
+        }
+    }
+
+    if !scene.kill_feed.is_empty() {
+        draw_kill_feed(&scene.kill_feed, game_state.message_font);
+    }
+
     if game_state.show_player_list {
         draw_player_list(&scene.world.players, game_state.player_list_font);
     }
@@ -1344,6 +1671,10 @@ fn draw_debug_multiplayer_text(counter: &mut usize, game_state: &GameState, data
                 data.local_world_time - data.scene.world.time
             ),
             format!("World updates buffer: {}", data.world_updates.len()),
+            format!(
+                "Interpolation delay: {:.3}",
+                data.scene.world.time - data.interpolation.render_time().unwrap_or(0.0)
+            ),
             format!(
                 "Mean world update delay: {:.3}",
                 data.world_frame_delay.get_last_value()
@@ -1358,6 +1689,10 @@ fn draw_debug_multiplayer_text(counter: &mut usize, game_state: &GameState, data
             ),
             format!("Mean input delay: {:.3}", data.input_delay.get_last_value()),
             format!("Delayed cast actions: {}", data.delayed_cast_actions.len()),
+            format!(
+                "Predicted actor actions: {}",
+                data.prediction.pending_actor_actions()
+            ),
             String::from("Actor action:"),
             format!("Moving: {}", data.actor_action.moving),
             format!(
@@ -1400,6 +1735,8 @@ fn draw_debug_scene_text(counter: &mut usize, scene: &Scene, font: Font) {
             format!("Guns: {}", scene.world.guns.len()),
             format!("Shields: {}", scene.world.shields.len()),
             format!("Temp obstacles: {}", scene.world.temp_obstacles.len()),
+            format!("Lightning bolts: {}", scene.world.lightning_bolts.len()),
+            format!("Mines: {}", scene.world.mines.len()),
         ],
     );
 }
@@ -1506,7 +1843,7 @@ fn draw_actor_staff(game_state: &GameState, actor: &Actor) {
 
 fn draw_actor(game_state: &GameState, scene: &Scene, actor: &Actor) {
     let context = unsafe { get_internal_gl() };
-    let time = scene.world.time as f64
+    let time = scene.world.time
         - actor.effect.applied.iter().sum::<f64>() / actor.effect.applied.len() as f64
         + actor.id.0 as f64;
     context
@@ -1604,6 +1941,37 @@ fn draw_disk_body_and_magick(
     context.quad_gl.pop_model_matrix();
 }
 
+fn draw_lightning_arc(arc: &EmittedLightningArc, time: f64) {
+    const SEGMENT_LENGTH: f64 = 0.5;
+    const MAX_DEVIATION: f64 = 0.2;
+    let color = get_magick_power_color(&arc.magick.power);
+    let direction = arc.target - arc.origin;
+    let normal = match direction.safe_normalized() {
+        Some(v) => Vec2f::new(-v.y, v.x),
+        None => return,
+    };
+    let segments = (direction.norm() / SEGMENT_LENGTH).ceil().max(1.0) as usize;
+    let mut begin = arc.origin;
+    for i in 1..=segments {
+        let end = if i == segments {
+            arc.target
+        } else {
+            arc.origin
+                + direction * (i as f64 / segments as f64)
+                + normal * (MAX_DEVIATION * (50.0 * time + 7.0 * i as f64).sin())
+        };
+        draw_line(
+            begin.x as f32,
+            begin.y as f32,
+            end.x as f32,
+            end.y as f32,
+            0.05,
+            color,
+        );
+        begin = end;
+    }
+}
+
 fn draw_ring_sector_body_and_magick<T>(
     body: &RingSector,
     power: &[T; 11],
@@ -1887,6 +2255,7 @@ fn draw_control_hud(spell_elements: &[Element], font: Font, element_material: Ma
     const CONTROL_KEYS: &[(&str, &str, f64)] = &[
         ("L.Shift", "Area of effect", 2.0),
         ("Tab", "Player's list", 1.5),
+        ("Enter", "Chat", 1.5),
         ("F2", "Debug HUD", 1.0),
         ("F1", "Control HUD", 1.0),
         ("Esc", "Main menu", 1.0),
@@ -2216,9 +2585,29 @@ fn update_scene_actor_index(scene: &mut Scene) {
     }
 }
 
+fn cycle_spectated_actor(scene: &mut Scene, step: isize) {
+    let actors = &scene.world.actors;
+    if actors.is_empty() {
+        scene.spectated_actor_id = None;
+        return;
+    }
+    let index = match scene
+        .spectated_actor_id
+        .and_then(|id| actors.iter().position(|v| v.id == id))
+    {
+        Some(v) => (v as isize + step).rem_euclid(actors.len() as isize) as usize,
+        None => 0,
+    };
+    scene.spectated_actor_id = Some(actors[index].id);
+}
+
 fn draw_spawn_message(time_left: f64, font: Font) {
     set_default_camera();
-    let text = format!("Spawn in {}s", time_left.ceil());
+    let text = if time_left < f64::MAX {
+        format!("Spawn in {}s", time_left.ceil())
+    } else {
+        String::from("Wait for the next round")
+    };
     let font_size = scaled_u16(MESSAGE_FONT_SIZE);
     let text_dimensions = measure_text(&text, Some(font), font_size, 1.0);
     draw_text_ex(
@@ -2235,19 +2624,87 @@ fn draw_spawn_message(time_left: f64, font: Font) {
     );
 }
 
+fn draw_round_result_message(
+    round_result: &RoundResult,
+    players: &[Player],
+    time: f64,
+    font: Font,
+) {
+    set_default_camera();
+    let winner = round_result
+        .winner
+        .and_then(|player_id| players.iter().find(|v| v.id == player_id));
+    let text = if let Some(winner) = winner {
+        format!(
+            "{} wins round {}, next round in {}s",
+            winner.name,
+            round_result.round,
+            (round_result.next_round_time - time).ceil()
+        )
+    } else {
+        format!(
+            "Round {} is a draw, next round in {}s",
+            round_result.round,
+            (round_result.next_round_time - time).ceil()
+        )
+    };
+    let font_size = scaled_u16(MESSAGE_FONT_SIZE);
+    let text_dimensions = measure_text(&text, Some(font), font_size, 1.0);
+    draw_text_ex(
+        &text,
+        (screen_width() - text_dimensions.width) / 2.0,
+        screen_height() / 3.0,
+        TextParams {
+            font,
+            font_size,
+            font_scale: 1.0,
+            color: WHITE,
+            font_scale_aspect: 1.0,
+        },
+    );
+}
+
+fn draw_kill_feed(kill_feed: &VecDeque<(f64, String)>, font: Font) {
+    set_default_camera();
+    let font_size = scaled_u16(HUD_FONT_SIZE);
+    for (i, (_, text)) in kill_feed.iter().enumerate() {
+        let text_dimensions = measure_text(text, Some(font), font_size, 1.0);
+        draw_text_ex(
+            text,
+            screen_width() - text_dimensions.width - HUD_MARGIN as f32,
+            HUD_MARGIN as f32 + (i + 1) as f32 * text_dimensions.height * 1.5,
+            TextParams {
+                font,
+                font_size,
+                font_scale: 1.0,
+                color: WHITE,
+                font_scale_aspect: 1.0,
+            },
+        );
+    }
+}
+
 fn draw_player_list(players: &[Player], font: Font) {
     const MAX_ROW_HEIGHT: f32 = 48.0;
     const FONT_SCALE: f32 = 1.0;
-    const NAME: &str = "name";
-    const DEATHS: &str = "deaths";
+    const COLUMNS: [&str; 6] = ["name", "kills", "assists", "deaths", "damage", "healed"];
     set_default_camera();
     let mut order: Vec<usize> = (0..players.len()).collect();
-    order.sort_by_key(|v| (players[*v].deaths, &players[*v].name));
+    order.sort_by(|l, r| {
+        let (l, r) = (&players[*l], &players[*r]);
+        l.team
+            .map(|v| v.0)
+            .cmp(&r.team.map(|v| v.0))
+            .then(r.kills.cmp(&l.kills))
+            .then(l.deaths.cmp(&r.deaths))
+            .then(l.name.cmp(&r.name))
+    });
     let x = screen_width() / 4.0;
     let y = screen_height() / 4.0;
     let width = screen_width() / 2.0;
     let height = screen_height() / 2.0;
-    let row_size = (height / players.len() as f32).min(MAX_ROW_HEIGHT);
+    let column_width = width / COLUMNS.len() as f32;
+    let row_size = (height / (players.len() + 1) as f32).min(MAX_ROW_HEIGHT);
     let font_size = (row_size * 2.0 / 3.0).round() as u16;
     let text_params = TextParams {
         font,
@@ -2256,54 +2713,50 @@ fn draw_player_list(players: &[Player], font: Font) {
         color: WHITE,
         font_scale_aspect: 1.0,
     };
+    let draw_cell = |text: &str, column: usize, row: usize, color: Color| {
+        draw_text_ex(
+            text,
+            x + column as f32 * column_width
+                + (column_width - measure_text(text, Some(font), font_size, 1.0).width) / 2.0,
+            y + (row + 1) as f32 * row_size,
+            TextParams {
+                color,
+                ..text_params
+            },
+        );
+    };
     draw_rectangle(x, y, width, height, Color::new(0.0, 0.0, 0.0, 0.25));
-    draw_text_ex(
-        NAME,
-        x + (width / 2.0 - measure_text(NAME, Some(font), font_size, 1.0).width) / 2.0,
-        y + row_size,
-        text_params,
-    );
-    draw_text_ex(
-        DEATHS,
-        x + width / 2.0
-            + (width / 2.0 - measure_text(DEATHS, Some(font), font_size, 1.0).width) / 2.0,
-        y + row_size,
-        text_params,
-    );
+    for (column, name) in COLUMNS.iter().enumerate() {
+        draw_cell(name, column, 0, WHITE);
+    }
     let line_y = y + 1.25 * row_size;
     draw_line(
         x + row_size,
         line_y,
-        x + width / 2.0 - row_size,
-        line_y,
-        2.0,
-        WHITE,
-    );
-    draw_line(
-        x + width / 2.0 + row_size,
-        line_y,
         x + width - row_size,
         line_y,
         2.0,
         WHITE,
     );
-    for i in order {
-        draw_text_ex(
-            &players[i].name,
-            x + (width / 2.0 - measure_text(&players[i].name, Some(font), font_size, 1.0).width)
-                / 2.0,
-            y + (i + 2) as f32 * row_size,
-            text_params,
-        );
-        let deaths_text = format!("{}", players[i].deaths);
-        draw_text_ex(
-            &deaths_text,
-            x + width / 2.0
-                + (width / 2.0 - measure_text(&deaths_text, Some(font), font_size, 1.0).width)
-                    / 2.0,
-            y + (i + 2) as f32 * row_size,
-            text_params,
-        );
+    for (row, i) in order.into_iter().enumerate() {
+        let player = &players[i];
+        let cells = [
+            player.name.clone(),
+            format!("{}", player.kills),
+            format!("{}", player.assists),
+            format!("{}", player.deaths),
+            format!("{}", (player.damage_dealt * 100.0).round()),
+            format!("{}", (player.damage_healed * 100.0).round()),
+        ];
+        let name_color = player.team.map(get_team_color).unwrap_or(WHITE);
+        for (column, text) in cells.iter().enumerate() {
+            draw_cell(
+                text,
+                column,
+                row + 1,
+                if column == 0 { name_color } else { WHITE },
+            );
+        }
     }
 }
 
@@ -2334,6 +2787,15 @@ fn get_world_paths() -> Vec<PathBuf> {
 
 fn get_actor_colors(actor: &Actor) -> (Color, Color) {
     let name_hash = get_actor_name_hash(actor);
+    let border = Color::from_rgba(
+        ((name_hash & (0xFF << 10)) >> 10) as u8,
+        ((name_hash & (0xFF << 8)) >> 8) as u8,
+        ((name_hash & (0xFF << 6)) >> 6) as u8,
+        u8::MAX,
+    );
+    if let Some(team) = actor.team {
+        return (get_team_color(team), border);
+    }
     (
         Color::from_rgba(
             ((name_hash & (0xFF << 4)) >> 4) as u8,
@@ -2341,15 +2803,14 @@ fn get_actor_colors(actor: &Actor) -> (Color, Color) {
             (name_hash & 0xFF) as u8,
             u8::MAX,
         ),
-        Color::from_rgba(
-            ((name_hash & (0xFF << 10)) >> 10) as u8,
-            ((name_hash & (0xFF << 8)) >> 8) as u8,
-            ((name_hash & (0xFF << 6)) >> 6) as u8,
-            u8::MAX,
-        ),
+        border,
     )
 }
 
+fn get_team_color(team: TeamId) -> Color {
+    TEAM_COLORS[team.0 as usize % TEAM_COLORS.len()]
+}
+
 fn get_actor_name_hash(actor: &Actor) -> u64 {
     use std::collections::hash_map::DefaultHasher;
     use std::hash::{Hash, Hasher};
@@ -0,0 +1,295 @@
+use std::str::FromStr;
+
+use crate::protocol::RoundResult;
+use crate::world::{Player, PlayerId, World};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GameModeType {
+    FreeForAll,
+    Deathmatch,
+    LastWizardStanding,
+    TimedRounds,
+}
+
+impl FromStr for GameModeType {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "free-for-all" => Ok(GameModeType::FreeForAll),
+            "deathmatch" => Ok(GameModeType::Deathmatch),
+            "last-wizard-standing" => Ok(GameModeType::LastWizardStanding),
+            "timed-rounds" => Ok(GameModeType::TimedRounds),
+            _ => Err(format!("Invalid game mode: {}", value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct GameModeSettings {
+    pub mode_type: GameModeType,
+    pub score_limit: u64,
+    pub time_limit: f64,
+    pub intermission: f64,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum GameModeEvent {
+    RoundFinished(RoundResult),
+    RoundStarted,
+}
+
+#[derive(Debug)]
+pub struct GameMode {
+    settings: GameModeSettings,
+    round: u64,
+    round_start_time: f64,
+    next_round_time: Option<f64>,
+}
+
+impl GameMode {
+    pub fn new(settings: GameModeSettings, world: &World) -> Self {
+        Self {
+            settings,
+            round: 1,
+            round_start_time: world.time,
+            next_round_time: None,
+        }
+    }
+
+    pub fn update(&mut self, world: &mut World) -> Option<GameModeEvent> {
+        if let Some(next_round_time) = self.next_round_time {
+            if next_round_time <= world.time {
+                self.round += 1;
+                self.round_start_time = world.time;
+                self.next_round_time = None;
+                return Some(GameModeEvent::RoundStarted);
+            }
+            return None;
+        }
+        let winner = match self.settings.mode_type {
+            GameModeType::FreeForAll => return None,
+            GameModeType::Deathmatch => {
+                if !world
+                    .players
+                    .iter()
+                    .any(|v| v.kills >= self.settings.score_limit)
+                    && !self.is_time_over(world)
+                {
+                    return None;
+                }
+                get_best_player(&world.players)
+            }
+            GameModeType::LastWizardStanding => {
+                if world.time
+                    < self.round_start_time + world.settings.initial_player_actor_spawn_delay
+                {
+                    return None;
+                }
+                for player in world.players.iter_mut() {
+                    if player.actor_id.is_none() {
+                        player.spawn_time = f64::MAX;
+                    }
+                }
+                let mut alive = world.players.iter().filter(|v| v.actor_id.is_some());
+                let last = alive.next();
+                if world.players.len() < 2 || alive.next().is_some() {
+                    return None;
+                }
+                last.map(|v| v.id)
+            }
+            GameModeType::TimedRounds => {
+                if !self.is_time_over(world) {
+                    return None;
+                }
+                get_best_player(&world.players)
+            }
+        };
+        let next_round_time = world.time + self.settings.intermission;
+        self.next_round_time = Some(next_round_time);
+        Some(GameModeEvent::RoundFinished(RoundResult {
+            round: self.round,
+            winner,
+            next_round_time,
+        }))
+    }
+
+    fn is_time_over(&self, world: &World) -> bool {
+        self.round_start_time + self.settings.time_limit <= world.time
+    }
+}
+
+pub fn reset_world(mut new_world: World, world: &World) -> World {
+    new_world.frame = world.frame + 1;
+    new_world.time = world.time;
+    new_world.id_counter = new_world.id_counter.max(world.id_counter);
+    new_world.players = world
+        .players
+        .iter()
+        .map(|v| Player {
+            id: v.id,
+            active: v.active,
+            name: v.name.clone(),
+            team: v.team,
+            actor_id: None,
+            spawn_time: new_world.time + new_world.settings.initial_player_actor_spawn_delay,
+            deaths: 0,
+            kills: 0,
+            assists: 0,
+            damage_dealt: 0.0,
+            damage_healed: 0.0,
+        })
+        .collect();
+    new_world
+}
+
+fn get_best_player(players: &[Player]) -> Option<PlayerId> {
+    let best = players.iter().max_by_key(|v| v.kills)?;
+    if players
+        .iter()
+        .filter(|v| v.kills == best.kills)
+        .nth(1)
+        .is_some()
+    {
+        return None;
+    }
+    Some(best.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::ActorId;
+
+    use super::*;
+
+    fn make_player(id: u64, kills: u64, actor_id: Option<ActorId>) -> Player {
+        Player {
+            id: PlayerId(id),
+            active: true,
+            name: format!("p{}", id),
+            team: None,
+            actor_id,
+            spawn_time: 0.0,
+            deaths: 0,
+            kills,
+            assists: 0,
+            damage_dealt: 0.0,
+            damage_healed: 0.0,
+        }
+    }
+
+    fn make_game_mode(mode_type: GameModeType, world: &World) -> GameMode {
+        GameMode::new(
+            GameModeSettings {
+                mode_type,
+                score_limit: 3,
+                time_limit: 60.0,
+                intermission: 5.0,
+            },
+            world,
+        )
+    }
+
+    #[test]
+    fn deathmatch_should_finish_round_on_score_limit_and_start_next_after_intermission() {
+        let mut world = World {
+            players: vec![make_player(1, 2, None), make_player(2, 1, None)],
+            ..Default::default()
+        };
+        let mut game_mode = make_game_mode(GameModeType::Deathmatch, &world);
+        world.time = 1.0;
+        assert_eq!(game_mode.update(&mut world), None);
+        world.players[0].kills = 3;
+        assert_eq!(
+            game_mode.update(&mut world),
+            Some(GameModeEvent::RoundFinished(RoundResult {
+                round: 1,
+                winner: Some(PlayerId(1)),
+                next_round_time: 6.0,
+            }))
+        );
+        world.time = 5.0;
+        assert_eq!(game_mode.update(&mut world), None);
+        world.time = 6.0;
+        assert_eq!(
+            game_mode.update(&mut world),
+            Some(GameModeEvent::RoundStarted)
+        );
+        assert_eq!(game_mode.round, 2);
+    }
+
+    #[test]
+    fn timed_rounds_should_finish_round_with_draw_on_equal_kills() {
+        let mut world = World {
+            players: vec![make_player(1, 2, None), make_player(2, 2, None)],
+            ..Default::default()
+        };
+        let mut game_mode = make_game_mode(GameModeType::TimedRounds, &world);
+        world.time = 59.0;
+        assert_eq!(game_mode.update(&mut world), None);
+        world.time = 60.0;
+        assert_eq!(
+            game_mode.update(&mut world),
+            Some(GameModeEvent::RoundFinished(RoundResult {
+                round: 1,
+                winner: None,
+                next_round_time: 65.0,
+            }))
+        );
+    }
+
+    #[test]
+    fn last_wizard_standing_should_disable_respawn_and_finish_round_with_one_actor_left() {
+        let mut world = World {
+            players: vec![
+                make_player(1, 0, Some(ActorId(3))),
+                make_player(2, 0, Some(ActorId(4))),
+                make_player(5, 0, Some(ActorId(6))),
+            ],
+            ..Default::default()
+        };
+        let mut game_mode = make_game_mode(GameModeType::LastWizardStanding, &world);
+        world.time = world.settings.initial_player_actor_spawn_delay;
+        assert_eq!(game_mode.update(&mut world), None);
+        world.players[0].actor_id = None;
+        assert_eq!(game_mode.update(&mut world), None);
+        assert_eq!(world.players[0].spawn_time, f64::MAX);
+        world.players[2].actor_id = None;
+        assert_eq!(
+            game_mode.update(&mut world),
+            Some(GameModeEvent::RoundFinished(RoundResult {
+                round: 1,
+                winner: Some(PlayerId(2)),
+                next_round_time: world.time + 5.0,
+            }))
+        );
+    }
+
+    #[test]
+    fn reset_world_should_keep_players_and_advance_frame() {
+        let world = World {
+            frame: 100,
+            time: 42.0,
+            id_counter: 10,
+            players: vec![make_player(1, 2, Some(ActorId(3)))],
+            ..Default::default()
+        };
+        let new_world = reset_world(
+            World {
+                id_counter: 5,
+                ..Default::default()
+            },
+            &world,
+        );
+        assert_eq!(new_world.frame, 101);
+        assert_eq!(new_world.time, 42.0);
+        assert_eq!(new_world.id_counter, 10);
+        assert_eq!(
+            new_world.players,
+            vec![Player {
+                spawn_time: 42.0 + new_world.settings.initial_player_actor_spawn_delay,
+                ..make_player(1, 0, None)
+            }]
+        );
+    }
+}
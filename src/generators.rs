@@ -7,7 +7,7 @@ use crate::vec2::Vec2f;
 use crate::world::{
     Actor, ActorId, ActorOccupation, Aura, Body, Disk, Effect, Element, Magick, MaterialType,
     PlayerId, Rectangle, StaticArea, StaticAreaId, StaticAreaShape, StaticObject, StaticObjectId,
-    StaticShape, World, WorldSettings,
+    StaticShape, TeamId, World, WorldSettings,
 };
 
 pub fn make_rng(random_seed: Option<u64>) -> SmallRng {
@@ -139,6 +139,8 @@ pub fn generate_world<R: Rng>(bounds: Rectf, rng: &mut R) -> World {
         guns: Vec::new(),
         shields: Vec::new(),
         temp_obstacles: Vec::new(),
+        lightning_bolts: Vec::new(),
+        mines: Vec::new(),
     }
 }
 
@@ -146,6 +148,7 @@ pub fn generate_player_actor<R: Rng>(
     id: ActorId,
     player_id: PlayerId,
     name: String,
+    team: Option<TeamId>,
     bounds: &Rectf,
     rng: &mut R,
 ) -> Actor {
@@ -157,6 +160,7 @@ pub fn generate_player_actor<R: Rng>(
         player_id,
         active: true,
         name,
+        team,
         body: Body {
             shape: Disk { radius },
             material_type: MaterialType::Flesh,
@@ -178,6 +182,7 @@ pub fn generate_player_actor<R: Rng>(
         position_z: radius,
         velocity_z: 0.0,
         occupation: ActorOccupation::None,
+        damagers: Vec::new(),
     }
 }
 
@@ -211,6 +216,7 @@ pub fn generate_actor<R: Rng>(
         player_id: PlayerId(0),
         active: true,
         name: format!("bot {}", id.0),
+        team: None,
         body: Body {
             shape: Disk { radius },
             material_type,
@@ -234,6 +240,7 @@ pub fn generate_actor<R: Rng>(
         position_z: radius,
         velocity_z: 0.0,
         occupation: ActorOccupation::None,
+        damagers: Vec::new(),
     }
 }
 
@@ -0,0 +1,270 @@
+use std::collections::HashMap;
+
+use crate::world::{
+    ActorId, BeamId, BoundedAreaId, FieldId, GunId, LightningBoltId, MineId, PlayerId,
+    ProjectileId, ShieldId, StaticAreaId, StaticObjectId, TempAreaId, TempObstacleId, World,
+};
+
+#[derive(Default, Debug)]
+pub struct IdIndex {
+    indices: HashMap<u64, usize>,
+    len: usize,
+}
+
+impl IdIndex {
+    pub fn clear(&mut self) {
+        self.indices.clear();
+        self.len = 0;
+    }
+
+    pub fn extend<T, F>(&mut self, values: &[T], get_id: F)
+    where
+        F: Fn(&T) -> u64,
+    {
+        if self.len > values.len()
+            || (self.len > 0 && self.get(get_id(&values[self.len - 1])) != Some(self.len - 1))
+        {
+            self.clear();
+        }
+        self.indices.extend(
+            values
+                .iter()
+                .enumerate()
+                .skip(self.len)
+                .map(|(index, value)| (get_id(value), index)),
+        );
+        self.len = values.len();
+    }
+
+    pub fn get(&self, id: u64) -> Option<usize> {
+        self.indices.get(&id).copied()
+    }
+}
+
+#[derive(Default, Debug)]
+pub struct WorldIndex {
+    players: IdIndex,
+    actors: IdIndex,
+    player_actors: IdIndex,
+    projectiles: IdIndex,
+    static_objects: IdIndex,
+    beams: IdIndex,
+    static_areas: IdIndex,
+    temp_areas: IdIndex,
+    bounded_areas: IdIndex,
+    fields: IdIndex,
+    guns: IdIndex,
+    shields: IdIndex,
+    temp_obstacles: IdIndex,
+    lightning_bolts: IdIndex,
+    mines: IdIndex,
+}
+
+impl WorldIndex {
+    pub fn rebuild(&mut self, world: &World) {
+        for index in [
+            &mut self.players,
+            &mut self.actors,
+            &mut self.player_actors,
+            &mut self.projectiles,
+            &mut self.static_objects,
+            &mut self.beams,
+            &mut self.static_areas,
+            &mut self.temp_areas,
+            &mut self.bounded_areas,
+            &mut self.fields,
+            &mut self.guns,
+            &mut self.shields,
+            &mut self.temp_obstacles,
+            &mut self.lightning_bolts,
+            &mut self.mines,
+        ] {
+            index.clear();
+        }
+        self.update(world);
+    }
+
+    pub fn update(&mut self, world: &World) {
+        self.players.extend(&world.players, |v| v.id.0);
+        self.actors.extend(&world.actors, |v| v.id.0);
+        self.player_actors.extend(&world.actors, |v| v.player_id.0);
+        self.projectiles.extend(&world.projectiles, |v| v.id.0);
+        self.static_objects
+            .extend(&world.static_objects, |v| v.id.0);
+        self.beams.extend(&world.beams, |v| v.id.0);
+        self.static_areas.extend(&world.static_areas, |v| v.id.0);
+        self.temp_areas.extend(&world.temp_areas, |v| v.id.0);
+        self.bounded_areas.extend(&world.bounded_areas, |v| v.id.0);
+        self.fields.extend(&world.fields, |v| v.id.0);
+        self.guns.extend(&world.guns, |v| v.id.0);
+        self.shields.extend(&world.shields, |v| v.id.0);
+        self.temp_obstacles
+            .extend(&world.temp_obstacles, |v| v.id.0);
+        self.lightning_bolts
+            .extend(&world.lightning_bolts, |v| v.id.0);
+        self.mines.extend(&world.mines, |v| v.id.0);
+    }
+
+    pub fn player(&self, id: PlayerId) -> Option<usize> {
+        self.players.get(id.0)
+    }
+
+    pub fn actor(&self, id: ActorId) -> Option<usize> {
+        self.actors.get(id.0)
+    }
+
+    pub fn player_actor(&self, id: PlayerId) -> Option<usize> {
+        self.player_actors.get(id.0)
+    }
+
+    pub fn projectile(&self, id: ProjectileId) -> Option<usize> {
+        self.projectiles.get(id.0)
+    }
+
+    pub fn static_object(&self, id: StaticObjectId) -> Option<usize> {
+        self.static_objects.get(id.0)
+    }
+
+    pub fn beam(&self, id: BeamId) -> Option<usize> {
+        self.beams.get(id.0)
+    }
+
+    pub fn static_area(&self, id: StaticAreaId) -> Option<usize> {
+        self.static_areas.get(id.0)
+    }
+
+    pub fn temp_area(&self, id: TempAreaId) -> Option<usize> {
+        self.temp_areas.get(id.0)
+    }
+
+    pub fn bounded_area(&self, id: BoundedAreaId) -> Option<usize> {
+        self.bounded_areas.get(id.0)
+    }
+
+    pub fn field(&self, id: FieldId) -> Option<usize> {
+        self.fields.get(id.0)
+    }
+
+    pub fn gun(&self, id: GunId) -> Option<usize> {
+        self.guns.get(id.0)
+    }
+
+    pub fn shield(&self, id: ShieldId) -> Option<usize> {
+        self.shields.get(id.0)
+    }
+
+    pub fn temp_obstacle(&self, id: TempObstacleId) -> Option<usize> {
+        self.temp_obstacles.get(id.0)
+    }
+
+    pub fn lightning_bolt(&self, id: LightningBoltId) -> Option<usize> {
+        self.lightning_bolts.get(id.0)
+    }
+
+    pub fn mine(&self, id: MineId) -> Option<usize> {
+        self.mines.get(id.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use crate::generators::generate_player_actor;
+    use crate::rect::Rectf;
+    use crate::vec2::Vec2f;
+
+    use super::*;
+
+    #[test]
+    fn rebuild_should_follow_retained_actors() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let bounds = Rectf::new(Vec2f::both(-10.0), Vec2f::both(10.0));
+        let mut world = World {
+            actors: (0..5)
+                .map(|i| {
+                    generate_player_actor(
+                        ActorId(i),
+                        PlayerId(10 + i),
+                        String::new(),
+                        None,
+                        &bounds,
+                        &mut rng,
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        assert_eq!(index.actor(ActorId(3)), Some(3));
+        assert_eq!(index.player_actor(PlayerId(13)), Some(3));
+        world.actors.retain(|v| v.id.0 % 2 == 1);
+        index.rebuild(&world);
+        assert_eq!(index.actor(ActorId(0)), None);
+        assert_eq!(index.actor(ActorId(1)), Some(0));
+        assert_eq!(index.actor(ActorId(3)), Some(1));
+        assert_eq!(index.player_actor(PlayerId(12)), None);
+        assert_eq!(index.player_actor(PlayerId(13)), Some(1));
+    }
+    #[test]
+    fn update_should_reindex_values_retained_and_appended_to_same_length() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let bounds = Rectf::new(Vec2f::both(-10.0), Vec2f::both(10.0));
+        let mut world = World {
+            actors: (0..3)
+                .map(|i| {
+                    generate_player_actor(
+                        ActorId(i),
+                        PlayerId(10 + i),
+                        String::new(),
+                        None,
+                        &bounds,
+                        &mut rng,
+                    )
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        world.actors.retain(|v| v.id != ActorId(0));
+        world.actors.push(generate_player_actor(
+            ActorId(3),
+            PlayerId(13),
+            String::new(),
+            None,
+            &bounds,
+            &mut rng,
+        ));
+        index.update(&world);
+        assert_eq!(index.actor(ActorId(0)), None);
+        assert_eq!(index.actor(ActorId(1)), Some(0));
+        assert_eq!(index.actor(ActorId(2)), Some(1));
+        assert_eq!(index.actor(ActorId(3)), Some(2));
+        assert_eq!(index.player_actor(PlayerId(10)), None);
+        assert_eq!(index.player_actor(PlayerId(13)), Some(2));
+    }
+
+    #[test]
+    fn update_should_add_appended_values() {
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let bounds = Rectf::new(Vec2f::both(-10.0), Vec2f::both(10.0));
+        let mut world = World::default();
+        let mut index = WorldIndex::default();
+        index.rebuild(&world);
+        for i in 0..3 {
+            world.actors.push(generate_player_actor(
+                ActorId(i),
+                PlayerId(10 + i),
+                String::new(),
+                None,
+                &bounds,
+                &mut rng,
+            ));
+            index.update(&world);
+            assert_eq!(index.actor(ActorId(i)), Some(i as usize));
+            assert_eq!(index.player_actor(PlayerId(10 + i)), Some(i as usize));
+        }
+    }
+}
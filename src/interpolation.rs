@@ -0,0 +1,321 @@
+use std::collections::VecDeque;
+
+use crate::engine::normalize_angle;
+use crate::vec2::Vec2f;
+use crate::world::{Actor, ActorId, Projectile, ProjectileId, World};
+
+const MAX_SAMPLES: usize = 120;
+const RENDER_TIME_CORRECTION_FACTOR: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct State {
+    position: Vec2f,
+    direction: Vec2f,
+    position_z: f64,
+    velocity: Vec2f,
+    velocity_z: f64,
+}
+
+struct Sample {
+    time: f64,
+    actors: Vec<(u64, State)>,
+    projectiles: Vec<(u64, State)>,
+}
+
+pub struct Interpolation {
+    delay: f64,
+    max_extrapolation: f64,
+    samples: VecDeque<Sample>,
+    latest_sample_age: f64,
+    render_time: Option<f64>,
+    actors: Vec<(ActorId, State)>,
+    projectiles: Vec<(ProjectileId, State)>,
+}
+
+impl Interpolation {
+    pub fn new(delay: f64, max_extrapolation: f64) -> Self {
+        Self {
+            delay,
+            max_extrapolation,
+            samples: VecDeque::new(),
+            latest_sample_age: 0.0,
+            render_time: None,
+            actors: Vec::new(),
+            projectiles: Vec::new(),
+        }
+    }
+
+    pub fn render_time(&self) -> Option<f64> {
+        self.render_time
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+        self.render_time = None;
+    }
+
+    pub fn add_sample(&mut self, world: &World) {
+        if matches!(self.samples.back(), Some(v) if v.time >= world.time) {
+            return;
+        }
+        let mut actors: Vec<(u64, State)> = world
+            .actors
+            .iter()
+            .map(|v| (v.id.0, get_actor_state(v)))
+            .collect();
+        actors.sort_by_key(|v| v.0);
+        let mut projectiles: Vec<(u64, State)> = world
+            .projectiles
+            .iter()
+            .map(|v| (v.id.0, get_projectile_state(v)))
+            .collect();
+        projectiles.sort_by_key(|v| v.0);
+        self.samples.push_back(Sample {
+            time: world.time,
+            actors,
+            projectiles,
+        });
+        while self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.latest_sample_age = 0.0;
+    }
+
+    pub fn restore(&mut self, world: &mut World) {
+        for (id, state) in self.actors.drain(..) {
+            if let Some(actor) = world.actors.iter_mut().find(|v| v.id == id) {
+                set_actor_state(state, actor);
+            }
+        }
+        for (id, state) in self.projectiles.drain(..) {
+            if let Some(projectile) = world.projectiles.iter_mut().find(|v| v.id == id) {
+                set_projectile_state(state, projectile);
+            }
+        }
+    }
+
+    pub fn interpolate(
+        &mut self,
+        time_step: f64,
+        local_actor_id: Option<ActorId>,
+        world: &mut World,
+    ) {
+        let latest_time = match self.samples.back() {
+            Some(v) => v.time,
+            None => return,
+        };
+        let render_time = self.update_render_time(time_step, latest_time);
+        while self.samples.len() >= 2 && self.samples[1].time <= render_time {
+            self.samples.pop_front();
+        }
+        let from = &self.samples[0];
+        let to = self.samples.get(1);
+        for actor in world.actors.iter_mut() {
+            if Some(actor.id) == local_actor_id {
+                continue;
+            }
+            let state = get_state(
+                render_time,
+                self.max_extrapolation,
+                from.time,
+                find_state(&from.actors, actor.id.0),
+                to.map(|v| (v.time, find_state(&v.actors, actor.id.0))),
+            );
+            if let Some(state) = state {
+                self.actors.push((actor.id, get_actor_state(actor)));
+                set_actor_state(state, actor);
+            }
+        }
+        for projectile in world.projectiles.iter_mut() {
+            let state = get_state(
+                render_time,
+                self.max_extrapolation,
+                from.time,
+                find_state(&from.projectiles, projectile.id.0),
+                to.map(|v| (v.time, find_state(&v.projectiles, projectile.id.0))),
+            );
+            if let Some(state) = state {
+                self.projectiles
+                    .push((projectile.id, get_projectile_state(projectile)));
+                set_projectile_state(state, projectile);
+            }
+        }
+    }
+
+    fn update_render_time(&mut self, time_step: f64, latest_time: f64) -> f64 {
+        let target = latest_time + self.latest_sample_age - self.delay;
+        self.latest_sample_age += time_step;
+        let mut render_time = match self.render_time {
+            Some(v) if (target - v).abs() <= self.delay => {
+                let next = v + time_step;
+                next + (target - next) * RENDER_TIME_CORRECTION_FACTOR
+            }
+            _ => target,
+        };
+        render_time = render_time.min(latest_time + self.max_extrapolation);
+        self.render_time = Some(render_time);
+        render_time
+    }
+}
+
+fn find_state(states: &[(u64, State)], id: u64) -> Option<State> {
+    states
+        .binary_search_by_key(&id, |v| v.0)
+        .ok()
+        .map(|i| states[i].1)
+}
+
+fn get_state(
+    render_time: f64,
+    max_extrapolation: f64,
+    from_time: f64,
+    from: Option<State>,
+    to: Option<(f64, Option<State>)>,
+) -> Option<State> {
+    let from = from?;
+    match to {
+        Some((to_time, Some(to))) => {
+            let k = ((render_time - from_time) / (to_time - from_time)).clamp(0.0, 1.0);
+            Some(interpolate_state(&from, &to, k))
+        }
+        Some((_, None)) => Some(from),
+        None => {
+            let duration = (render_time - from_time).clamp(0.0, max_extrapolation);
+            Some(extrapolate_state(&from, duration))
+        }
+    }
+}
+
+fn interpolate_state(from: &State, to: &State, k: f64) -> State {
+    State {
+        position: from.position + (to.position - from.position) * k,
+        direction: from
+            .direction
+            .rotated(normalize_angle(to.direction.angle() - from.direction.angle()) * k),
+        position_z: from.position_z + (to.position_z - from.position_z) * k,
+        velocity: from.velocity + (to.velocity - from.velocity) * k,
+        velocity_z: from.velocity_z + (to.velocity_z - from.velocity_z) * k,
+    }
+}
+
+fn extrapolate_state(state: &State, duration: f64) -> State {
+    State {
+        position: state.position + state.velocity * duration,
+        position_z: (state.position_z + state.velocity_z * duration).max(0.0),
+        ..*state
+    }
+}
+
+fn get_actor_state(actor: &Actor) -> State {
+    State {
+        position: actor.position,
+        direction: actor.current_direction,
+        position_z: actor.position_z,
+        velocity: actor.velocity,
+        velocity_z: actor.velocity_z,
+    }
+}
+
+fn set_actor_state(state: State, actor: &mut Actor) {
+    actor.position = state.position;
+    actor.current_direction = state.direction;
+    actor.position_z = state.position_z;
+    actor.velocity = state.velocity;
+    actor.velocity_z = state.velocity_z;
+}
+
+fn get_projectile_state(projectile: &Projectile) -> State {
+    State {
+        position: projectile.position,
+        direction: Vec2f::ZERO,
+        position_z: projectile.position_z,
+        velocity: projectile.velocity,
+        velocity_z: projectile.velocity_z,
+    }
+}
+
+fn set_projectile_state(state: State, projectile: &mut Projectile) {
+    projectile.position = state.position;
+    projectile.position_z = state.position_z;
+    projectile.velocity = state.velocity;
+    projectile.velocity_z = state.velocity_z;
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    use crate::generators::generate_player_actor;
+    use crate::rect::Rectf;
+    use crate::world::PlayerId;
+
+    use super::*;
+
+    const TIME_STEP: f64 = 1.0 / 60.0;
+
+    fn make_world(time: f64, positions: &[Vec2f]) -> World {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let bounds = Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0));
+        let actors = positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                let mut actor = generate_player_actor(
+                    ActorId(i as u64 + 1),
+                    PlayerId(i as u64 + 1),
+                    String::new(),
+                    None,
+                    &bounds,
+                    &mut rng,
+                );
+                actor.position = *position;
+                actor.position_z = 0.0;
+                actor.velocity = Vec2f::ZERO;
+                actor.velocity_z = 0.0;
+                actor.current_direction = Vec2f::I;
+                actor
+            })
+            .collect();
+        World {
+            time,
+            bounds,
+            actors,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn interpolate_should_set_remote_actors_state_between_samples_at_delay() {
+        let mut interpolation = Interpolation::new(0.5, 0.25);
+        let mut world = make_world(1.0, &[Vec2f::ZERO, Vec2f::ZERO]);
+        interpolation.add_sample(&world);
+        world = make_world(2.0, &[Vec2f::only_x(10.0), Vec2f::only_x(10.0)]);
+        world.actors[0].current_direction = Vec2f::new(0.0, 1.0);
+        interpolation.add_sample(&world);
+        interpolation.interpolate(TIME_STEP, Some(ActorId(2)), &mut world);
+        assert_eq!(interpolation.render_time(), Some(1.5));
+        assert_eq!(world.actors[0].position, Vec2f::only_x(5.0));
+        assert!(
+            (world.actors[0].current_direction.angle() - std::f64::consts::FRAC_PI_4).abs() < 1e-9
+        );
+        assert_eq!(world.actors[1].position, Vec2f::only_x(10.0));
+        interpolation.restore(&mut world);
+        assert_eq!(world.actors[0].position, Vec2f::only_x(10.0));
+        assert_eq!(world.actors[0].current_direction, Vec2f::new(0.0, 1.0));
+    }
+
+    #[test]
+    fn interpolate_should_limit_extrapolation_when_samples_are_late() {
+        let mut interpolation = Interpolation::new(0.1, 0.25);
+        let mut world = make_world(1.0, &[Vec2f::ZERO]);
+        world.actors[0].velocity = Vec2f::only_x(2.0);
+        interpolation.add_sample(&world);
+        for _ in 0..120 {
+            interpolation.restore(&mut world);
+            interpolation.interpolate(TIME_STEP, None, &mut world);
+        }
+        assert_eq!(interpolation.render_time(), Some(1.25));
+        assert_eq!(world.actors[0].position, Vec2f::only_x(0.5));
+    }
+}
@@ -1,26 +1,46 @@
-#[cfg(any(feature = "client", feature = "server"))]
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
 #[macro_use]
 extern crate log;
 
-#[cfg(feature = "client")]
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod bot;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+mod broadphase;
+#[cfg(any(feature = "client", feature = "bot"))]
 pub mod client;
-#[cfg(any(feature = "client", feature = "server"))]
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
 mod control;
-#[cfg(any(feature = "client", feature = "server"))]
-mod engine;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod encryption;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod engine;
 #[cfg(feature = "client")]
 pub mod game;
-#[cfg(any(feature = "client", feature = "server"))]
-mod generators;
+#[cfg(feature = "server")]
+pub mod game_mode;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod generators;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod index;
+#[cfg(feature = "client")]
+pub mod interpolation;
+#[cfg(feature = "bot")]
+pub mod load_test;
 #[cfg(any(feature = "client", feature = "server"))]
 mod meters;
-#[cfg(any(feature = "client", feature = "server"))]
+#[cfg(feature = "client")]
+pub mod prediction;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
 pub mod protocol;
-#[cfg(any(feature = "client", feature = "server"))]
-mod rect;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod quantization;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod rect;
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
+pub mod reliable;
 #[cfg(feature = "server")]
 pub mod server;
-#[cfg(any(feature = "client", feature = "server"))]
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
 pub mod vec2;
-#[cfg(any(feature = "client", feature = "server"))]
+#[cfg(any(feature = "client", feature = "server", feature = "bot"))]
 pub mod world;
@@ -0,0 +1,439 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+
+use clap::Clap;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+
+use crate::bot::get_numbered_name;
+use crate::client::{Client, GameClientSettings, UdpClientSettings};
+use crate::encryption::EncryptionKey;
+use crate::generators::make_rng;
+use crate::protocol::{
+    is_valid_player_name, ActorAction, Capabilities, CastAction, GameUpdate, Metric, PlayerControl,
+};
+use crate::vec2::Vec2f;
+use crate::world::Element;
+
+#[derive(Clap, Debug)]
+pub struct LoadTestParams {
+    #[clap(long, default_value = "127.0.0.1")]
+    pub server_address: String,
+    #[clap(long, default_value = "21227")]
+    pub server_port: u16,
+    #[clap(long, default_value = "1")]
+    pub players: usize,
+    #[clap(long, default_value = "Loadbot")]
+    pub player_name_prefix: String,
+    #[clap(long, default_value = "60")]
+    pub duration: f64,
+    #[clap(long, default_value = "60", validator = parse_positive_number)]
+    pub control_frequency: f64,
+    #[clap(long, default_value = "3")]
+    pub connect_timeout: f64,
+    #[clap(long, default_value = "3")]
+    pub read_timeout: f64,
+    #[clap(long, default_value = "1")]
+    pub reconnect_timeout: f64,
+    #[clap(long, default_value = "0.25")]
+    pub retry_period: f64,
+    #[clap(long)]
+    pub random_seed: Option<u64>,
+    #[clap(long)]
+    pub script: Option<String>,
+    #[clap(long)]
+    pub quantized_world_updates: bool,
+    #[clap(long)]
+    pub encryption_key: Option<EncryptionKey>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct LoadTestReport {
+    pub players: usize,
+    pub joined_players: usize,
+    pub join_latency: Metric,
+    pub world_frame_delay: Metric,
+    pub world_snapshots: u64,
+    pub world_updates: u64,
+    pub dropped_world_frames: u64,
+    pub received_messages_per_second: f64,
+    pub sent_messages_per_second: f64,
+    pub errors: Vec<String>,
+}
+
+pub fn run_load_test(
+    params: LoadTestParams,
+    stop: Arc<AtomicBool>,
+) -> Result<LoadTestReport, String> {
+    info!("Run load test: {:?}", params);
+    let server_address: SocketAddr = format!("{}:{}", params.server_address, params.server_port)
+        .parse()
+        .map_err(|e| format!("Invalid server address: {}", e))?;
+    let script = match params.script.as_ref() {
+        Some(path) => Some(Arc::new(load_script(path)?)),
+        None => None,
+    };
+    let player_names: Vec<String> = (0..params.players)
+        .map(|i| get_numbered_name(params.player_name_prefix.as_str(), i))
+        .collect();
+    if let Some(name) = player_names.iter().find(|v| !is_valid_player_name(v)) {
+        return Err(format!("Invalid player name: {}", name));
+    }
+    let mut rng = make_rng(params.random_seed);
+    let deadline = Instant::now() + Duration::from_secs_f64(params.duration);
+    let handles: Vec<_> = player_names
+        .into_iter()
+        .enumerate()
+        .map(|(i, player_name)| {
+            let settings = PlayerSettings {
+                game_client: GameClientSettings {
+                    id: i as u64 + 1,
+                    connect_timeout: Duration::from_secs_f64(params.connect_timeout),
+                    retry_period: Duration::from_secs_f64(params.retry_period),
+                    player_name,
+                    team: None,
+                    spectator: false,
+                    capabilities: Capabilities {
+                        quantized_world_updates: params.quantized_world_updates,
+                    },
+                },
+                udp_client: UdpClientSettings {
+                    id: i as u64 + 1,
+                    server_address,
+                    read_timeout: Duration::from_secs_f64(params.read_timeout),
+                    reconnect_timeout: Duration::from_secs_f64(params.reconnect_timeout),
+                    encryption_key: params.encryption_key.clone(),
+                },
+                control_period: Duration::from_secs_f64(1.0 / params.control_frequency),
+                deadline,
+                random_seed: rng.gen(),
+            };
+            let script = script.clone();
+            let stop = stop.clone();
+            spawn(move || run_player(settings, script, stop))
+        })
+        .collect();
+    let stats: Vec<PlayerStats> = handles
+        .into_iter()
+        .map(|v| {
+            v.join()
+                .unwrap_or_else(|_| PlayerStats::failed(String::from("Player thread has panicked")))
+        })
+        .collect();
+    Ok(make_report(&stats))
+}
+
+fn parse_positive_number(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(v) if v > 0.0 && v.is_finite() => Ok(v),
+        Ok(v) => Err(format!("Value should be positive: {}", v)),
+        Err(e) => Err(format!("Invalid number: {}", e)),
+    }
+}
+
+fn load_script(path: &str) -> Result<Vec<ActorAction>, String> {
+    let file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open script \"{}\": {}", path, e))?;
+    let script: Vec<ActorAction> = serde_json::from_reader(file)
+        .map_err(|e| format!("Failed to read script \"{}\": {}", path, e))?;
+    if script.is_empty() {
+        return Err(format!("Script \"{}\" is empty", path));
+    }
+    Ok(script)
+}
+
+struct PlayerSettings {
+    game_client: GameClientSettings,
+    udp_client: UdpClientSettings,
+    control_period: Duration,
+    deadline: Instant,
+    random_seed: u64,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+struct Summary {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Summary {
+    fn add(&mut self, value: f64) {
+        if self.count == 0 || value < self.min {
+            self.min = value;
+        }
+        if self.count == 0 || value > self.max {
+            self.max = value;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+
+    fn merge(&mut self, other: &Summary) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 || other.min < self.min {
+            self.min = other.min;
+        }
+        if self.count == 0 || other.max > self.max {
+            self.max = other.max;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+    }
+
+    fn metric(&self) -> Metric {
+        Metric {
+            min: self.min,
+            mean: if self.count > 0 {
+                self.sum / self.count as f64
+            } else {
+                0.0
+            },
+            max: self.max,
+        }
+    }
+}
+
+#[derive(Default, Debug)]
+struct PlayerStats {
+    join_latency: Option<Duration>,
+    world_frame_delay: Summary,
+    world_snapshots: u64,
+    world_updates: u64,
+    dropped_world_frames: u64,
+    last_world_frame: u64,
+    received_messages: u64,
+    sent_messages: u64,
+    duration: Duration,
+    error: Option<String>,
+}
+
+impl PlayerStats {
+    fn failed(error: String) -> Self {
+        Self {
+            error: Some(error),
+            ..Default::default()
+        }
+    }
+
+    fn add_world_frame(&mut self, frame: u64) {
+        if self.last_world_frame != 0 && frame > self.last_world_frame + 1 {
+            self.dropped_world_frames += frame - self.last_world_frame - 1;
+        }
+        self.last_world_frame = self.last_world_frame.max(frame);
+    }
+}
+
+fn run_player(
+    settings: PlayerSettings,
+    script: Option<Arc<Vec<ActorAction>>>,
+    stop: Arc<AtomicBool>,
+) -> PlayerStats {
+    let id = settings.game_client.id;
+    let start = Instant::now();
+    let mut client = Client::new(settings.game_client, settings.udp_client);
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(settings.random_seed);
+    let mut stats = PlayerStats::default();
+    let mut ack_cast_action_world_frame = 0;
+    let mut step = 0;
+    'control: while !stop.load(Ordering::Acquire) && Instant::now() < settings.deadline {
+        let frame_start = Instant::now();
+        if !client.is_running() {
+            break;
+        }
+        while let Ok(game_update) = client.receiver().try_recv() {
+            stats.received_messages += 1;
+            match game_update {
+                GameUpdate::SetPlayerId(..) => {
+                    stats.join_latency = Some(Instant::now() - start);
+                }
+                GameUpdate::WorldSnapshot {
+                    ack_cast_action_world_frame: v,
+                    world,
+                    ..
+                } => {
+                    stats.world_snapshots += 1;
+                    stats.add_world_frame(world.frame);
+                    ack_cast_action_world_frame = v;
+                }
+                GameUpdate::WorldUpdate {
+                    ack_cast_action_world_frame: v,
+                    world_update,
+                    ..
+                } => {
+                    stats.world_updates += 1;
+                    stats
+                        .world_frame_delay
+                        .add((world_update.after_frame - world_update.before_frame) as f64);
+                    stats.add_world_frame(world_update.after_frame);
+                    ack_cast_action_world_frame = v;
+                }
+                GameUpdate::RoundResult(..) | GameUpdate::ChatMessage(..) => (),
+                GameUpdate::GameOver(message) => {
+                    stats.error = Some(message);
+                    break 'control;
+                }
+            }
+        }
+        if stats.join_latency.is_some() {
+            let actor_action = match script.as_ref() {
+                Some(script) => script[step % script.len()].clone(),
+                None => generate_actor_action(&mut rng),
+            };
+            step += 1;
+            let player_control = PlayerControl {
+                ack_world_frame: stats.last_world_frame,
+                cast_action_world_frame: ack_cast_action_world_frame + 1,
+                actor_action,
+            };
+            if client.sender().send(player_control).is_err() {
+                break;
+            }
+            stats.sent_messages += 1;
+        }
+        let passed = Instant::now() - frame_start;
+        if passed < settings.control_period {
+            sleep(settings.control_period - passed);
+        }
+    }
+    stats.duration = Instant::now() - start;
+    client.stop();
+    if let Err(e) = client.join() {
+        stats.error.get_or_insert(e);
+    }
+    if stats.join_latency.is_none() && stats.error.is_none() {
+        stats.error = Some(String::from("Failed to join the server"));
+    }
+    debug!("[{}] Load test player has stopped: {:?}", id, stats);
+    stats
+}
+
+fn generate_actor_action<R: Rng>(rng: &mut R) -> ActorAction {
+    let cast_action = if rng.gen_bool(0.1) {
+        Some(match rng.gen_range(0..5) {
+            0 => CastAction::StartDirectedMagick,
+            1 => CastAction::CompleteDirectedMagick,
+            2 => CastAction::SelfMagick,
+            3 => CastAction::StartAreaOfEffectMagick,
+            _ => CastAction::AddSpellElement(Element::from(rng.gen_range(0..11))),
+        })
+    } else {
+        None
+    };
+    ActorAction {
+        moving: rng.gen_bool(0.5),
+        target_direction: Vec2f::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)),
+        cast_action,
+    }
+}
+
+fn make_report(stats: &[PlayerStats]) -> LoadTestReport {
+    let mut join_latency = Summary::default();
+    let mut world_frame_delay = Summary::default();
+    let mut received_messages_per_second = 0.0;
+    let mut sent_messages_per_second = 0.0;
+    for v in stats.iter() {
+        if let Some(latency) = v.join_latency {
+            join_latency.add(latency.as_secs_f64());
+        }
+        world_frame_delay.merge(&v.world_frame_delay);
+        if v.duration > Duration::new(0, 0) {
+            received_messages_per_second += v.received_messages as f64 / v.duration.as_secs_f64();
+            sent_messages_per_second += v.sent_messages as f64 / v.duration.as_secs_f64();
+        }
+    }
+    LoadTestReport {
+        players: stats.len(),
+        joined_players: stats.iter().filter(|v| v.join_latency.is_some()).count(),
+        join_latency: join_latency.metric(),
+        world_frame_delay: world_frame_delay.metric(),
+        world_snapshots: stats.iter().map(|v| v.world_snapshots).sum(),
+        world_updates: stats.iter().map(|v| v.world_updates).sum(),
+        dropped_world_frames: stats.iter().map(|v| v.dropped_world_frames).sum(),
+        received_messages_per_second,
+        sent_messages_per_second,
+        errors: stats.iter().filter_map(|v| v.error.clone()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_world_frame_should_count_skipped_frames() {
+        let mut stats = PlayerStats::default();
+        for frame in [10, 11, 14, 13, 15, 20] {
+            stats.add_world_frame(frame);
+        }
+        assert_eq!(stats.dropped_world_frames, 6);
+        assert_eq!(stats.last_world_frame, 20);
+    }
+
+    #[test]
+    fn load_test_params_should_reject_non_positive_control_frequency() {
+        for value in ["0", "-1", "inf", "NaN"] {
+            assert!(
+                LoadTestParams::try_parse_from(["spell_bot", "--control-frequency", value])
+                    .is_err(),
+                "{}",
+                value
+            );
+        }
+        let params =
+            LoadTestParams::try_parse_from(["spell_bot", "--control-frequency", "0.5"]).unwrap();
+        assert_eq!(params.control_frequency, 0.5);
+    }
+
+    #[test]
+    fn make_report_should_aggregate_player_stats() {
+        let mut first = PlayerStats {
+            join_latency: Some(Duration::from_millis(100)),
+            received_messages: 120,
+            sent_messages: 60,
+            duration: Duration::from_secs(2),
+            ..Default::default()
+        };
+        first.world_frame_delay.add(1.0);
+        first.world_frame_delay.add(3.0);
+        let mut second = PlayerStats {
+            join_latency: Some(Duration::from_millis(300)),
+            received_messages: 60,
+            sent_messages: 30,
+            duration: Duration::from_secs(1),
+            ..Default::default()
+        };
+        second.world_frame_delay.add(2.0);
+        let third = PlayerStats::failed(String::from("Timeout"));
+        let report = make_report(&[first, second, third]);
+        assert_eq!(
+            report,
+            LoadTestReport {
+                players: 3,
+                joined_players: 2,
+                join_latency: Metric {
+                    min: 0.1,
+                    mean: 0.2,
+                    max: 0.3,
+                },
+                world_frame_delay: Metric {
+                    min: 1.0,
+                    mean: 2.0,
+                    max: 3.0,
+                },
+                world_snapshots: 0,
+                world_updates: 0,
+                dropped_world_frames: 0,
+                received_messages_per_second: 120.0,
+                sent_messages_per_second: 60.0,
+                errors: vec![String::from("Timeout")],
+            }
+        );
+    }
+}
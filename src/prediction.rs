@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+
+use crate::engine::{get_current_direction, normalize_angle, Engine};
+use crate::protocol::ActorAction;
+use crate::vec2::Vec2f;
+use crate::world::{Actor, World};
+
+const MAX_PENDING_ACTOR_ACTIONS: usize = 120;
+const MAX_REPLAYED_FRAMES: u64 = 120;
+const CORRECTION_FACTOR: f64 = 0.15;
+const MAX_POSITION_ERROR: f64 = 2.0;
+
+struct PendingActorAction {
+    world_frame: u64,
+    actor_action: ActorAction,
+}
+
+pub struct Prediction {
+    engine: Engine,
+    actor_actions: VecDeque<PendingActorAction>,
+    acks: VecDeque<(u64, u64)>,
+    ack_world_frame: u64,
+    authoritative_actor: Option<Actor>,
+    predicted_actor: Option<Actor>,
+    position_error: Vec2f,
+    direction_error: f64,
+}
+
+impl Default for Prediction {
+    fn default() -> Self {
+        Self {
+            engine: Engine::default(),
+            actor_actions: VecDeque::new(),
+            acks: VecDeque::new(),
+            ack_world_frame: 0,
+            authoritative_actor: None,
+            predicted_actor: None,
+            position_error: Vec2f::ZERO,
+            direction_error: 0.0,
+        }
+    }
+}
+
+impl Prediction {
+    pub fn pending_actor_actions(&self) -> usize {
+        self.actor_actions.len()
+    }
+
+    pub fn add_actor_action(&mut self, world_frame: u64, mut actor_action: ActorAction) {
+        actor_action.cast_action = None;
+        if let Some(last) = self.actor_actions.back_mut() {
+            if last.world_frame == world_frame {
+                last.actor_action = actor_action;
+                return;
+            }
+        }
+        self.actor_actions.push_back(PendingActorAction {
+            world_frame,
+            actor_action,
+        });
+        while self.actor_actions.len() > MAX_PENDING_ACTOR_ACTIONS {
+            self.actor_actions.pop_front();
+        }
+    }
+
+    pub fn ack_actor_action(&mut self, world_frame: u64, ack_actor_action_world_frame: u64) {
+        self.acks
+            .push_back((world_frame, ack_actor_action_world_frame));
+    }
+
+    pub fn restore(&mut self, world: &mut World) {
+        if let Some(actor) = self.authoritative_actor.take() {
+            if let Some(v) = world.actors.iter_mut().find(|v| v.id == actor.id) {
+                *v = actor;
+            }
+        }
+    }
+
+    pub fn predict(&mut self, time_step: f64, actor_index: Option<usize>, world: &mut World) {
+        while let Some((world_frame, ack_world_frame)) = self.acks.front().cloned() {
+            if world_frame > world.frame {
+                break;
+            }
+            self.ack_world_frame = self.ack_world_frame.max(ack_world_frame);
+            self.acks.pop_front();
+        }
+        while let Some(v) = self.actor_actions.front() {
+            if v.world_frame > self.ack_world_frame {
+                break;
+            }
+            self.actor_actions.pop_front();
+        }
+        let actor_index = match actor_index {
+            Some(v) => v,
+            None => {
+                self.predicted_actor = None;
+                return;
+            }
+        };
+        self.engine.prepare_move_actor(world);
+        let mut predicted = world.actors[actor_index].clone();
+        let mut now = world.time;
+        let mut replayed_frames = 0;
+        for (i, v) in self.actor_actions.iter().enumerate() {
+            predicted.moving = v.actor_action.moving;
+            predicted.target_direction = v.actor_action.target_direction;
+            let frames = match self.actor_actions.get(i + 1) {
+                Some(next) => next.world_frame - v.world_frame,
+                None => 1,
+            }
+            .min(MAX_REPLAYED_FRAMES - replayed_frames);
+            for _ in 0..frames {
+                now += time_step;
+                self.engine
+                    .move_actor(now, time_step, world, &mut predicted);
+            }
+            replayed_frames += frames;
+        }
+        self.update_error(time_step, world.settings.max_rotation_speed, &predicted);
+        let actor = &mut world.actors[actor_index];
+        self.authoritative_actor = Some(actor.clone());
+        actor.position = predicted.position + self.position_error;
+        actor.velocity = predicted.velocity;
+        actor.current_direction = predicted.current_direction.rotated(self.direction_error);
+        actor.target_direction = predicted.target_direction;
+        actor.moving = predicted.moving;
+        self.predicted_actor = Some(predicted);
+    }
+
+    fn update_error(&mut self, time_step: f64, max_rotation_speed: f64, predicted: &Actor) {
+        match self.predicted_actor.as_ref() {
+            Some(last) if last.id == predicted.id => {
+                let expected_position = last.position + last.velocity * time_step;
+                let expected_direction = get_current_direction(
+                    last.current_direction,
+                    predicted.target_direction,
+                    time_step,
+                    max_rotation_speed,
+                );
+                self.position_error += expected_position - predicted.position;
+                self.direction_error = normalize_angle(
+                    self.direction_error + expected_direction.angle()
+                        - predicted.current_direction.angle(),
+                );
+            }
+            _ => {
+                self.position_error = Vec2f::ZERO;
+                self.direction_error = 0.0;
+            }
+        }
+        if self.position_error.norm() > MAX_POSITION_ERROR {
+            self.position_error = Vec2f::ZERO;
+        }
+        self.position_error *= 1.0 - CORRECTION_FACTOR;
+        self.direction_error *= 1.0 - CORRECTION_FACTOR;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::SmallRng;
+    use rand::SeedableRng;
+
+    use crate::generators::generate_player_actor;
+    use crate::rect::Rectf;
+    use crate::world::{
+        ActorId, Body, Effect, MaterialType, Player, PlayerId, Rectangle, StaticObject,
+        StaticObjectId, StaticShape,
+    };
+
+    use super::*;
+
+    const TIME_STEP: f64 = 1.0 / 60.0;
+
+    fn make_world() -> World {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let bounds = Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0));
+        let mut actor = generate_player_actor(
+            ActorId(2),
+            PlayerId(1),
+            String::from("p1"),
+            None,
+            &bounds,
+            &mut rng,
+        );
+        actor.position = Vec2f::ZERO;
+        actor.current_direction = Vec2f::I;
+        actor.target_direction = Vec2f::I;
+        World {
+            frame: 1,
+            bounds,
+            id_counter: 3,
+            players: vec![Player {
+                id: PlayerId(1),
+                active: true,
+                name: String::from("p1"),
+                team: None,
+                actor_id: Some(ActorId(2)),
+                spawn_time: 0.0,
+                deaths: 0,
+                kills: 0,
+                assists: 0,
+                damage_dealt: 0.0,
+                damage_healed: 0.0,
+            }],
+            actors: vec![actor],
+            ..Default::default()
+        }
+    }
+
+    fn make_moving_actor_action() -> ActorAction {
+        ActorAction {
+            moving: true,
+            target_direction: Vec2f::I,
+            cast_action: None,
+        }
+    }
+
+    #[test]
+    fn predict_should_move_local_actor_and_restore_should_revert_it() {
+        let mut world = make_world();
+        let mut prediction = Prediction::default();
+        for _ in 0..10 {
+            prediction.add_actor_action(world.frame, make_moving_actor_action());
+        }
+        prediction.predict(TIME_STEP, Some(0), &mut world);
+        assert!(world.actors[0].position.x > 0.0);
+        assert!(world.actors[0].moving);
+        prediction.restore(&mut world);
+        assert_eq!(world.actors[0], make_world().actors[0]);
+    }
+
+    #[test]
+    fn predict_should_replay_only_not_acknowledged_actor_actions() {
+        let mut world = make_world();
+        let mut prediction = Prediction::default();
+        for world_frame in 1..=5 {
+            prediction.add_actor_action(world_frame, make_moving_actor_action());
+        }
+        prediction.ack_actor_action(10, 3);
+        prediction.predict(TIME_STEP, Some(0), &mut world);
+        assert_eq!(prediction.pending_actor_actions(), 5);
+        prediction.restore(&mut world);
+        world.frame = 10;
+        prediction.predict(TIME_STEP, Some(0), &mut world);
+        assert_eq!(prediction.pending_actor_actions(), 2);
+    }
+
+    #[test]
+    fn predict_should_smooth_small_corrections_and_snap_large_ones() {
+        let mut world = make_world();
+        let mut prediction = Prediction::default();
+        prediction.predict(TIME_STEP, Some(0), &mut world);
+        assert_eq!(world.actors[0].position, Vec2f::ZERO);
+        prediction.restore(&mut world);
+        world.actors[0].position = Vec2f::only_x(1.0);
+        prediction.predict(TIME_STEP, Some(0), &mut world);
+        assert!(world.actors[0].position.x > 0.0);
+        assert!(world.actors[0].position.x < 0.5);
+        prediction.restore(&mut world);
+        world.actors[0].position = Vec2f::only_x(10.0);
+        prediction.predict(TIME_STEP, Some(0), &mut world);
+        assert_eq!(world.actors[0].position, Vec2f::only_x(10.0));
+    }
+
+    #[test]
+    fn predict_should_stop_local_actor_at_static_object() {
+        let mut world = make_world();
+        world.static_objects.push(StaticObject {
+            id: StaticObjectId(4),
+            body: Body {
+                shape: StaticShape::Rectangle(Rectangle {
+                    width: 1.0,
+                    height: 20.0,
+                }),
+                material_type: MaterialType::Stone,
+            },
+            position: Vec2f::only_x(3.0),
+            rotation: 0.0,
+            health: 1.0,
+            effect: Effect::default(),
+        });
+        let mut prediction = Prediction::default();
+        for world_frame in 1..=MAX_PENDING_ACTOR_ACTIONS as u64 {
+            prediction.add_actor_action(world_frame, make_moving_actor_action());
+        }
+        prediction.predict(TIME_STEP, Some(0), &mut world);
+        let actor = &world.actors[0];
+        assert!(actor.position.x > 1.0, "{:?}", actor.position);
+        assert!(
+            actor.position.x + actor.body.shape.radius <= 2.5 + 1e-3,
+            "{:?}",
+            actor.position
+        );
+    }
+
+    #[test]
+    fn add_actor_action_should_keep_last_action_for_world_frame() {
+        let mut prediction = Prediction::default();
+        prediction.add_actor_action(1, make_moving_actor_action());
+        prediction.add_actor_action(1, make_moving_actor_action());
+        prediction.add_actor_action(2, make_moving_actor_action());
+        prediction.add_actor_action(2, ActorAction::default());
+        assert_eq!(prediction.pending_actor_actions(), 2);
+        assert!(!prediction.actor_actions[1].actor_action.moving);
+    }
+
+    #[test]
+    fn predict_should_advance_one_step_per_world_frame() {
+        let mut world = make_world();
+        let mut per_render_frame = Prediction::default();
+        for _ in 0..10 {
+            per_render_frame.add_actor_action(world.frame, make_moving_actor_action());
+        }
+        per_render_frame.predict(TIME_STEP, Some(0), &mut world);
+        let single_frame_position = world.actors[0].position;
+        per_render_frame.restore(&mut world);
+        let mut single = Prediction::default();
+        single.add_actor_action(world.frame, make_moving_actor_action());
+        single.predict(TIME_STEP, Some(0), &mut world);
+        assert_eq!(world.actors[0].position, single_frame_position);
+        single.restore(&mut world);
+        let mut with_gap = Prediction::default();
+        with_gap.add_actor_action(1, make_moving_actor_action());
+        with_gap.add_actor_action(4, make_moving_actor_action());
+        with_gap.predict(TIME_STEP, Some(0), &mut world);
+        let gap_position = world.actors[0].position;
+        with_gap.restore(&mut world);
+        let mut consecutive = Prediction::default();
+        for world_frame in 1..=4 {
+            consecutive.add_actor_action(world_frame, make_moving_actor_action());
+        }
+        consecutive.predict(TIME_STEP, Some(0), &mut world);
+        assert_eq!(world.actors[0].position, gap_position);
+        assert!(gap_position.x > single_frame_position.x);
+    }
+}
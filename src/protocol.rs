@@ -1,28 +1,47 @@
+use std::collections::HashSet;
 use std::fmt::Formatter;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use serde::{Deserialize, Serialize};
 
+use crate::quantization::{Quantization, QuantizedWorldUpdate};
 use crate::vec2::Vec2f;
 use crate::world::{
     Actor, ActorId, ActorOccupation, Aura, Beam, BoundedArea, DelayedMagick, Effect, Element,
-    Field, Gun, GunId, Player, PlayerId, Projectile, ProjectileId, Shield, ShieldId, StaticArea,
-    StaticObject, StaticObjectId, TempArea, TempObstacle, TempObstacleId, World,
+    Field, Gun, GunId, LightningBolt, Mine, MineId, ObjectId, Player, PlayerId, Projectile,
+    ProjectileId, Shield, ShieldId, StaticArea, StaticAreaShape, StaticObject, StaticObjectId,
+    StaticShape, TeamId, TempArea, TempObstacle, TempObstacleId, World,
 };
 
+pub const PROTOCOL_VERSION: u64 = 11;
 pub const HEARTBEAT_PERIOD: Duration = Duration::from_secs(1);
 pub const MIN_PLAYER_NAME_LEN: usize = 3;
 pub const MAX_PLAYER_NAME_LEN: usize = 16;
 pub const MAX_SERVER_MESSAGE_SIZE: usize = 65_507;
 pub const MAX_SERVER_MESSAGE_DATA_SIZE: usize = 32_768;
+pub const MAX_SERVER_MESSAGE_FRAGMENTS: usize = 64;
+pub const MAX_DECOMPRESSED_SERVER_MESSAGE_DATA_SIZE: usize = 4_194_304;
+pub const SERVER_MESSAGE_FRAGMENTS_TIMEOUT: Duration = Duration::from_secs(1);
 pub const MAX_CLIENT_MESSAGE_SIZE: usize = 1024;
+pub const MAX_CHAT_MESSAGE_LEN: usize = 256;
+pub const CLIENT_MESSAGE_MAGIC: [u8; 4] = *b"SPLC";
+pub const SERVER_MESSAGE_MAGIC: [u8; 4] = *b"SPLS";
+pub const REJECT_MESSAGE_MAGIC: [u8; 4] = *b"SPLR";
+pub const CHALLENGE_MESSAGE_MAGIC: [u8; 4] = *b"SPLK";
+pub const MESSAGE_PREFIX_SIZE: usize = 12;
+pub const CLIENT_MESSAGE_HEADER_SIZE: usize = MESSAGE_PREFIX_SIZE + 8;
+const MAX_INCOMPLETE_SERVER_MESSAGES: usize = 4;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct ServerMessage {
     pub session_id: u64,
     pub number: u64,
+    pub fragment: u16,
+    pub fragments: u16,
     pub decompressed_data_size: u64,
     pub data: Vec<u8>,
+    pub reliable: Option<u64>,
+    pub ack: u64,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -30,24 +49,60 @@ pub struct ClientMessage {
     pub session_id: u64,
     pub number: u64,
     pub data: ClientMessageData,
+    #[serde(skip)]
+    pub secret: u64,
+    pub reliable: Option<u64>,
+    pub ack: u64,
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
 pub enum ServerMessageData {
     NewPlayer {
         update_period: Duration,
-        player_id: PlayerId,
+        player_id: Option<PlayerId>,
+        capabilities: Capabilities,
+        quantization: Option<Quantization>,
+        resume_token: u64,
+        secret: u64,
     },
     Error(String),
     GameUpdate(GameUpdate),
+    QuantizedWorldUpdate {
+        ack_actor_action_world_frame: u64,
+        ack_cast_action_world_frame: u64,
+        world_update: Box<QuantizedWorldUpdate>,
+    },
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ClientMessageData {
-    Join(String),
+    Join {
+        player_name: String,
+        team: Option<TeamId>,
+        spectator: bool,
+        capabilities: Capabilities,
+        resume_token: Option<u64>,
+    },
     Quit,
     Heartbeat,
     PlayerControl(PlayerControl),
+    Chat {
+        text: String,
+        team_only: bool,
+    },
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct Capabilities {
+    pub quantized_world_updates: bool,
+}
+
+impl Capabilities {
+    pub fn intersection(&self, other: &Self) -> Self {
+        Self {
+            quantized_world_updates: self.quantized_world_updates && other.quantized_world_updates,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, PartialEq)]
@@ -63,7 +118,23 @@ pub enum GameUpdate {
         ack_cast_action_world_frame: u64,
         world_update: Box<WorldUpdate>,
     },
+    RoundResult(RoundResult),
     GameOver(String),
+    ChatMessage(ChatMessage),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct ChatMessage {
+    pub sender: Option<PlayerId>,
+    pub team: Option<TeamId>,
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct RoundResult {
+    pub round: u64,
+    pub winner: Option<PlayerId>,
+    pub next_round_time: f64,
 }
 
 #[derive(Default, Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -83,6 +154,8 @@ pub struct WorldUpdate {
     pub guns: Option<Difference<Gun, GunUpdate>>,
     pub shields: Option<Difference<Shield, ShieldUpdate>>,
     pub temp_obstacles: Option<Difference<TempObstacle, TempObstacleUpdate>>,
+    pub lightning_bolts: Option<ExistenceDifference<LightningBolt>>,
+    pub mines: Option<Difference<Mine, MineUpdate>>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
@@ -91,6 +164,10 @@ pub struct PlayerUpdate {
     pub actor_id: Option<Option<ActorId>>,
     pub spawn_time: Option<f64>,
     pub deaths: Option<u64>,
+    pub kills: Option<u64>,
+    pub assists: Option<u64>,
+    pub damage_dealt: Option<f64>,
+    pub damage_healed: Option<f64>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
@@ -110,6 +187,7 @@ pub struct ActorUpdate {
     pub position_z: Option<f64>,
     pub velocity_z: Option<f64>,
     pub occupation: Option<ActorOccupation>,
+    pub damagers: Option<Vec<PlayerId>>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
@@ -150,7 +228,14 @@ pub struct TempObstacleUpdate {
     pub effect: Option<Effect>,
 }
 
-#[derive(Default, Debug, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
+pub struct MineUpdate {
+    pub id: MineId,
+    pub detonated: Option<bool>,
+    pub deadline: Option<f64>,
+}
+
+#[derive(Default, Clone, Debug, Deserialize, Serialize)]
 pub struct PlayerControl {
     pub ack_world_frame: u64,
     pub cast_action_world_frame: u64,
@@ -164,7 +249,7 @@ pub struct ActorAction {
     pub cast_action: Option<CastAction>,
 }
 
-#[derive(Clone, Debug, Deserialize, Serialize)]
+#[derive(Copy, Clone, Debug, Deserialize, Serialize, PartialEq)]
 pub enum CastAction {
     AddSpellElement(Element),
     StartDirectedMagick,
@@ -173,6 +258,33 @@ pub enum CastAction {
     StartAreaOfEffectMagick,
 }
 
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub enum WorldEvent {
+    Damage {
+        source: Option<ObjectId>,
+        target: ObjectId,
+        physical: f64,
+        magical: [f64; 11],
+    },
+    Collision {
+        lhs: ObjectId,
+        rhs: ObjectId,
+    },
+    Cast {
+        actor_id: ActorId,
+        cast_action: CastAction,
+    },
+    Spawn {
+        actor_id: ActorId,
+        player_id: PlayerId,
+    },
+    Death {
+        actor_id: ActorId,
+        player_id: PlayerId,
+        killer: Option<PlayerId>,
+    },
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 #[serde(tag = "type")]
 pub enum HttpMessage {
@@ -195,7 +307,7 @@ pub struct Session {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub struct GameSessionInfo {
     pub session_id: u64,
-    pub player_id: u64,
+    pub player_id: Option<u64>,
     pub last_message_time: f64,
     pub last_message_number: u64,
     pub messages_per_frame: u8,
@@ -205,6 +317,8 @@ pub struct GameSessionInfo {
     pub ack_cast_action_frame: u64,
     pub since_last_message: f64,
     pub world_frame_delay: u64,
+    pub capabilities: Capabilities,
+    pub update_frequency: f64,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Copy, Clone)]
@@ -233,18 +347,61 @@ pub fn get_server_message_data_type(value: &ServerMessageData) -> &'static str {
         ServerMessageData::NewPlayer { .. } => "NewPlayer",
         ServerMessageData::Error(..) => "Error",
         ServerMessageData::GameUpdate(..) => "GameUpdate",
+        ServerMessageData::QuantizedWorldUpdate { .. } => "QuantizedWorldUpdate",
     }
 }
 
 pub fn get_client_message_data_type(value: &ClientMessageData) -> &'static str {
     match value {
-        ClientMessageData::Join(..) => "Join",
+        ClientMessageData::Join { .. } => "Join",
         ClientMessageData::Quit => "Quit",
         ClientMessageData::Heartbeat => "Heartbeat",
         ClientMessageData::PlayerControl(..) => "PlayerControl",
+        ClientMessageData::Chat { .. } => "Chat",
     }
 }
 
+pub fn is_reliable_server_message_data(value: &ServerMessageData) -> bool {
+    matches!(
+        value,
+        ServerMessageData::NewPlayer { .. }
+            | ServerMessageData::Error(..)
+            | ServerMessageData::GameUpdate(GameUpdate::RoundResult(..))
+            | ServerMessageData::GameUpdate(GameUpdate::GameOver(..))
+            | ServerMessageData::GameUpdate(GameUpdate::ChatMessage(..))
+    )
+}
+
+pub fn is_reliable_client_message_data(value: &ClientMessageData) -> bool {
+    matches!(
+        value,
+        ClientMessageData::Join { .. } | ClientMessageData::Quit | ClientMessageData::Chat { .. }
+    )
+}
+
+pub fn format_kill_feed_message(event: &WorldEvent, players: &[Player]) -> Option<String> {
+    if let WorldEvent::Death {
+        player_id, killer, ..
+    } = event
+    {
+        let get_name = |id: PlayerId| {
+            players
+                .iter()
+                .find(|v| v.id == id)
+                .map(|v| v.name.as_str())
+                .unwrap_or("unknown")
+        };
+        let victim = get_name(*player_id);
+        return Some(match killer {
+            Some(killer) if killer != player_id => {
+                format!("{} killed {}", get_name(*killer), victim)
+            }
+            _ => format!("{} died", victim),
+        });
+    }
+    None
+}
+
 pub fn make_world_update(before: &World, after: &World) -> WorldUpdate {
     WorldUpdate {
         before_frame: before.frame,
@@ -268,6 +425,11 @@ pub fn make_world_update(before: &World, after: &World) -> WorldUpdate {
             &before.temp_obstacles,
             &after.temp_obstacles,
         ),
+        lightning_bolts: get_lightning_bolts_difference(
+            &before.lightning_bolts,
+            &after.lightning_bolts,
+        ),
+        mines: get_mines_difference(&before.mines, &after.mines),
     }
 }
 
@@ -346,12 +508,27 @@ fn get_temp_obstacles_difference(
     get_difference(before, after, |v| v.id.0, make_temp_obstacle_update)
 }
 
+fn get_lightning_bolts_difference(
+    before: &[LightningBolt],
+    after: &[LightningBolt],
+) -> Option<ExistenceDifference<LightningBolt>> {
+    get_existence_difference(before, after, |v| v.id.0)
+}
+
+fn get_mines_difference(before: &[Mine], after: &[Mine]) -> Option<Difference<Mine, MineUpdate>> {
+    get_difference(before, after, |v| v.id.0, make_mine_update)
+}
+
 fn make_player_update(b: &Player, a: &Player) -> Option<PlayerUpdate> {
     let mut r = PlayerUpdate::default();
     let mut d = false;
     d = clone_if_different(&b.actor_id, &a.actor_id, &mut r.actor_id) || d;
     d = clone_if_different(&b.spawn_time, &a.spawn_time, &mut r.spawn_time) || d;
     d = clone_if_different(&b.deaths, &a.deaths, &mut r.deaths) || d;
+    d = clone_if_different(&b.kills, &a.kills, &mut r.kills) || d;
+    d = clone_if_different(&b.assists, &a.assists, &mut r.assists) || d;
+    d = clone_if_different(&b.damage_dealt, &a.damage_dealt, &mut r.damage_dealt) || d;
+    d = clone_if_different(&b.damage_healed, &a.damage_healed, &mut r.damage_healed) || d;
     if d {
         r.id = a.id;
         Some(r)
@@ -385,6 +562,7 @@ fn make_actor_update(b: &Actor, a: &Actor) -> Option<ActorUpdate> {
     d = clone_if_different(&b.position_z, &a.position_z, &mut r.position_z) || d;
     d = clone_if_different(&b.velocity_z, &a.velocity_z, &mut r.velocity_z) || d;
     d = clone_if_different(&b.occupation, &a.occupation, &mut r.occupation) || d;
+    d = clone_if_different(&b.damagers, &a.damagers, &mut r.damagers) || d;
     if d {
         r.id = a.id;
         Some(r)
@@ -461,6 +639,19 @@ fn make_temp_obstacle_update(b: &TempObstacle, a: &TempObstacle) -> Option<TempO
     }
 }
 
+fn make_mine_update(b: &Mine, a: &Mine) -> Option<MineUpdate> {
+    let mut r = MineUpdate::default();
+    let mut d = false;
+    d = clone_if_different(&b.detonated, &a.detonated, &mut r.detonated) || d;
+    d = clone_if_different(&b.deadline, &a.deadline, &mut r.deadline) || d;
+    if d {
+        r.id = a.id;
+        Some(r)
+    } else {
+        None
+    }
+}
+
 fn clone_if_different<T>(before: &T, after: &T, out: &mut Option<T>) -> bool
 where
     T: PartialEq + Clone,
@@ -656,6 +847,18 @@ pub fn apply_world_update(update: WorldUpdate, world: &mut World) {
         apply_temp_obstacle_update,
         &mut world.temp_obstacles,
     );
+    apply_existence_difference(
+        update.lightning_bolts,
+        &|v| v.id.0,
+        &mut world.lightning_bolts,
+    );
+    apply_difference(
+        update.mines,
+        &|v| v.id.0,
+        &|a, b| a.id == b.id,
+        apply_mine_update,
+        &mut world.mines,
+    );
 }
 
 fn apply_difference<T, U, GetId, EqualById, ApplyUpdate>(
@@ -711,7 +914,7 @@ fn update_from<U, EqualById, ApplyUpdate, T>(
     src: Option<Vec<U>>,
     equal_by_id: &EqualById,
     apply_update: ApplyUpdate,
-    dst: &mut Vec<T>,
+    dst: &mut [T],
 ) where
     EqualById: Fn(&U, &T) -> bool,
     ApplyUpdate: Fn(&U, &mut T),
@@ -738,6 +941,10 @@ fn apply_player_update(src: &PlayerUpdate, dst: &mut Player) {
     clone_if_some(&src.actor_id, &mut dst.actor_id);
     clone_if_some(&src.spawn_time, &mut dst.spawn_time);
     clone_if_some(&src.deaths, &mut dst.deaths);
+    clone_if_some(&src.kills, &mut dst.kills);
+    clone_if_some(&src.assists, &mut dst.assists);
+    clone_if_some(&src.damage_dealt, &mut dst.damage_dealt);
+    clone_if_some(&src.damage_healed, &mut dst.damage_healed);
 }
 
 fn apply_actor_update(src: &ActorUpdate, dst: &mut Actor) {
@@ -755,6 +962,7 @@ fn apply_actor_update(src: &ActorUpdate, dst: &mut Actor) {
     clone_if_some(&src.position_z, &mut dst.position_z);
     clone_if_some(&src.velocity_z, &mut dst.velocity_z);
     clone_if_some(&src.occupation, &mut dst.occupation);
+    clone_if_some(&src.damagers, &mut dst.damagers);
 }
 
 fn apply_projectile_update(src: &ProjectileUpdate, dst: &mut Projectile) {
@@ -785,6 +993,11 @@ fn apply_temp_obstacle_update(src: &TempObstacleUpdate, dst: &mut TempObstacle)
     clone_if_some(&src.effect, &mut dst.effect);
 }
 
+fn apply_mine_update(src: &MineUpdate, dst: &mut Mine) {
+    clone_if_some(&src.detonated, &mut dst.detonated);
+    clone_if_some(&src.deadline, &mut dst.deadline);
+}
+
 fn clone_if_some<T: Clone>(src: &Option<T>, dst: &mut T) {
     if let Some(value) = src.as_ref() {
         *dst = value.clone();
@@ -806,6 +1019,8 @@ where
     let mut sort_guns = false;
     let mut sort_shields = false;
     let mut sort_temp_obstacles = false;
+    let mut sort_lightning_bolts = false;
+    let mut sort_mines = false;
     for v in src {
         add_removed_difference(&v.actors, &mut dst.actors, &mut sort_actors);
         add_removed_difference(&v.projectiles, &mut dst.projectiles, &mut sort_projectiles);
@@ -834,6 +1049,12 @@ where
             &mut dst.temp_obstacles,
             &mut sort_temp_obstacles,
         );
+        add_removed_existence_difference(
+            &v.lightning_bolts,
+            &mut dst.lightning_bolts,
+            &mut sort_lightning_bolts,
+        );
+        add_removed_difference(&v.mines, &mut dst.mines, &mut sort_mines);
     }
     sort_and_dedup(sort_actors, dst.actors.as_mut().map(|v| v.removed.as_mut()));
     sort_and_dedup(
@@ -867,6 +1088,11 @@ where
         sort_temp_obstacles,
         dst.temp_obstacles.as_mut().map(|v| v.removed.as_mut()),
     );
+    sort_and_dedup(
+        sort_lightning_bolts,
+        dst.lightning_bolts.as_mut().map(|v| v.removed.as_mut()),
+    );
+    sort_and_dedup(sort_mines, dst.mines.as_mut().map(|v| v.removed.as_mut()));
 }
 
 fn add_removed_difference<T, U>(
@@ -931,6 +1157,316 @@ fn sort_and_dedup(sort: bool, values: Option<Option<&mut Vec<u64>>>) {
     }
 }
 
+pub fn get_player_actor_position(world: &World, player_id: PlayerId) -> Option<Vec2f> {
+    world
+        .players
+        .iter()
+        .find(|v| v.id == player_id)
+        .and_then(|v| v.actor_id)
+        .and_then(|id| world.actors.iter().find(|v| v.id == id))
+        .map(|v| v.position)
+}
+
+pub fn filter_world_area(world: &World, center: Vec2f, radius: f64) -> World {
+    let is_near = |position: Vec2f, size: f64| position.distance(center) <= radius + size;
+    let actors: Vec<Actor> = world
+        .actors
+        .iter()
+        .filter(|v| is_near(v.position, v.body.shape.radius))
+        .cloned()
+        .collect();
+    let actor_ids: HashSet<u64> = actors.iter().map(|v| v.id.0).collect();
+    let has_actor = |id: ActorId| actor_ids.contains(&id.0);
+    World {
+        frame: world.frame,
+        settings: world.settings.clone(),
+        bounds: world.bounds.clone(),
+        time: world.time,
+        id_counter: world.id_counter,
+        players: world.players.clone(),
+        projectiles: filter_cloned(&world.projectiles, |v| {
+            is_near(v.position, v.body.shape.radius)
+        }),
+        static_objects: filter_cloned(&world.static_objects, |v| {
+            is_near(v.position, get_static_shape_radius(&v.body.shape))
+        }),
+        beams: filter_cloned(&world.beams, |v| has_actor(v.actor_id)),
+        static_areas: filter_cloned(&world.static_areas, |v| {
+            is_near(v.position, get_static_area_shape_radius(&v.body.shape))
+        }),
+        temp_areas: filter_cloned(&world.temp_areas, |v| {
+            is_near(v.position, v.body.shape.radius)
+        }),
+        bounded_areas: filter_cloned(&world.bounded_areas, |v| has_actor(v.actor_id)),
+        fields: filter_cloned(&world.fields, |v| has_actor(v.actor_id)),
+        guns: filter_cloned(&world.guns, |v| has_actor(v.actor_id)),
+        shields: filter_cloned(&world.shields, |v| is_near(v.position, v.body.shape.radius)),
+        temp_obstacles: filter_cloned(&world.temp_obstacles, |v| {
+            is_near(v.position, v.body.shape.radius)
+        }),
+        lightning_bolts: filter_cloned(&world.lightning_bolts, |v| has_actor(v.actor_id)),
+        mines: filter_cloned(&world.mines, |v| is_near(v.position, v.body.shape.radius)),
+        actors,
+    }
+}
+
+fn get_static_shape_radius(shape: &StaticShape) -> f64 {
+    match shape {
+        StaticShape::CircleArc(v) => v.radius,
+        StaticShape::Disk(v) => v.radius,
+        StaticShape::Rectangle(v) => Vec2f::new(v.width, v.height).norm() / 2.0,
+    }
+}
+
+fn get_static_area_shape_radius(shape: &StaticAreaShape) -> f64 {
+    match shape {
+        StaticAreaShape::Disk(v) => v.radius,
+        StaticAreaShape::Rectangle(v) => Vec2f::new(v.width, v.height).norm() / 2.0,
+    }
+}
+
+fn filter_cloned<T: Clone, F: Fn(&T) -> bool>(values: &[T], f: F) -> Vec<T> {
+    values.iter().filter(|v| f(*v)).cloned().collect()
+}
+
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct WorldEntityIds {
+    actors: HashSet<u64>,
+    projectiles: HashSet<u64>,
+    static_objects: HashSet<u64>,
+    beams: HashSet<u64>,
+    static_areas: HashSet<u64>,
+    temp_areas: HashSet<u64>,
+    bounded_areas: HashSet<u64>,
+    fields: HashSet<u64>,
+    guns: HashSet<u64>,
+    shields: HashSet<u64>,
+    temp_obstacles: HashSet<u64>,
+    lightning_bolts: HashSet<u64>,
+    mines: HashSet<u64>,
+}
+
+impl WorldEntityIds {
+    pub fn new(world: &World) -> Self {
+        Self {
+            actors: world.actors.iter().map(|v| v.id.0).collect(),
+            projectiles: world.projectiles.iter().map(|v| v.id.0).collect(),
+            static_objects: world.static_objects.iter().map(|v| v.id.0).collect(),
+            beams: world.beams.iter().map(|v| v.id.0).collect(),
+            static_areas: world.static_areas.iter().map(|v| v.id.0).collect(),
+            temp_areas: world.temp_areas.iter().map(|v| v.id.0).collect(),
+            bounded_areas: world.bounded_areas.iter().map(|v| v.id.0).collect(),
+            fields: world.fields.iter().map(|v| v.id.0).collect(),
+            guns: world.guns.iter().map(|v| v.id.0).collect(),
+            shields: world.shields.iter().map(|v| v.id.0).collect(),
+            temp_obstacles: world.temp_obstacles.iter().map(|v| v.id.0).collect(),
+            lightning_bolts: world.lightning_bolts.iter().map(|v| v.id.0).collect(),
+            mines: world.mines.iter().map(|v| v.id.0).collect(),
+        }
+    }
+
+    pub fn intersect(&mut self, other: &Self) {
+        for (dst, src) in self.sets_mut().iter_mut().zip(other.sets().iter()) {
+            dst.retain(|v| src.contains(v));
+        }
+    }
+
+    pub fn extend(&mut self, other: &Self) {
+        for (dst, src) in self.sets_mut().iter_mut().zip(other.sets().iter()) {
+            dst.extend(src.iter().copied());
+        }
+    }
+
+    fn sets(&self) -> [&HashSet<u64>; 13] {
+        [
+            &self.actors,
+            &self.projectiles,
+            &self.static_objects,
+            &self.beams,
+            &self.static_areas,
+            &self.temp_areas,
+            &self.bounded_areas,
+            &self.fields,
+            &self.guns,
+            &self.shields,
+            &self.temp_obstacles,
+            &self.lightning_bolts,
+            &self.mines,
+        ]
+    }
+
+    fn sets_mut(&mut self) -> [&mut HashSet<u64>; 13] {
+        [
+            &mut self.actors,
+            &mut self.projectiles,
+            &mut self.static_objects,
+            &mut self.beams,
+            &mut self.static_areas,
+            &mut self.temp_areas,
+            &mut self.bounded_areas,
+            &mut self.fields,
+            &mut self.guns,
+            &mut self.shields,
+            &mut self.temp_obstacles,
+            &mut self.lightning_bolts,
+            &mut self.mines,
+        ]
+    }
+}
+
+pub fn make_known_world(
+    ack: &World,
+    world: &World,
+    after: &World,
+    known: &WorldEntityIds,
+    sent: &WorldEntityIds,
+) -> World {
+    let after_ids = WorldEntityIds::new(after);
+    World {
+        frame: ack.frame,
+        settings: ack.settings.clone(),
+        bounds: ack.bounds.clone(),
+        time: ack.time,
+        id_counter: ack.id_counter,
+        players: ack.players.clone(),
+        actors: get_known_entities(
+            &ack.actors,
+            &world.actors,
+            |v| v.id.0,
+            &known.actors,
+            &sent.actors,
+            &after_ids.actors,
+        ),
+        projectiles: get_known_entities(
+            &ack.projectiles,
+            &world.projectiles,
+            |v| v.id.0,
+            &known.projectiles,
+            &sent.projectiles,
+            &after_ids.projectiles,
+        ),
+        static_objects: get_known_entities(
+            &ack.static_objects,
+            &world.static_objects,
+            |v| v.id.0,
+            &known.static_objects,
+            &sent.static_objects,
+            &after_ids.static_objects,
+        ),
+        beams: get_known_entities(
+            &ack.beams,
+            &world.beams,
+            |v| v.id.0,
+            &known.beams,
+            &sent.beams,
+            &after_ids.beams,
+        ),
+        static_areas: get_known_entities(
+            &ack.static_areas,
+            &world.static_areas,
+            |v| v.id.0,
+            &known.static_areas,
+            &sent.static_areas,
+            &after_ids.static_areas,
+        ),
+        temp_areas: get_known_entities(
+            &ack.temp_areas,
+            &world.temp_areas,
+            |v| v.id.0,
+            &known.temp_areas,
+            &sent.temp_areas,
+            &after_ids.temp_areas,
+        ),
+        bounded_areas: get_known_entities(
+            &ack.bounded_areas,
+            &world.bounded_areas,
+            |v| v.id.0,
+            &known.bounded_areas,
+            &sent.bounded_areas,
+            &after_ids.bounded_areas,
+        ),
+        fields: get_known_entities(
+            &ack.fields,
+            &world.fields,
+            |v| v.id.0,
+            &known.fields,
+            &sent.fields,
+            &after_ids.fields,
+        ),
+        guns: get_known_entities(
+            &ack.guns,
+            &world.guns,
+            |v| v.id.0,
+            &known.guns,
+            &sent.guns,
+            &after_ids.guns,
+        ),
+        shields: get_known_entities(
+            &ack.shields,
+            &world.shields,
+            |v| v.id.0,
+            &known.shields,
+            &sent.shields,
+            &after_ids.shields,
+        ),
+        temp_obstacles: get_known_entities(
+            &ack.temp_obstacles,
+            &world.temp_obstacles,
+            |v| v.id.0,
+            &known.temp_obstacles,
+            &sent.temp_obstacles,
+            &after_ids.temp_obstacles,
+        ),
+        lightning_bolts: get_known_entities(
+            &ack.lightning_bolts,
+            &world.lightning_bolts,
+            |v| v.id.0,
+            &known.lightning_bolts,
+            &sent.lightning_bolts,
+            &after_ids.lightning_bolts,
+        ),
+        mines: get_known_entities(
+            &ack.mines,
+            &world.mines,
+            |v| v.id.0,
+            &known.mines,
+            &sent.mines,
+            &after_ids.mines,
+        ),
+    }
+}
+
+fn get_known_entities<T, GetId>(
+    ack: &[T],
+    current: &[T],
+    get_id: GetId,
+    known: &HashSet<u64>,
+    sent: &HashSet<u64>,
+    after: &HashSet<u64>,
+) -> Vec<T>
+where
+    T: Clone,
+    GetId: Fn(&T) -> u64,
+{
+    let is_removed = |id: u64| sent.contains(&id) && !after.contains(&id);
+    let mut result: Vec<T> = ack
+        .iter()
+        .filter(|v| known.contains(&get_id(v)) || is_removed(get_id(v)))
+        .cloned()
+        .collect();
+    let len = result.len();
+    for value in current.iter() {
+        let id = get_id(value);
+        if is_removed(id) && !result[0..len].iter().any(|v| get_id(v) == id) {
+            result.push(value.clone());
+        }
+    }
+    if result.len() > len {
+        result.sort_by_key(|v| get_id(v));
+    }
+    result
+}
+
 pub fn is_valid_player_name(value: &str) -> bool {
     MIN_PLAYER_NAME_LEN <= value.len()
         && value.len() <= MAX_PLAYER_NAME_LEN
@@ -946,6 +1482,10 @@ pub enum DeserializeError {
     DecompressedServerMessageDataTooLong(usize),
     ClientMessageTooLong(usize),
     DeserializeError(bincode::Error),
+    DecryptError,
+    InvalidMessagePrefix,
+    IncompatibleClientProtocolVersion(u64),
+    IncompatibleServerProtocolVersion(u64),
 }
 
 impl std::fmt::Display for DeserializeError {
@@ -972,29 +1512,199 @@ impl std::fmt::Display for DeserializeError {
                 write!(f, "Client message is tool long: {} bytes", v)
             }
             DeserializeError::DeserializeError(e) => write!(f, "{}", e),
+            DeserializeError::DecryptError => write!(f, "Failed to decrypt message"),
+            DeserializeError::InvalidMessagePrefix => write!(f, "Invalid message prefix"),
+            DeserializeError::IncompatibleClientProtocolVersion(v) => write!(
+                f,
+                "Incompatible protocol version: client={} server={}",
+                v, PROTOCOL_VERSION
+            ),
+            DeserializeError::IncompatibleServerProtocolVersion(v) => write!(
+                f,
+                "Incompatible protocol version: client={} server={}",
+                PROTOCOL_VERSION, v
+            ),
         }
     }
 }
 
-pub fn make_server_message(
+#[derive(Debug)]
+pub enum SerializeError {
+    DecompressedServerMessageDataTooLong(usize),
+    TooManyServerMessageFragments(usize),
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::DecompressedServerMessageDataTooLong(v) => write!(
+                f,
+                "Serialized data size is greater than limit: {} > {}",
+                v, MAX_DECOMPRESSED_SERVER_MESSAGE_DATA_SIZE
+            ),
+            SerializeError::TooManyServerMessageFragments(v) => write!(
+                f,
+                "Number of server message fragments is greater than limit: {} > {}",
+                v, MAX_SERVER_MESSAGE_FRAGMENTS
+            ),
+        }
+    }
+}
+
+pub fn make_server_messages(
     session_id: u64,
     number: u64,
+    reliable: Option<u64>,
+    ack: u64,
     data: &ServerMessageData,
-) -> ServerMessage {
+) -> Result<Vec<ServerMessage>, SerializeError> {
     let serialized = bincode::serialize(data).unwrap();
-    if serialized.len() > MAX_SERVER_MESSAGE_DATA_SIZE {
-        warn!(
-            "Serialized data size is greater than limit: {} > {}",
+    if serialized.len() > MAX_DECOMPRESSED_SERVER_MESSAGE_DATA_SIZE {
+        return Err(SerializeError::DecompressedServerMessageDataTooLong(
             serialized.len(),
-            MAX_SERVER_MESSAGE_DATA_SIZE
-        );
+        ));
     }
     let compressed = lz4_flex::compress(&serialized);
-    ServerMessage {
-        session_id,
-        number,
-        decompressed_data_size: serialized.len() as u64,
-        data: compressed,
+    if compressed.len() <= MAX_SERVER_MESSAGE_DATA_SIZE {
+        return Ok(vec![ServerMessage {
+            session_id,
+            number,
+            fragment: 0,
+            fragments: 1,
+            decompressed_data_size: serialized.len() as u64,
+            data: compressed,
+            reliable,
+            ack,
+        }]);
+    }
+    let chunks = compressed.chunks(MAX_SERVER_MESSAGE_DATA_SIZE);
+    let fragments = chunks.len();
+    if fragments > MAX_SERVER_MESSAGE_FRAGMENTS {
+        return Err(SerializeError::TooManyServerMessageFragments(fragments));
+    }
+    Ok(chunks
+        .enumerate()
+        .map(|(fragment, chunk)| ServerMessage {
+            session_id,
+            number,
+            fragment: fragment as u16,
+            fragments: fragments as u16,
+            decompressed_data_size: serialized.len() as u64,
+            data: chunk.to_vec(),
+            reliable,
+            ack,
+        })
+        .collect())
+}
+
+struct IncompleteServerMessage {
+    session_id: u64,
+    number: u64,
+    decompressed_data_size: u64,
+    reliable: Option<u64>,
+    ack: u64,
+    first_received: Instant,
+    received: usize,
+    fragments: Vec<Option<Vec<u8>>>,
+}
+
+#[derive(Default)]
+pub struct ServerMessageAssembler {
+    incomplete: Vec<IncompleteServerMessage>,
+}
+
+impl ServerMessageAssembler {
+    pub fn add(&mut self, message: ServerMessage, now: Instant) -> Option<ServerMessage> {
+        self.incomplete.retain(|v| {
+            let expired = now.duration_since(v.first_received) >= SERVER_MESSAGE_FRAGMENTS_TIMEOUT;
+            if expired {
+                debug!(
+                    "Drop incomplete server message {}: {}/{} fragments are received",
+                    v.number,
+                    v.received,
+                    v.fragments.len()
+                );
+            }
+            !expired
+        });
+        if message.fragments <= 1 {
+            return Some(message);
+        }
+        let fragments = message.fragments as usize;
+        let fragment = message.fragment as usize;
+        if fragment >= fragments || fragments > MAX_SERVER_MESSAGE_FRAGMENTS {
+            return None;
+        }
+        let index = match self
+            .incomplete
+            .iter()
+            .position(|v| v.session_id == message.session_id && v.number == message.number)
+        {
+            Some(v) => v,
+            None => {
+                if self.incomplete.len() >= MAX_INCOMPLETE_SERVER_MESSAGES {
+                    self.incomplete.remove(0);
+                }
+                self.incomplete.push(IncompleteServerMessage {
+                    session_id: message.session_id,
+                    number: message.number,
+                    decompressed_data_size: message.decompressed_data_size,
+                    reliable: message.reliable,
+                    ack: message.ack,
+                    first_received: now,
+                    received: 0,
+                    fragments: vec![None; fragments],
+                });
+                self.incomplete.len() - 1
+            }
+        };
+        let incomplete = &mut self.incomplete[index];
+        if incomplete.fragments.len() != fragments
+            || incomplete.decompressed_data_size != message.decompressed_data_size
+        {
+            return None;
+        }
+        if incomplete.fragments[fragment].is_none() {
+            incomplete.received += 1;
+        }
+        incomplete.fragments[fragment] = Some(message.data);
+        if incomplete.received < fragments {
+            return None;
+        }
+        let complete = self.incomplete.remove(index);
+        Some(ServerMessage {
+            session_id: complete.session_id,
+            number: complete.number,
+            fragment: 0,
+            fragments: 1,
+            decompressed_data_size: complete.decompressed_data_size,
+            data: complete.fragments.into_iter().flatten().flatten().collect(),
+            reliable: complete.reliable,
+            ack: complete.ack,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ClientMessageHeader {
+    pub protocol_version: u64,
+    pub secret: u64,
+}
+
+pub fn serialize_client_message(value: &ClientMessage) -> Vec<u8> {
+    let mut buffer = make_message_prefix(CLIENT_MESSAGE_MAGIC);
+    buffer.extend_from_slice(&value.secret.to_le_bytes());
+    bincode::serialize_into(&mut buffer, value).unwrap();
+    buffer
+}
+
+pub fn read_client_message_header(input: &[u8]) -> Option<ClientMessageHeader> {
+    match read_message_prefix(CLIENT_MESSAGE_MAGIC, input) {
+        Some((protocol_version, body)) => Some(ClientMessageHeader {
+            protocol_version,
+            secret: read_u64(body)?,
+        }),
+        None => None,
     }
 }
 
@@ -1002,14 +1712,28 @@ pub fn deserialize_client_message(input: &[u8]) -> Result<ClientMessage, Deseria
     if input.len() > MAX_CLIENT_MESSAGE_SIZE {
         return Err(DeserializeError::ClientMessageTooLong(input.len()));
     }
-    match bincode::deserialize(input) {
-        Ok(v) => Ok(v),
+    let secret = match read_client_message_header(input) {
+        Some(ClientMessageHeader {
+            protocol_version: PROTOCOL_VERSION,
+            secret,
+        }) => secret,
+        Some(v) => {
+            return Err(DeserializeError::IncompatibleClientProtocolVersion(
+                v.protocol_version,
+            ))
+        }
+        None => return Err(DeserializeError::InvalidMessagePrefix),
+    };
+    match bincode::deserialize::<ClientMessage>(&input[CLIENT_MESSAGE_HEADER_SIZE..]) {
+        Ok(v) => Ok(ClientMessage { secret, ..v }),
         Err(e) => Err(DeserializeError::DeserializeError(e)),
     }
 }
 
 pub fn serialize_server_message(value: &ServerMessage) -> Vec<u8> {
-    bincode::serialize(value).unwrap()
+    let mut buffer = make_message_prefix(SERVER_MESSAGE_MAGIC);
+    bincode::serialize_into(&mut buffer, value).unwrap();
+    buffer
 }
 
 pub fn deserialize_server_message(input: &[u8]) -> Result<ServerMessage, DeserializeError> {
@@ -1018,22 +1742,77 @@ pub fn deserialize_server_message(input: &[u8]) -> Result<ServerMessage, Deseria
             input.len(),
         ));
     }
-    match bincode::deserialize(input) {
+    if let Some(v) = get_rejected_protocol_version(input) {
+        return Err(DeserializeError::IncompatibleServerProtocolVersion(v));
+    }
+    let body = match read_message_prefix(SERVER_MESSAGE_MAGIC, input) {
+        Some((PROTOCOL_VERSION, v)) => v,
+        Some((v, _)) => return Err(DeserializeError::IncompatibleServerProtocolVersion(v)),
+        None => return Err(DeserializeError::InvalidMessagePrefix),
+    };
+    match bincode::deserialize(body) {
         Ok(v) => Ok(v),
         Err(e) => Err(DeserializeError::DeserializeError(e)),
     }
 }
 
+pub fn make_reject_message() -> Vec<u8> {
+    make_message_prefix(REJECT_MESSAGE_MAGIC)
+}
+
+pub fn get_rejected_protocol_version(input: &[u8]) -> Option<u64> {
+    match read_message_prefix(REJECT_MESSAGE_MAGIC, input) {
+        Some((v, [])) => Some(v),
+        _ => None,
+    }
+}
+
+pub fn make_challenge_message(cookie: u64) -> Vec<u8> {
+    let mut buffer = make_message_prefix(CHALLENGE_MESSAGE_MAGIC);
+    buffer.extend_from_slice(&cookie.to_le_bytes());
+    buffer
+}
+
+pub fn get_challenge_cookie(input: &[u8]) -> Option<u64> {
+    match read_message_prefix(CHALLENGE_MESSAGE_MAGIC, input) {
+        Some((_, body)) if body.len() == 8 => read_u64(body),
+        _ => None,
+    }
+}
+
+fn make_message_prefix(magic: [u8; 4]) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(MESSAGE_PREFIX_SIZE);
+    buffer.extend_from_slice(&magic);
+    buffer.extend_from_slice(&PROTOCOL_VERSION.to_le_bytes());
+    buffer
+}
+
+fn read_message_prefix(magic: [u8; 4], input: &[u8]) -> Option<(u64, &[u8])> {
+    if input.len() < MESSAGE_PREFIX_SIZE || input[0..magic.len()] != magic {
+        return None;
+    }
+    Some((
+        read_u64(&input[magic.len()..])?,
+        &input[MESSAGE_PREFIX_SIZE..],
+    ))
+}
+
+fn read_u64(input: &[u8]) -> Option<u64> {
+    let mut value = [0; 8];
+    value.copy_from_slice(input.get(0..8)?);
+    Some(u64::from_le_bytes(value))
+}
+
 pub fn deserialize_server_message_data(
     input: &[u8],
     decompressed_size: usize,
 ) -> Result<ServerMessageData, DeserializeError> {
-    if input.len() > MAX_SERVER_MESSAGE_DATA_SIZE {
+    if input.len() > MAX_SERVER_MESSAGE_FRAGMENTS * MAX_SERVER_MESSAGE_DATA_SIZE {
         return Err(DeserializeError::CompressedServerMessageDataTooLong(
             input.len(),
         ));
     }
-    if decompressed_size > MAX_SERVER_MESSAGE_DATA_SIZE {
+    if decompressed_size > MAX_DECOMPRESSED_SERVER_MESSAGE_DATA_SIZE {
         return Err(
             DeserializeError::DeclaredDecompressedServerMessageDataTooLong(decompressed_size),
         );
@@ -1042,7 +1821,7 @@ pub fn deserialize_server_message_data(
         Ok(v) => v,
         Err(e) => return Err(DeserializeError::DecompressError(e)),
     };
-    if decompressed.len() > MAX_SERVER_MESSAGE_DATA_SIZE {
+    if decompressed.len() > MAX_DECOMPRESSED_SERVER_MESSAGE_DATA_SIZE {
         return Err(DeserializeError::DecompressedServerMessageDataTooLong(
             decompressed.len(),
         ));
@@ -1058,9 +1837,10 @@ mod tests {
     use rand::rngs::SmallRng;
     use rand::{Rng, SeedableRng};
 
-    use crate::generators::generate_world;
+    use crate::generators::{generate_static_objects, generate_world};
     use crate::rect::Rectf;
     use crate::vec2::Vec2f;
+    use crate::world::MaterialType;
 
     use super::*;
 
@@ -1068,7 +1848,7 @@ mod tests {
     fn serialized_default_world_update_size() {
         assert_eq!(
             bincode::serialize(&WorldUpdate::default()).unwrap().len(),
-            36
+            38
         );
     }
 
@@ -1076,7 +1856,7 @@ mod tests {
     fn serialized_default_actor_update_size() {
         assert_eq!(
             bincode::serialize(&ActorUpdate::default()).unwrap().len(),
-            22
+            23
         );
     }
 
@@ -1224,4 +2004,331 @@ mod tests {
             34
         );
     }
+
+    fn make_large_world_snapshot() -> ServerMessageData {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let bounds = Rectf::new(Vec2f::both(-1e3), Vec2f::both(1e3));
+        let mut world = generate_world(bounds.clone(), &mut rng);
+        generate_static_objects(
+            MaterialType::Stone,
+            2000,
+            &bounds,
+            &mut world.id_counter,
+            &mut world.static_objects,
+            &mut rng,
+        );
+        ServerMessageData::GameUpdate(GameUpdate::WorldSnapshot {
+            ack_actor_action_world_frame: 0,
+            ack_cast_action_world_frame: 0,
+            world: Box::new(world),
+        })
+    }
+
+    #[test]
+    fn server_message_assembler_should_restore_fragmented_large_world_snapshot() {
+        let data = make_large_world_snapshot();
+        let messages = make_server_messages(1, 2, Some(3), 4, &data).unwrap();
+        assert!(messages.len() > 1);
+        assert!(messages.len() <= MAX_SERVER_MESSAGE_FRAGMENTS);
+        let now = Instant::now();
+        let mut assembler = ServerMessageAssembler::default();
+        let mut assembled = Vec::new();
+        for message in messages.into_iter().rev() {
+            let serialized = serialize_server_message(&message);
+            assert!(serialized.len() <= MAX_SERVER_MESSAGE_SIZE);
+            let message = deserialize_server_message(&serialized).unwrap();
+            assembled.extend(assembler.add(message, now));
+        }
+        assert_eq!(assembled.len(), 1);
+        assert_eq!(assembled[0].reliable, Some(3));
+        assert_eq!(assembled[0].ack, 4);
+        assert_eq!(
+            deserialize_server_message_data(
+                &assembled[0].data,
+                assembled[0].decompressed_data_size as usize
+            )
+            .unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn server_message_assembler_should_drop_incomplete_fragments_after_timeout() {
+        let mut messages =
+            make_server_messages(1, 2, None, 0, &make_large_world_snapshot()).unwrap();
+        let last = messages.pop().unwrap();
+        let now = Instant::now();
+        let mut assembler = ServerMessageAssembler::default();
+        for message in messages {
+            assert!(assembler.add(message, now).is_none());
+        }
+        assert!(assembler
+            .add(last, now + SERVER_MESSAGE_FRAGMENTS_TIMEOUT)
+            .is_none());
+    }
+
+    #[test]
+    fn make_server_messages_should_reject_data_requiring_too_many_fragments() {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let text: String = (0..MAX_SERVER_MESSAGE_FRAGMENTS * MAX_SERVER_MESSAGE_DATA_SIZE + 1)
+            .map(|_| rng.gen_range(' '..='~'))
+            .collect();
+        let data = ServerMessageData::GameUpdate(GameUpdate::GameOver(text));
+        assert!(matches!(
+            make_server_messages(1, 2, None, 0, &data),
+            Err(SerializeError::TooManyServerMessageFragments(v)) if v > MAX_SERVER_MESSAGE_FRAGMENTS
+        ));
+    }
+
+    #[test]
+    fn make_server_messages_should_reject_too_long_decompressed_data() {
+        let text = " ".repeat(MAX_DECOMPRESSED_SERVER_MESSAGE_DATA_SIZE);
+        let data = ServerMessageData::GameUpdate(GameUpdate::GameOver(text));
+        assert!(matches!(
+            make_server_messages(1, 2, None, 0, &data),
+            Err(SerializeError::DecompressedServerMessageDataTooLong(v)) if v > MAX_DECOMPRESSED_SERVER_MESSAGE_DATA_SIZE
+        ));
+    }
+
+    fn make_join_client_message() -> ClientMessage {
+        ClientMessage {
+            session_id: 0,
+            number: 1,
+            data: ClientMessageData::Join {
+                player_name: String::from("test"),
+                team: None,
+                spectator: false,
+                capabilities: Capabilities::default(),
+                resume_token: None,
+            },
+            secret: 42,
+            reliable: Some(1),
+            ack: 0,
+        }
+    }
+
+    #[test]
+    fn deserialize_client_message_should_restore_serialized_message() {
+        let serialized = serialize_client_message(&make_join_client_message());
+        assert_eq!(&serialized[0..4], &CLIENT_MESSAGE_MAGIC);
+        assert_eq!(
+            &serialized[4..MESSAGE_PREFIX_SIZE],
+            &PROTOCOL_VERSION.to_le_bytes()
+        );
+        assert_eq!(
+            read_client_message_header(&serialized),
+            Some(ClientMessageHeader {
+                protocol_version: PROTOCOL_VERSION,
+                secret: 42,
+            })
+        );
+        let deserialized = deserialize_client_message(&serialized).unwrap();
+        assert_eq!(deserialized.session_id, 0);
+        assert_eq!(deserialized.number, 1);
+        assert_eq!(deserialized.secret, 42);
+        assert!(matches!(
+            deserialized.data,
+            ClientMessageData::Join { player_name, .. } if player_name == "test"
+        ));
+    }
+
+    #[test]
+    fn deserialize_client_message_should_return_version_of_incompatible_client() {
+        let mut serialized = serialize_client_message(&make_join_client_message());
+        serialized[4..MESSAGE_PREFIX_SIZE].copy_from_slice(&(PROTOCOL_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            deserialize_client_message(&serialized),
+            Err(DeserializeError::IncompatibleClientProtocolVersion(v)) if v == PROTOCOL_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn deserialize_client_message_should_detect_legacy_join_message() {
+        #[derive(Serialize)]
+        enum LegacyClientMessageData {
+            Join(String),
+        }
+        let join = bincode::serialize(&(
+            0u64,
+            1u64,
+            LegacyClientMessageData::Join("a".repeat(MAX_PLAYER_NAME_LEN)),
+        ))
+        .unwrap();
+        assert!(matches!(
+            deserialize_client_message(&join),
+            Err(DeserializeError::InvalidMessagePrefix)
+        ));
+    }
+
+    #[test]
+    fn deserialize_server_message_should_return_version_of_rejecting_server() {
+        let reject = make_reject_message();
+        assert_eq!(reject.len(), MESSAGE_PREFIX_SIZE);
+        assert_eq!(
+            get_rejected_protocol_version(&reject),
+            Some(PROTOCOL_VERSION)
+        );
+        assert!(matches!(
+            deserialize_server_message(&reject),
+            Err(DeserializeError::IncompatibleServerProtocolVersion(
+                PROTOCOL_VERSION
+            ))
+        ));
+    }
+
+    #[test]
+    fn get_challenge_cookie_should_return_cookie_of_challenge_message() {
+        let challenge = make_challenge_message(42);
+        assert_eq!(challenge.len(), MESSAGE_PREFIX_SIZE + 8);
+        assert_eq!(get_challenge_cookie(&challenge), Some(42));
+        assert_eq!(get_challenge_cookie(&make_reject_message()), None);
+        assert_eq!(
+            get_challenge_cookie(&challenge[0..challenge.len() - 1]),
+            None
+        );
+    }
+
+    fn make_area_of_interest_world() -> World {
+        let mut rng = SmallRng::seed_from_u64(42);
+        let mut world = generate_world(Rectf::new(Vec2f::both(-1e2), Vec2f::both(1e2)), &mut rng);
+        for (i, actor) in world.actors.iter_mut().enumerate() {
+            actor.position = Vec2f::only_x(10.0 * i as f64);
+        }
+        world.players.push(Player {
+            id: PlayerId(1),
+            active: true,
+            name: String::from("a"),
+            team: None,
+            actor_id: Some(world.actors[0].id),
+            spawn_time: 0.0,
+            deaths: 0,
+            kills: 0,
+            assists: 0,
+            damage_dealt: 0.0,
+            damage_healed: 0.0,
+        });
+        world
+    }
+
+    #[test]
+    fn filter_world_area_should_keep_near_entities_and_all_players() {
+        let world = make_area_of_interest_world();
+        let filtered = filter_world_area(&world, Vec2f::ZERO, 15.0);
+        assert_eq!(
+            filtered.actors.iter().map(|v| v.id).collect::<Vec<_>>(),
+            vec![world.actors[0].id, world.actors[1].id]
+        );
+        assert_eq!(filtered.players, world.players);
+        assert!(!filtered.static_objects.is_empty());
+        assert!(filtered.static_objects.len() < world.static_objects.len());
+        for v in filtered.static_objects.iter() {
+            assert!(v.position.norm() <= 15.0 + get_static_shape_radius(&v.body.shape));
+        }
+        assert!(filtered.static_areas.len() < world.static_areas.len());
+        assert_eq!(
+            filter_world_area(&world, Vec2f::ZERO, 1e3).static_objects,
+            world.static_objects
+        );
+    }
+
+    #[test]
+    fn get_player_actor_position_should_return_none_for_player_without_actor() {
+        let mut world = make_area_of_interest_world();
+        assert_eq!(
+            get_player_actor_position(&world, PlayerId(1)),
+            Some(Vec2f::ZERO)
+        );
+        assert_eq!(get_player_actor_position(&world, PlayerId(2)), None);
+        world.players[0].actor_id = None;
+        assert_eq!(get_player_actor_position(&world, PlayerId(1)), None);
+    }
+
+    #[test]
+    fn world_update_should_add_entering_and_remove_leaving_area_of_interest_actors() {
+        let world_before = make_area_of_interest_world();
+        let mut world_after = world_before.clone();
+        world_after.frame += 1;
+        world_after.actors[1].position = Vec2f::only_x(-100.0);
+        world_after.actors[3].position = Vec2f::only_y(5.0);
+        let mut before = filter_world_area(&world_before, Vec2f::ZERO, 15.0);
+        let after = filter_world_area(&world_after, Vec2f::ZERO, 15.0);
+        let world_update = make_world_update(&before, &after);
+        let actors = world_update.actors.clone().unwrap();
+        assert_eq!(
+            actors
+                .added
+                .unwrap()
+                .iter()
+                .map(|v| v.id)
+                .collect::<Vec<_>>(),
+            vec![world_before.actors[3].id]
+        );
+        assert_eq!(actors.removed, Some(vec![world_before.actors[1].id.0]));
+        apply_world_update(world_update, &mut before);
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn make_known_world_should_make_update_valid_for_any_sent_world() {
+        let world = make_area_of_interest_world();
+        let ack = filter_world_area(&world, Vec2f::ZERO, 15.0);
+        let mut sent_world = world.clone();
+        sent_world.frame += 1;
+        sent_world.actors[1].position = Vec2f::only_x(-100.0);
+        sent_world.actors[3].position = Vec2f::only_y(5.0);
+        let sent = filter_world_area(&sent_world, Vec2f::ZERO, 15.0);
+        let mut after_world = world.clone();
+        after_world.frame += 2;
+        after_world.actors[2].position = Vec2f::only_y(-5.0);
+        let after = filter_world_area(&after_world, Vec2f::ZERO, 15.0);
+        let mut known = WorldEntityIds::new(&ack);
+        known.intersect(&WorldEntityIds::new(&sent));
+        let mut sent_ids = WorldEntityIds::new(&ack);
+        sent_ids.extend(&WorldEntityIds::new(&sent));
+        let before = make_known_world(&world, &after_world, &after, &known, &sent_ids);
+        let world_update = make_world_update(&before, &after);
+        for mut client_world in [ack, sent] {
+            apply_world_update(world_update.clone(), &mut client_world);
+            client_world.actors.sort_by_key(|v| v.id.0);
+            assert_eq!(client_world, after);
+        }
+    }
+
+    #[test]
+    fn format_kill_feed_message_should_name_killer_and_victim() {
+        let world = make_area_of_interest_world();
+        let mut players = world.players.clone();
+        players.push(Player {
+            id: PlayerId(2),
+            name: String::from("b"),
+            ..players[0].clone()
+        });
+        let death = |killer| WorldEvent::Death {
+            actor_id: world.actors[0].id,
+            player_id: PlayerId(1),
+            killer,
+        };
+        assert_eq!(
+            format_kill_feed_message(&death(Some(PlayerId(2))), &players),
+            Some(String::from("b killed a"))
+        );
+        assert_eq!(
+            format_kill_feed_message(&death(Some(PlayerId(1))), &players),
+            Some(String::from("a died"))
+        );
+        assert_eq!(
+            format_kill_feed_message(&death(None), &players),
+            Some(String::from("a died"))
+        );
+        assert_eq!(
+            format_kill_feed_message(
+                &WorldEvent::Spawn {
+                    actor_id: world.actors[0].id,
+                    player_id: PlayerId(1),
+                },
+                &players
+            ),
+            None
+        );
+    }
 }
@@ -0,0 +1,792 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use crate::protocol::{
+    ActorUpdate, Difference, ProjectileUpdate, ShieldUpdate, StaticObjectUpdate,
+    TempObstacleUpdate, WorldUpdate,
+};
+use crate::rect::Rectf;
+use crate::vec2::Vec2f;
+use crate::world::{ActorId, ProjectileId, ShieldId, StaticObjectId, TempObstacleId};
+
+const MAX_POSITION_BITS: u64 = 32;
+const MAX_DIRECTION_BITS: u8 = 32;
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct Quantization {
+    pub position_precision: f64,
+    pub velocity_precision: f64,
+    pub direction_bits: u8,
+    pub health_precision: f64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct QuantizedWorldUpdate {
+    pub bounds: Rectf,
+    pub world_update: WorldUpdate,
+    pub data: Vec<u8>,
+}
+
+pub fn quantize_world_update(
+    world_update: &WorldUpdate,
+    bounds: &Rectf,
+    quantization: &Quantization,
+) -> QuantizedWorldUpdate {
+    let quantizer = Quantizer::new(bounds, quantization);
+    let mut writer = BitWriter::default();
+    let mut rest = world_update.clone();
+    let actors = take_updated(&mut rest.actors);
+    writer.write_var(actors.len() as u64);
+    let mut id = 0;
+    let mut not_quantized_actors = Vec::new();
+    for actor in actors {
+        write_id(actor.id.0, &mut id, &mut writer);
+        quantizer.write_actor_update(&actor, &mut writer);
+        if let Some(v) = get_not_quantized_actor_update(actor) {
+            not_quantized_actors.push(v);
+        }
+    }
+    put_not_quantized(&mut rest.actors, not_quantized_actors);
+    let projectiles = take_updated(&mut rest.projectiles);
+    writer.write_var(projectiles.len() as u64);
+    let mut id = 0;
+    for projectile in projectiles {
+        write_id(projectile.id.0, &mut id, &mut writer);
+        quantizer.write_projectile_update(&projectile, &mut writer);
+    }
+    let static_objects = take_updated(&mut rest.static_objects);
+    writer.write_var(static_objects.len() as u64);
+    let mut id = 0;
+    let mut not_quantized_static_objects = Vec::new();
+    for static_object in static_objects {
+        write_id(static_object.id.0, &mut id, &mut writer);
+        quantizer.write_health(static_object.health, &mut writer);
+        if let Some(effect) = static_object.effect {
+            not_quantized_static_objects.push(StaticObjectUpdate {
+                id: static_object.id,
+                health: None,
+                effect: Some(effect),
+            });
+        }
+    }
+    put_not_quantized(&mut rest.static_objects, not_quantized_static_objects);
+    let shields = take_updated(&mut rest.shields);
+    writer.write_var(shields.len() as u64);
+    let mut id = 0;
+    for shield in shields {
+        write_id(shield.id.0, &mut id, &mut writer);
+        quantizer.write_health(shield.power, &mut writer);
+    }
+    let temp_obstacles = take_updated(&mut rest.temp_obstacles);
+    writer.write_var(temp_obstacles.len() as u64);
+    let mut id = 0;
+    let mut not_quantized_temp_obstacles = Vec::new();
+    for temp_obstacle in temp_obstacles {
+        write_id(temp_obstacle.id.0, &mut id, &mut writer);
+        quantizer.write_health(temp_obstacle.health, &mut writer);
+        if let Some(effect) = temp_obstacle.effect {
+            not_quantized_temp_obstacles.push(TempObstacleUpdate {
+                id: temp_obstacle.id,
+                health: None,
+                effect: Some(effect),
+            });
+        }
+    }
+    put_not_quantized(&mut rest.temp_obstacles, not_quantized_temp_obstacles);
+    QuantizedWorldUpdate {
+        bounds: bounds.clone(),
+        world_update: rest,
+        data: writer.data,
+    }
+}
+
+pub fn dequantize_world_update(
+    value: QuantizedWorldUpdate,
+    quantization: &Quantization,
+) -> Option<WorldUpdate> {
+    let QuantizedWorldUpdate {
+        bounds,
+        mut world_update,
+        data,
+    } = value;
+    let quantizer = Quantizer::new(&bounds, quantization);
+    let mut reader = BitReader::new(&data);
+    let mut actors = Vec::new();
+    let mut id = 0;
+    for _ in 0..reader.read_var()? {
+        let actor_id = ActorId(read_id(&mut id, &mut reader)?);
+        actors.push(quantizer.read_actor_update(actor_id, &mut reader)?);
+    }
+    let mut projectiles = Vec::new();
+    let mut id = 0;
+    for _ in 0..reader.read_var()? {
+        let projectile_id = ProjectileId(read_id(&mut id, &mut reader)?);
+        projectiles.push(quantizer.read_projectile_update(projectile_id, &mut reader)?);
+    }
+    let mut static_objects = Vec::new();
+    let mut id = 0;
+    for _ in 0..reader.read_var()? {
+        static_objects.push(StaticObjectUpdate {
+            id: StaticObjectId(read_id(&mut id, &mut reader)?),
+            health: quantizer.read_health(&mut reader)?,
+            effect: None,
+        });
+    }
+    let mut shields = Vec::new();
+    let mut id = 0;
+    for _ in 0..reader.read_var()? {
+        shields.push(ShieldUpdate {
+            id: ShieldId(read_id(&mut id, &mut reader)?),
+            power: quantizer.read_health(&mut reader)?,
+        });
+    }
+    let mut temp_obstacles = Vec::new();
+    let mut id = 0;
+    for _ in 0..reader.read_var()? {
+        temp_obstacles.push(TempObstacleUpdate {
+            id: TempObstacleId(read_id(&mut id, &mut reader)?),
+            health: quantizer.read_health(&mut reader)?,
+            effect: None,
+        });
+    }
+    put_updated(
+        &mut world_update.actors,
+        actors,
+        |v| v.id,
+        merge_not_quantized_actor_update,
+    );
+    put_updated(
+        &mut world_update.projectiles,
+        projectiles,
+        |v| v.id,
+        |_, _| (),
+    );
+    put_updated(
+        &mut world_update.static_objects,
+        static_objects,
+        |v| v.id,
+        |src, dst| dst.effect = src.effect,
+    );
+    put_updated(&mut world_update.shields, shields, |v| v.id, |_, _| ());
+    put_updated(
+        &mut world_update.temp_obstacles,
+        temp_obstacles,
+        |v| v.id,
+        |src, dst| dst.effect = src.effect,
+    );
+    Some(world_update)
+}
+
+fn take_updated<T, U>(difference: &mut Option<Difference<T, U>>) -> Vec<U>
+where
+    T: std::fmt::Debug + PartialEq,
+    U: std::fmt::Debug + PartialEq,
+{
+    let updated = match difference.as_mut() {
+        Some(v) => v.updated.take().unwrap_or_default(),
+        None => return Vec::new(),
+    };
+    if matches!(difference, Some(v) if v.added.is_none() && v.removed.is_none()) {
+        *difference = None;
+    }
+    updated
+}
+
+fn get_or_insert_difference<T, U>(
+    difference: &mut Option<Difference<T, U>>,
+) -> &mut Difference<T, U>
+where
+    T: std::fmt::Debug + PartialEq,
+    U: std::fmt::Debug + PartialEq,
+{
+    difference.get_or_insert_with(|| Difference {
+        added: None,
+        updated: None,
+        removed: None,
+    })
+}
+
+fn put_not_quantized<T, U>(difference: &mut Option<Difference<T, U>>, not_quantized: Vec<U>)
+where
+    T: std::fmt::Debug + PartialEq,
+    U: std::fmt::Debug + PartialEq,
+{
+    if !not_quantized.is_empty() {
+        get_or_insert_difference(difference).updated = Some(not_quantized);
+    }
+}
+
+fn put_updated<T, U, I, K, M>(
+    difference: &mut Option<Difference<T, U>>,
+    mut updated: Vec<U>,
+    get_id: K,
+    merge: M,
+) where
+    T: std::fmt::Debug + PartialEq,
+    U: std::fmt::Debug + PartialEq,
+    I: PartialEq,
+    K: Fn(&U) -> I,
+    M: Fn(U, &mut U),
+{
+    if updated.is_empty() {
+        return;
+    }
+    let difference = get_or_insert_difference(difference);
+    for not_quantized in difference.updated.take().unwrap_or_default() {
+        match updated
+            .iter_mut()
+            .find(|v| get_id(v) == get_id(&not_quantized))
+        {
+            Some(v) => merge(not_quantized, v),
+            None => updated.push(not_quantized),
+        }
+    }
+    difference.updated = Some(updated);
+}
+
+fn get_not_quantized_actor_update(value: ActorUpdate) -> Option<ActorUpdate> {
+    if value.effect.is_none()
+        && value.aura.is_none()
+        && value.spell_elements.is_none()
+        && value.delayed_magick.is_none()
+        && value.occupation.is_none()
+        && value.damagers.is_none()
+    {
+        return None;
+    }
+    Some(ActorUpdate {
+        id: value.id,
+        effect: value.effect,
+        aura: value.aura,
+        spell_elements: value.spell_elements,
+        delayed_magick: value.delayed_magick,
+        occupation: value.occupation,
+        damagers: value.damagers,
+        ..Default::default()
+    })
+}
+
+fn merge_not_quantized_actor_update(src: ActorUpdate, dst: &mut ActorUpdate) {
+    dst.effect = src.effect;
+    dst.aura = src.aura;
+    dst.spell_elements = src.spell_elements;
+    dst.delayed_magick = src.delayed_magick;
+    dst.occupation = src.occupation;
+    dst.damagers = src.damagers;
+}
+
+fn write_id(value: u64, last: &mut u64, writer: &mut BitWriter) {
+    writer.write_var(encode_zigzag(value.wrapping_sub(*last) as i64));
+    *last = value;
+}
+
+fn read_id(last: &mut u64, reader: &mut BitReader) -> Option<u64> {
+    *last = last.wrapping_add(decode_zigzag(reader.read_var()?) as u64);
+    Some(*last)
+}
+
+struct Quantizer {
+    min: Vec2f,
+    position_precision: f64,
+    position_bits: (u8, u8),
+    velocity_precision: f64,
+    direction_bits: u8,
+    health_precision: f64,
+}
+
+impl Quantizer {
+    fn new(bounds: &Rectf, quantization: &Quantization) -> Self {
+        let size = bounds.max - bounds.min;
+        Self {
+            min: bounds.min,
+            position_precision: quantization.position_precision,
+            position_bits: (
+                get_bits(size.x / quantization.position_precision),
+                get_bits(size.y / quantization.position_precision),
+            ),
+            velocity_precision: quantization.velocity_precision,
+            direction_bits: quantization.direction_bits.clamp(1, MAX_DIRECTION_BITS),
+            health_precision: quantization.health_precision,
+        }
+    }
+
+    fn write_actor_update(&self, value: &ActorUpdate, writer: &mut BitWriter) {
+        writer.write_bool(value.position.is_some());
+        writer.write_bool(value.health.is_some());
+        writer.write_bool(value.velocity.is_some());
+        writer.write_bool(value.dynamic_force.is_some());
+        writer.write_bool(value.current_direction.is_some());
+        writer.write_bool(value.target_direction.is_some());
+        writer.write_bool(value.moving.is_some());
+        writer.write_bool(value.position_z.is_some());
+        writer.write_bool(value.velocity_z.is_some());
+        if let Some(v) = value.position {
+            self.write_position(v, writer);
+        }
+        if let Some(v) = value.health {
+            write_signed(v, self.health_precision, writer);
+        }
+        if let Some(v) = value.velocity {
+            self.write_velocity(v, writer);
+        }
+        if let Some(v) = value.dynamic_force {
+            self.write_velocity(v, writer);
+        }
+        if let Some(v) = value.current_direction {
+            self.write_direction(v, writer);
+        }
+        if let Some(v) = value.target_direction {
+            self.write_direction(v, writer);
+        }
+        if let Some(v) = value.moving {
+            writer.write_bool(v);
+        }
+        if let Some(v) = value.position_z {
+            write_signed(v, self.position_precision, writer);
+        }
+        if let Some(v) = value.velocity_z {
+            write_signed(v, self.velocity_precision, writer);
+        }
+    }
+
+    fn read_actor_update(&self, id: ActorId, reader: &mut BitReader) -> Option<ActorUpdate> {
+        let position = reader.read_bool()?;
+        let health = reader.read_bool()?;
+        let velocity = reader.read_bool()?;
+        let dynamic_force = reader.read_bool()?;
+        let current_direction = reader.read_bool()?;
+        let target_direction = reader.read_bool()?;
+        let moving = reader.read_bool()?;
+        let position_z = reader.read_bool()?;
+        let velocity_z = reader.read_bool()?;
+        Some(ActorUpdate {
+            id,
+            position: read_if(position, || self.read_position(reader))?,
+            health: read_if(health, || read_signed(self.health_precision, reader))?,
+            velocity: read_if(velocity, || self.read_velocity(reader))?,
+            dynamic_force: read_if(dynamic_force, || self.read_velocity(reader))?,
+            current_direction: read_if(current_direction, || self.read_direction(reader))?,
+            target_direction: read_if(target_direction, || self.read_direction(reader))?,
+            moving: read_if(moving, || reader.read_bool())?,
+            position_z: read_if(position_z, || read_signed(self.position_precision, reader))?,
+            velocity_z: read_if(velocity_z, || read_signed(self.velocity_precision, reader))?,
+            ..Default::default()
+        })
+    }
+
+    fn write_projectile_update(&self, value: &ProjectileUpdate, writer: &mut BitWriter) {
+        writer.write_bool(value.position.is_some());
+        writer.write_bool(value.health.is_some());
+        writer.write_bool(value.velocity.is_some());
+        writer.write_bool(value.dynamic_force.is_some());
+        writer.write_bool(value.position_z.is_some());
+        writer.write_bool(value.velocity_z.is_some());
+        if let Some(v) = value.position {
+            self.write_position(v, writer);
+        }
+        if let Some(v) = value.health {
+            write_signed(v, self.health_precision, writer);
+        }
+        if let Some(v) = value.velocity {
+            self.write_velocity(v, writer);
+        }
+        if let Some(v) = value.dynamic_force {
+            self.write_velocity(v, writer);
+        }
+        if let Some(v) = value.position_z {
+            write_signed(v, self.position_precision, writer);
+        }
+        if let Some(v) = value.velocity_z {
+            write_signed(v, self.velocity_precision, writer);
+        }
+    }
+
+    fn read_projectile_update(
+        &self,
+        id: ProjectileId,
+        reader: &mut BitReader,
+    ) -> Option<ProjectileUpdate> {
+        let position = reader.read_bool()?;
+        let health = reader.read_bool()?;
+        let velocity = reader.read_bool()?;
+        let dynamic_force = reader.read_bool()?;
+        let position_z = reader.read_bool()?;
+        let velocity_z = reader.read_bool()?;
+        Some(ProjectileUpdate {
+            id,
+            position: read_if(position, || self.read_position(reader))?,
+            health: read_if(health, || read_signed(self.health_precision, reader))?,
+            velocity: read_if(velocity, || self.read_velocity(reader))?,
+            dynamic_force: read_if(dynamic_force, || self.read_velocity(reader))?,
+            position_z: read_if(position_z, || read_signed(self.position_precision, reader))?,
+            velocity_z: read_if(velocity_z, || read_signed(self.velocity_precision, reader))?,
+        })
+    }
+
+    fn write_health(&self, value: Option<f64>, writer: &mut BitWriter) {
+        writer.write_bool(value.is_some());
+        if let Some(v) = value {
+            write_signed(v, self.health_precision, writer);
+        }
+    }
+
+    fn read_health(&self, reader: &mut BitReader) -> Option<Option<f64>> {
+        let present = reader.read_bool()?;
+        read_if(present, || read_signed(self.health_precision, reader))
+    }
+
+    fn write_position(&self, value: Vec2f, writer: &mut BitWriter) {
+        let (x_bits, y_bits) = self.position_bits;
+        writer.write(
+            quantize_unsigned(value.x - self.min.x, self.position_precision, x_bits),
+            x_bits,
+        );
+        writer.write(
+            quantize_unsigned(value.y - self.min.y, self.position_precision, y_bits),
+            y_bits,
+        );
+    }
+
+    fn read_position(&self, reader: &mut BitReader) -> Option<Vec2f> {
+        let (x_bits, y_bits) = self.position_bits;
+        let x = reader.read(x_bits)?;
+        let y = reader.read(y_bits)?;
+        Some(Vec2f::new(
+            self.min.x + x as f64 * self.position_precision,
+            self.min.y + y as f64 * self.position_precision,
+        ))
+    }
+
+    fn write_velocity(&self, value: Vec2f, writer: &mut BitWriter) {
+        write_signed(value.x, self.velocity_precision, writer);
+        write_signed(value.y, self.velocity_precision, writer);
+    }
+
+    fn read_velocity(&self, reader: &mut BitReader) -> Option<Vec2f> {
+        Some(Vec2f::new(
+            read_signed(self.velocity_precision, reader)?,
+            read_signed(self.velocity_precision, reader)?,
+        ))
+    }
+
+    fn write_direction(&self, value: Vec2f, writer: &mut BitWriter) {
+        let steps = (1u64 << self.direction_bits) as f64;
+        let quantized = ((value.angle() + PI) / (2.0 * PI) * steps).round() as u64;
+        writer.write(quantized % (1 << self.direction_bits), self.direction_bits);
+    }
+
+    fn read_direction(&self, reader: &mut BitReader) -> Option<Vec2f> {
+        let steps = (1u64 << self.direction_bits) as f64;
+        let angle = reader.read(self.direction_bits)? as f64 / steps * 2.0 * PI - PI;
+        Some(Vec2f::new(angle.cos(), angle.sin()))
+    }
+}
+
+fn read_if<T, F>(present: bool, read: F) -> Option<Option<T>>
+where
+    F: FnOnce() -> Option<T>,
+{
+    if present {
+        read().map(Some)
+    } else {
+        Some(None)
+    }
+}
+
+fn get_bits(steps: f64) -> u8 {
+    let steps = (steps.ceil().max(0.0) as u64).min((1 << MAX_POSITION_BITS) - 1);
+    (64 - steps.leading_zeros()) as u8
+}
+
+fn quantize_unsigned(value: f64, precision: f64, bits: u8) -> u64 {
+    ((value / precision).round().max(0.0) as u64).min((1 << bits) - 1)
+}
+
+fn write_signed(value: f64, precision: f64, writer: &mut BitWriter) {
+    writer.write_var(encode_zigzag((value / precision).round() as i64));
+}
+
+fn read_signed(precision: f64, reader: &mut BitReader) -> Option<f64> {
+    Some(decode_zigzag(reader.read_var()?) as f64 * precision)
+}
+
+fn encode_zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn decode_zigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+#[derive(Default)]
+struct BitWriter {
+    data: Vec<u8>,
+    bits: usize,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u64, bits: u8) {
+        for i in 0..bits {
+            if self.bits / 8 == self.data.len() {
+                self.data.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                *self.data.last_mut().unwrap() |= 1 << (self.bits % 8);
+            }
+            self.bits += 1;
+        }
+    }
+
+    fn write_bool(&mut self, value: bool) {
+        self.write(value as u64, 1);
+    }
+
+    fn write_var(&mut self, mut value: u64) {
+        loop {
+            self.write(value & 0x7f, 7);
+            value >>= 7;
+            self.write_bool(value != 0);
+            if value == 0 {
+                break;
+            }
+        }
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    bits: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, bits: 0 }
+    }
+
+    fn read(&mut self, bits: u8) -> Option<u64> {
+        let mut value = 0;
+        for i in 0..bits {
+            let byte = *self.data.get(self.bits / 8)?;
+            if (byte >> (self.bits % 8)) & 1 == 1 {
+                value |= 1 << i;
+            }
+            self.bits += 1;
+        }
+        Some(value)
+    }
+
+    fn read_bool(&mut self) -> Option<bool> {
+        Some(self.read(1)? == 1)
+    }
+
+    fn read_var(&mut self) -> Option<u64> {
+        let mut value = 0;
+        let mut shift = 0;
+        loop {
+            if shift >= 64 {
+                return None;
+            }
+            value |= self.read(7)? << shift;
+            shift += 7;
+            if !self.read_bool()? {
+                break;
+            }
+        }
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::world::{Effect, Element};
+
+    use super::*;
+
+    const QUANTIZATION: Quantization = Quantization {
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+    };
+
+    fn make_bounds() -> Rectf {
+        Rectf::new(Vec2f::both(-100.0), Vec2f::both(100.0))
+    }
+
+    fn make_actor_update(id: u64) -> ActorUpdate {
+        let k = id as f64;
+        ActorUpdate {
+            id: ActorId(id),
+            position: Some(Vec2f::new(-42.123_456 + k, 13.987_654 - k)),
+            health: Some(0.876_543),
+            velocity: Some(Vec2f::new(3.123_45, -2.765_43)),
+            current_direction: Some(Vec2f::I.rotated(0.3 * k)),
+            target_direction: Some(Vec2f::I),
+            moving: Some(true),
+            position_z: Some(0.5),
+            velocity_z: Some(-1.25),
+            ..Default::default()
+        }
+    }
+
+    fn make_world_update(actors: u64) -> WorldUpdate {
+        let mut actor_updates: Vec<ActorUpdate> = (1..=actors).map(make_actor_update).collect();
+        actor_updates[0].spell_elements = Some(vec![Element::Fire, Element::Arcane]);
+        WorldUpdate {
+            before_frame: 1,
+            after_frame: 2,
+            time: 0.5,
+            actors: Some(Difference {
+                added: None,
+                updated: Some(actor_updates),
+                removed: Some(vec![100]),
+            }),
+            projectiles: Some(Difference {
+                added: None,
+                updated: Some(vec![ProjectileUpdate {
+                    id: ProjectileId(1000),
+                    position: Some(Vec2f::new(99.999, -99.999)),
+                    velocity: Some(Vec2f::only_x(30.0)),
+                    position_z: Some(1.0),
+                    ..Default::default()
+                }]),
+                removed: None,
+            }),
+            static_objects: Some(Difference {
+                added: None,
+                updated: Some(vec![
+                    StaticObjectUpdate {
+                        id: StaticObjectId(2000),
+                        health: Some(0.345_678),
+                        effect: Some(Effect::default()),
+                    },
+                    StaticObjectUpdate {
+                        id: StaticObjectId(2001),
+                        health: Some(0.5),
+                        effect: None,
+                    },
+                ]),
+                removed: None,
+            }),
+            shields: Some(Difference {
+                added: None,
+                updated: Some(vec![ShieldUpdate {
+                    id: ShieldId(3000),
+                    power: Some(0.123_456),
+                }]),
+                removed: None,
+            }),
+            temp_obstacles: Some(Difference {
+                added: None,
+                updated: Some(vec![TempObstacleUpdate {
+                    id: TempObstacleId(4000),
+                    health: None,
+                    effect: Some(Effect::default()),
+                }]),
+                removed: None,
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn assert_near(actual: Option<f64>, expected: Option<f64>, precision: f64) {
+        match (actual, expected) {
+            (Some(a), Some(e)) => assert!((a - e).abs() <= precision / 2.0, "{} {}", a, e),
+            (a, e) => assert_eq!(a, e),
+        }
+    }
+
+    fn assert_near_vec2f(actual: Option<Vec2f>, expected: Option<Vec2f>, precision: f64) {
+        assert_near(actual.map(|v| v.x), expected.map(|v| v.x), precision);
+        assert_near(actual.map(|v| v.y), expected.map(|v| v.y), precision);
+    }
+
+    #[test]
+    fn dequantized_world_update_should_match_original_within_precision() {
+        let world_update = make_world_update(3);
+        let quantized = quantize_world_update(&world_update, &make_bounds(), &QUANTIZATION);
+        let dequantized = dequantize_world_update(quantized, &QUANTIZATION).unwrap();
+        assert_eq!(dequantized.before_frame, world_update.before_frame);
+        assert_eq!(dequantized.after_frame, world_update.after_frame);
+        assert_eq!(dequantized.time, world_update.time);
+        let actors = dequantized.actors.unwrap();
+        let expected_actors = world_update.actors.unwrap();
+        assert_eq!(actors.removed, expected_actors.removed);
+        let direction_precision = 2.0 * PI / (1 << QUANTIZATION.direction_bits) as f64;
+        for (a, e) in actors
+            .updated
+            .unwrap()
+            .iter()
+            .zip(expected_actors.updated.unwrap().iter())
+        {
+            assert_eq!(a.id, e.id);
+            assert_near_vec2f(a.position, e.position, QUANTIZATION.position_precision);
+            assert_near(a.health, e.health, QUANTIZATION.health_precision);
+            assert_near_vec2f(a.velocity, e.velocity, QUANTIZATION.velocity_precision);
+            assert_near_vec2f(
+                a.current_direction,
+                e.current_direction,
+                direction_precision,
+            );
+            assert_eq!(a.target_direction, e.target_direction);
+            assert_eq!(a.moving, e.moving);
+            assert_eq!(a.spell_elements, e.spell_elements);
+            assert_near(a.position_z, e.position_z, QUANTIZATION.position_precision);
+            assert_near(a.velocity_z, e.velocity_z, QUANTIZATION.velocity_precision);
+        }
+        let projectiles = dequantized.projectiles.unwrap().updated.unwrap();
+        let expected_projectiles = world_update.projectiles.unwrap().updated.unwrap();
+        assert_eq!(projectiles.len(), 1);
+        assert_eq!(projectiles[0].id, expected_projectiles[0].id);
+        assert_near_vec2f(
+            projectiles[0].position,
+            expected_projectiles[0].position,
+            QUANTIZATION.position_precision,
+        );
+        assert_eq!(projectiles[0].health, None);
+        let static_objects = dequantized.static_objects.unwrap().updated.unwrap();
+        let expected_static_objects = world_update.static_objects.unwrap().updated.unwrap();
+        assert_eq!(static_objects.len(), expected_static_objects.len());
+        for (a, e) in static_objects.iter().zip(expected_static_objects.iter()) {
+            assert_eq!(a.id, e.id);
+            assert_near(a.health, e.health, QUANTIZATION.health_precision);
+            assert_eq!(a.effect, e.effect);
+        }
+        let shields = dequantized.shields.unwrap().updated.unwrap();
+        let expected_shields = world_update.shields.unwrap().updated.unwrap();
+        assert_eq!(shields.len(), 1);
+        assert_eq!(shields[0].id, expected_shields[0].id);
+        assert_near(
+            shields[0].power,
+            expected_shields[0].power,
+            QUANTIZATION.health_precision,
+        );
+        assert_eq!(
+            dequantized.temp_obstacles.unwrap().updated,
+            world_update.temp_obstacles.unwrap().updated
+        );
+    }
+
+    #[test]
+    fn quantized_world_update_should_be_smaller_than_original() {
+        let world_update = make_world_update(100);
+        let quantized = quantize_world_update(&world_update, &make_bounds(), &QUANTIZATION);
+        let original_size = bincode::serialize(&world_update).unwrap().len();
+        let quantized_size = bincode::serialize(&quantized).unwrap().len();
+        assert!(
+            quantized_size * 3 < original_size,
+            "{} {}",
+            quantized_size,
+            original_size
+        );
+    }
+
+    #[test]
+    fn dequantize_world_update_should_fail_on_truncated_data() {
+        let mut quantized =
+            quantize_world_update(&make_world_update(3), &make_bounds(), &QUANTIZATION);
+        quantized.data.truncate(quantized.data.len() / 2);
+        assert_eq!(dequantize_world_update(quantized, &QUANTIZATION), None);
+    }
+}
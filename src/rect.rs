@@ -20,6 +20,34 @@ impl Rectf {
     pub fn height(&self) -> f64 {
         self.max.y - self.min.y
     }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.min.x <= other.max.x
+            && other.min.x <= self.max.x
+            && self.min.y <= other.max.y
+            && other.min.y <= self.max.y
+    }
+
+    pub fn extended(&self, margin: f64) -> Self {
+        Self {
+            min: self.min - Vec2f::both(margin),
+            max: self.max + Vec2f::both(margin),
+        }
+    }
+
+    pub fn translated(&self, shift: Vec2f) -> Self {
+        Self {
+            min: self.min + shift,
+            max: self.max + shift,
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: Vec2f::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: Vec2f::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
 }
 
 impl PartialEq for Rectf {
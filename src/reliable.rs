@@ -0,0 +1,145 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+pub const RELIABLE_RESEND_PERIOD: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Clone)]
+pub struct ReliableSender<T> {
+    max_pending: usize,
+    number: u64,
+    pending: VecDeque<(u64, T)>,
+    sent: usize,
+    last_send: Option<Instant>,
+}
+
+impl<T> ReliableSender<T> {
+    pub fn new(max_pending: usize) -> Self {
+        Self {
+            max_pending,
+            number: 0,
+            pending: VecDeque::new(),
+            sent: 0,
+            last_send: None,
+        }
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn push(&mut self, value: T) -> Option<u64> {
+        if self.pending.len() >= self.max_pending {
+            return None;
+        }
+        self.number += 1;
+        self.pending.push_back((self.number, value));
+        Some(self.number)
+    }
+
+    pub fn ack(&mut self, number: u64) {
+        while matches!(self.pending.front(), Some((v, _)) if *v <= number) {
+            self.pending.pop_front();
+            self.sent = self.sent.saturating_sub(1);
+        }
+    }
+
+    pub fn resend(&mut self) {
+        self.last_send = None;
+    }
+
+    pub fn poll(&mut self, now: Instant) -> impl Iterator<Item = &(u64, T)> {
+        let sent = if matches!(self.last_send, Some(v) if now - v < RELIABLE_RESEND_PERIOD) {
+            self.sent
+        } else {
+            self.last_send = Some(now);
+            0
+        };
+        self.sent = self.pending.len();
+        self.pending.iter().skip(sent)
+    }
+
+    pub fn clear(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.number = 0;
+        self.sent = 0;
+        self.last_send = None;
+        self.pending.drain(..).map(|(_, v)| v)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ReliableReceiver {
+    number: u64,
+}
+
+impl ReliableReceiver {
+    pub fn ack(&self) -> u64 {
+        self.number
+    }
+
+    pub fn accept(&mut self, number: u64) -> bool {
+        if number != self.number + 1 {
+            return false;
+        }
+        self.number = number;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reliable_sender_should_send_new_messages_and_resend_not_acknowledged_after_period() {
+        let now = Instant::now();
+        let mut sender = ReliableSender::new(3);
+        assert_eq!(sender.push("a"), Some(1));
+        assert_eq!(sender.push("b"), Some(2));
+        assert_eq!(
+            sender.poll(now).cloned().collect::<Vec<_>>(),
+            vec![(1, "a"), (2, "b")]
+        );
+        assert_eq!(sender.push("c"), Some(3));
+        assert_eq!(sender.push("d"), None);
+        assert_eq!(
+            sender.poll(now).cloned().collect::<Vec<_>>(),
+            vec![(3, "c")]
+        );
+        assert_eq!(sender.poll(now).count(), 0);
+        sender.ack(2);
+        assert_eq!(sender.pending(), 1);
+        assert_eq!(
+            sender
+                .poll(now + RELIABLE_RESEND_PERIOD)
+                .cloned()
+                .collect::<Vec<_>>(),
+            vec![(3, "c")]
+        );
+        sender.resend();
+        assert_eq!(
+            sender.poll(now).cloned().collect::<Vec<_>>(),
+            vec![(3, "c")]
+        );
+    }
+
+    #[test]
+    fn reliable_sender_clear_should_return_pending_messages_and_restart_numbers() {
+        let mut sender = ReliableSender::new(3);
+        sender.push("a");
+        sender.push("b");
+        sender.ack(1);
+        assert_eq!(sender.clear().collect::<Vec<_>>(), vec!["b"]);
+        assert_eq!(sender.pending(), 0);
+        assert_eq!(sender.push("c"), Some(1));
+    }
+
+    #[test]
+    fn reliable_receiver_should_accept_only_next_number() {
+        let mut receiver = ReliableReceiver::default();
+        assert!(!receiver.accept(2));
+        assert!(receiver.accept(1));
+        assert!(!receiver.accept(1));
+        assert!(receiver.accept(2));
+        assert_eq!(receiver.ack(), 2);
+    }
+}
@@ -1,4 +1,6 @@
+use std::collections::hash_map::RandomState;
 use std::collections::VecDeque;
+use std::hash::{BuildHasher, Hash, Hasher};
 use std::net::SocketAddr;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
@@ -14,24 +16,39 @@ use rand::{Rng, SeedableRng};
 use serde::Deserialize;
 use tokio::net::UdpSocket;
 
-use crate::control::apply_actor_action;
-use crate::engine::{get_next_id, remove_player, Engine};
+use crate::bot::{get_bot_name, update_bots, Bot};
+use crate::encryption::{Direction, Encryption, EncryptionKey};
+use crate::engine::{get_next_id, Engine};
+use crate::game_mode::{reset_world, GameMode, GameModeEvent, GameModeSettings, GameModeType};
 use crate::generators::{generate_world, make_rng};
 use crate::meters::{measure, DurationMovingAverage, FpsMovingAverage};
 use crate::protocol::{
-    add_all_removed, deserialize_client_message, get_client_message_data_type,
-    is_valid_player_name, make_server_message, make_world_update, serialize_server_message,
-    ActorAction, ClientMessage, ClientMessageData, GameSessionInfo, GameUpdate, HttpMessage,
-    Metric, ServerMessage, ServerMessageData, ServerStatus, Session, UdpSessionState, WorldUpdate,
-    HEARTBEAT_PERIOD,
+    add_all_removed, deserialize_client_message, filter_world_area, format_kill_feed_message,
+    get_client_message_data_type, get_player_actor_position, is_reliable_server_message_data,
+    is_valid_player_name, make_challenge_message, make_known_world, make_reject_message,
+    make_server_messages, make_world_update, read_client_message_header, serialize_server_message,
+    ActorAction, Capabilities, ChatMessage, ClientMessage, ClientMessageData, GameSessionInfo,
+    GameUpdate, HttpMessage, Metric, ServerMessage, ServerMessageData, ServerStatus, Session,
+    UdpSessionState, WorldEntityIds, WorldEvent, WorldUpdate, HEARTBEAT_PERIOD,
+    MAX_CHAT_MESSAGE_LEN, MAX_SERVER_MESSAGE_SIZE, PROTOCOL_VERSION,
 };
+use crate::quantization::{quantize_world_update, Quantization};
 use crate::rect::Rectf;
+use crate::reliable::{ReliableReceiver, ReliableSender};
 use crate::vec2::Vec2f;
-use crate::world::{load_world, Player, PlayerId, World};
+use crate::world::{load_world, Player, PlayerId, TeamId, World};
 
 const MAX_SESSION_MESSAGES_PER_FRAME: u8 = 3;
+const MAX_SESSION_CHAT_MESSAGES_PER_PERIOD: usize = 5;
+const SESSION_CHAT_PERIOD: Duration = Duration::from_secs(5);
 const MAX_DELAYED_MESSAGES_PER_SESSION: usize = 10;
 const MAX_WORLD_HISTORY_SIZE: usize = 120;
+const COOKIE_PERIOD: Duration = Duration::from_secs(10);
+const MAX_PENDING_RELIABLE_MESSAGES_PER_SESSION: usize = 64;
+const MAX_SESSION_UPDATE_INTERVAL: u64 = 8;
+const MAX_SESSION_ACK_DELAY: Duration = Duration::from_millis(250);
+const SESSION_CONGESTION_BACKOFF_PERIOD: Duration = Duration::from_millis(250);
+const SESSION_CONGESTION_RECOVERY_PERIOD: Duration = Duration::from_secs(1);
 
 #[derive(Clap, Debug)]
 pub struct ServerParams {
@@ -59,6 +76,36 @@ pub struct ServerParams {
     pub http_max_connections: usize,
     #[clap(long)]
     pub world: Option<String>,
+    #[clap(long, default_value = "free-for-all")]
+    pub game_mode: GameModeType,
+    #[clap(long, default_value = "20")]
+    pub score_limit: u64,
+    #[clap(long, default_value = "600")]
+    pub time_limit: f64,
+    #[clap(long, default_value = "10")]
+    pub intermission: f64,
+    #[clap(long, default_value = "0")]
+    pub teams: u8,
+    #[clap(long, default_value = "0")]
+    pub bots: usize,
+    #[clap(long, default_value = "0.2")]
+    pub max_lag_compensation: f64,
+    #[clap(long, default_value = "0.001")]
+    pub position_precision: f64,
+    #[clap(long, default_value = "0.001")]
+    pub velocity_precision: f64,
+    #[clap(long, default_value = "12")]
+    pub direction_bits: u8,
+    #[clap(long, default_value = "0.0001")]
+    pub health_precision: f64,
+    #[clap(long)]
+    pub disable_quantized_world_updates: bool,
+    #[clap(long)]
+    pub encryption_key: Option<EncryptionKey>,
+    #[clap(long, default_value = "10")]
+    pub resume_timeout: f64,
+    #[clap(long)]
+    pub area_of_interest_radius: Option<f64>,
 }
 
 pub fn run_server(params: ServerParams, stop: Arc<AtomicBool>) {
@@ -89,6 +136,7 @@ pub fn run_server(params: ServerParams, stop: Arc<AtomicBool>) {
             max_sessions: params.max_sessions,
             update_period,
             session_timeout: Duration::from_secs_f64(params.udp_session_timeout),
+            encryption_key: params.encryption_key.clone(),
         },
         client_sender,
         server_receiver,
@@ -96,19 +144,12 @@ pub fn run_server(params: ServerParams, stop: Arc<AtomicBool>) {
         stop_udp_server.clone(),
     );
     let mut world_rng = make_rng(params.random_seed);
-    let world = if let Some(world_path) = params.world.as_ref() {
-        match load_world(world_path) {
-            Ok(v) => v,
-            Err(e) => {
-                error!("Failed to read world from \"{}\": {}", world_path, e);
-                return;
-            }
+    let world = match make_world(params.world.as_deref(), &mut world_rng) {
+        Ok(v) => v,
+        Err(e) => {
+            error!("{}", e);
+            return;
         }
-    } else {
-        generate_world(
-            Rectf::new(Vec2f::both(-1e2), Vec2f::both(1e2)),
-            &mut world_rng,
-        )
     };
     run_game_server(
         world,
@@ -117,6 +158,27 @@ pub fn run_server(params: ServerParams, stop: Arc<AtomicBool>) {
             max_players: params.max_players,
             update_period,
             session_timeout: Duration::from_secs_f64(params.game_session_timeout),
+            world_path: params.world,
+            game_mode: GameModeSettings {
+                mode_type: params.game_mode,
+                score_limit: params.score_limit,
+                time_limit: params.time_limit,
+                intermission: params.intermission,
+            },
+            teams: params.teams,
+            bots: params.bots,
+            max_lag_compensation: Duration::from_secs_f64(params.max_lag_compensation),
+            quantization: Quantization {
+                position_precision: params.position_precision,
+                velocity_precision: params.velocity_precision,
+                direction_bits: params.direction_bits,
+                health_precision: params.health_precision,
+            },
+            capabilities: Capabilities {
+                quantized_world_updates: !params.disable_quantized_world_updates,
+            },
+            resume_timeout: Duration::from_secs_f64(params.resume_timeout),
+            area_of_interest_radius: params.area_of_interest_radius,
         },
         server_sender,
         client_receiver,
@@ -137,6 +199,18 @@ pub fn run_server(params: ServerParams, stop: Arc<AtomicBool>) {
     );
 }
 
+fn make_world<R: Rng>(world_path: Option<&str>, rng: &mut R) -> Result<World, String> {
+    if let Some(world_path) = world_path {
+        load_world(world_path)
+            .map_err(|e| format!("Failed to read world from \"{}\": {}", world_path, e))
+    } else {
+        Ok(generate_world(
+            Rectf::new(Vec2f::both(-1e2), Vec2f::both(1e2)),
+            rng,
+        ))
+    }
+}
+
 pub enum UdpAdminMessage {
     GetSessions(tokio::sync::mpsc::Sender<Vec<UdpSession>>),
 }
@@ -150,6 +224,10 @@ pub enum GameAdminMessage {
     },
     GetStatus(tokio::sync::mpsc::Sender<ServerStatus>),
     GetWorld(tokio::sync::mpsc::Sender<Box<World>>),
+    Announce {
+        text: String,
+        response: tokio::sync::mpsc::Sender<Result<(), String>>,
+    },
 }
 
 pub enum InternalServerMessage {
@@ -166,6 +244,7 @@ pub struct UdpServerSettings {
     pub max_sessions: usize,
     pub update_period: Duration,
     pub session_timeout: Duration,
+    pub encryption_key: Option<EncryptionKey>,
 }
 
 pub fn run_background_udp_sever(
@@ -204,6 +283,11 @@ pub async fn run_udp_server(
             settings.session_timeout, HEARTBEAT_PERIOD
         );
     }
+    let mut rng = StdRng::from_entropy();
+    let encryption = settings
+        .encryption_key
+        .as_ref()
+        .map(|v| Encryption::new(v, rng.gen()));
     UdpServer {
         socket: UdpSocket::bind(&settings.address).await?,
         stop,
@@ -211,10 +295,13 @@ pub async fn run_udp_server(
         sender,
         client_receiver,
         admin_receiver,
-        recv_buffer: vec![0u8; 65_507],
+        recv_buffer: vec![0u8; MAX_SERVER_MESSAGE_SIZE],
         sessions: Vec::new(),
-        rng: StdRng::from_entropy(),
+        rng,
         message_counter: 0,
+        cookie_key: RandomState::new(),
+        start_time: Instant::now(),
+        encryption,
     }
     .run()
     .await;
@@ -232,14 +319,20 @@ struct UdpServer {
     sessions: Vec<UdpSession>,
     rng: StdRng,
     message_counter: u64,
+    cookie_key: RandomState,
+    start_time: Instant,
+    encryption: Option<Encryption>,
 }
 
 #[derive(Clone)]
 pub struct UdpSession {
     peer: SocketAddr,
     session_id: u64,
+    secret: u64,
     last_recv_time: Instant,
     state: UdpSessionState,
+    reliable_sender: ReliableSender<Arc<ServerMessageData>>,
+    reliable_receiver: ReliableReceiver,
 }
 
 impl UdpServer {
@@ -257,6 +350,7 @@ impl UdpServer {
             self.clean_udp_sessions(stop).await;
             self.handle_game_messages().await;
             self.handle_admin_messages().await;
+            self.send_reliable_messages().await;
             if !stop {
                 self.receive_messages(last_update).await;
             }
@@ -268,31 +362,31 @@ impl UdpServer {
         let now = Instant::now();
         let session_timeout = self.settings.session_timeout;
         for session in self.sessions.iter() {
-            let quit = if session_timeout <= now - session.last_recv_time {
+            if session_timeout <= now - session.last_recv_time {
                 warn!("UDP session {} is timed out", session.session_id);
-                true
-            } else {
-                stop
-            };
-            if quit {
+            }
+            if stop {
                 self.sender
                     .send(ClientMessage {
                         session_id: session.session_id,
                         number: u64::MAX,
                         data: ClientMessageData::Quit,
+                        secret: session.secret,
+                        reliable: None,
+                        ack: 0,
                     })
                     .ok();
             }
         }
         if stop {
-            self.send_broadcast_server_message(&ServerMessageData::GameUpdate(
+            self.send_broadcast_server_message(ServerMessageData::GameUpdate(
                 GameUpdate::GameOver(String::from("Server is stopped")),
             ))
             .await;
             self.sessions.clear();
         } else {
             self.sessions.retain(|v| {
-                !matches!(v.state, UdpSessionState::Done)
+                (!matches!(v.state, UdpSessionState::Done) || v.reliable_sender.pending() > 0)
                     && now - v.last_recv_time < session_timeout
             });
         }
@@ -302,10 +396,10 @@ impl UdpServer {
         while let Ok(message) = self.client_receiver.try_recv() {
             match message {
                 InternalServerMessage::Unicast { session_id, data } => {
-                    self.send_unicast_server_message(session_id, &data).await;
+                    self.send_unicast_server_message(session_id, data).await;
                 }
                 InternalServerMessage::Broadcast(data) => {
-                    self.send_broadcast_server_message(&data).await;
+                    self.send_broadcast_server_message(data).await;
                 }
             }
         }
@@ -321,45 +415,185 @@ impl UdpServer {
         }
     }
 
-    async fn send_unicast_server_message(&mut self, session_id: u64, data: &ServerMessageData) {
+    async fn send_unicast_server_message(&mut self, session_id: u64, data: ServerMessageData) {
         if let Some(session) = self
             .sessions
             .iter_mut()
             .find(|v| v.session_id == session_id)
         {
-            match data {
+            match &data {
                 ServerMessageData::NewPlayer { .. } => session.state = UdpSessionState::Established,
                 ServerMessageData::GameUpdate(GameUpdate::GameOver(..)) => {
                     session.state = UdpSessionState::Done
                 }
                 _ => (),
             }
+            if is_reliable_server_message_data(&data) {
+                push_reliable_server_message(session, Arc::new(data));
+                send_reliable_server_messages(
+                    &self.socket,
+                    self.encryption.as_ref(),
+                    &mut self.message_counter,
+                    session,
+                    Instant::now(),
+                )
+                .await;
+                return;
+            }
             self.message_counter += 1;
-            send_server_message(
-                &self.socket,
-                session,
-                &make_server_message(session.session_id, self.message_counter, data),
-            )
-            .await;
+            let server_messages = match make_server_messages(
+                session.session_id,
+                self.message_counter,
+                None,
+                session.reliable_receiver.ack(),
+                &data,
+            ) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        "Failed to make server message for session {}: {}",
+                        session.session_id, e
+                    );
+                    return;
+                }
+            };
+            for server_message in server_messages.iter() {
+                send_server_message(
+                    &self.socket,
+                    self.encryption.as_ref(),
+                    session,
+                    server_message,
+                )
+                .await;
+            }
         }
     }
 
-    async fn send_broadcast_server_message(&mut self, data: &ServerMessageData) {
-        if self
+    async fn send_broadcast_server_message(&mut self, data: ServerMessageData) {
+        if !self
             .sessions
             .iter()
             .any(|v| matches!(v.state, UdpSessionState::Established))
         {
-            self.message_counter += 1;
-            let mut server_message = make_server_message(0, self.message_counter, data);
+            return;
+        }
+        if is_reliable_server_message_data(&data) {
+            let data = Arc::new(data);
+            let now = Instant::now();
             for session in self
                 .sessions
-                .iter()
+                .iter_mut()
                 .filter(|v| matches!(v.state, UdpSessionState::Established))
             {
+                push_reliable_server_message(session, data.clone());
+                send_reliable_server_messages(
+                    &self.socket,
+                    self.encryption.as_ref(),
+                    &mut self.message_counter,
+                    session,
+                    now,
+                )
+                .await;
+            }
+            return;
+        }
+        self.message_counter += 1;
+        let mut server_messages =
+            match make_server_messages(0, self.message_counter, None, 0, &data) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!("Failed to make broadcast server message: {}", e);
+                    return;
+                }
+            };
+        for session in self
+            .sessions
+            .iter()
+            .filter(|v| matches!(v.state, UdpSessionState::Established))
+        {
+            for server_message in server_messages.iter_mut() {
                 server_message.session_id = session.session_id;
-                send_server_message(&self.socket, session, &server_message).await;
+                server_message.ack = session.reliable_receiver.ack();
+                send_server_message(
+                    &self.socket,
+                    self.encryption.as_ref(),
+                    session,
+                    server_message,
+                )
+                .await;
+            }
+        }
+    }
+
+    async fn send_reliable_messages(&mut self) {
+        let now = Instant::now();
+        for session in self.sessions.iter_mut() {
+            send_reliable_server_messages(
+                &self.socket,
+                self.encryption.as_ref(),
+                &mut self.message_counter,
+                session,
+                now,
+            )
+            .await;
+        }
+    }
+
+    async fn send_challenge(&mut self, peer: SocketAddr, cookie: u64) {
+        if let Err(e) = self
+            .socket
+            .send_to(&make_challenge_message(cookie), peer)
+            .await
+        {
+            warn!("Failed to send challenge to {}: {}", peer, e);
+        }
+    }
+
+    async fn reject_peer(&mut self, peer: SocketAddr, protocol_version: u64) {
+        info!(
+            "Reject client from {} with incompatible protocol version {}",
+            peer, protocol_version
+        );
+        if let Err(e) = self.socket.send_to(&make_reject_message(), peer).await {
+            warn!("Failed to send reject message to {}: {}", peer, e);
+        }
+    }
+
+    fn get_cookie_period(&self, now: Instant) -> u64 {
+        (now - self.start_time).as_secs() / COOKIE_PERIOD.as_secs()
+    }
+
+    fn make_cookie(&self, peer: &SocketAddr, period: u64) -> u64 {
+        let mut hasher = self.cookie_key.build_hasher();
+        peer.hash(&mut hasher);
+        period.hash(&mut hasher);
+        hasher.finish().max(1)
+    }
+
+    fn is_valid_cookie(&self, peer: &SocketAddr, cookie: u64, now: Instant) -> bool {
+        let period = self.get_cookie_period(now);
+        cookie != 0
+            && (is_same_secret(cookie, self.make_cookie(peer, period))
+                || (period > 0 && is_same_secret(cookie, self.make_cookie(peer, period - 1))))
+    }
+
+    fn add_session(&mut self, peer: SocketAddr) -> u64 {
+        loop {
+            let session_id = self.rng.gen();
+            if session_id == 0 || self.sessions.iter().any(|v| v.session_id == session_id) {
+                continue;
             }
+            info!("New UDP session {} from {}", session_id, peer);
+            self.sessions.push(UdpSession {
+                session_id,
+                secret: self.rng.gen(),
+                peer,
+                last_recv_time: Instant::now(),
+                state: UdpSessionState::New,
+                reliable_sender: ReliableSender::new(MAX_PENDING_RELIABLE_MESSAGES_PER_SESSION),
+                reliable_receiver: ReliableReceiver::default(),
+            });
+            break session_id;
         }
     }
 
@@ -375,26 +609,97 @@ impl UdpServer {
                 tokio::time::timeout(recv_timeout, self.socket.recv_from(&mut self.recv_buffer))
                     .await
             {
-                let session_id =
-                    if let Some(session) = self.sessions.iter_mut().find(|v| v.peer == peer) {
-                        session.last_recv_time = Instant::now();
-                        session.session_id
-                    } else if self.sessions.len() < self.settings.max_sessions {
-                        loop {
-                            let session_id = self.rng.gen();
-                            if self.sessions.iter_mut().any(|v| v.session_id == session_id) {
+                let decrypted;
+                let (encrypted_header, input) = match self.encryption.as_ref() {
+                    Some(encryption) => {
+                        match encryption
+                            .decrypt(Direction::ClientToServer, &self.recv_buffer[0..size])
+                        {
+                            Ok((header, data)) => {
+                                decrypted = data;
+                                (Some(header), decrypted.as_slice())
+                            }
+                            Err(e) => {
+                                debug!("Failed to decrypt client message from {}: {}", peer, e);
                                 continue;
                             }
-                            info!("New UDP session {} from {}", session_id, peer);
-                            self.sessions.push(UdpSession {
-                                session_id,
-                                peer,
-                                last_recv_time: Instant::now(),
-                                state: UdpSessionState::New,
-                            });
-                            break session_id;
                         }
-                    } else {
+                    }
+                    None => (None, &self.recv_buffer[0..size]),
+                };
+                let header = match read_client_message_header(input) {
+                    Some(v) => v,
+                    None => {
+                        debug!("Ignore client message from {} with invalid header", peer);
+                        continue;
+                    }
+                };
+                let session = self
+                    .sessions
+                    .iter()
+                    .find(|v| v.peer == peer)
+                    .map(|v| (v.session_id, v.secret, v.state));
+                let (session_id, is_new_peer, has_session_secret) = match session {
+                    Some((session_id, secret, state)) => (
+                        session_id,
+                        matches!(state, UdpSessionState::Done),
+                        is_same_secret(header.secret, secret),
+                    ),
+                    None => (0, true, false),
+                };
+                if !has_session_secret {
+                    if !is_new_peer {
+                        debug!(
+                            "Server has received client message with invalid secret for session {}",
+                            session_id
+                        );
+                        continue;
+                    }
+                    if !self.is_valid_cookie(&peer, header.secret, now) {
+                        let cookie = self.make_cookie(&peer, self.get_cookie_period(now));
+                        self.send_challenge(peer, cookie).await;
+                        continue;
+                    }
+                }
+                if header.protocol_version != PROTOCOL_VERSION {
+                    self.reject_peer(peer, header.protocol_version).await;
+                    continue;
+                }
+                let mut client_message = match deserialize_client_message(input) {
+                    Ok(v) => v,
+                    Err(e) => {
+                        debug!("Failed to deserialize client message: {}", e);
+                        continue;
+                    }
+                };
+                if let Some(header) = encrypted_header {
+                    if header.session_id != client_message.session_id
+                        || header.number != client_message.number
+                        || header.fragment != 0
+                    {
+                        debug!(
+                            "Client message from {} does not match encrypted message header",
+                            peer
+                        );
+                        continue;
+                    }
+                }
+                let session_id = if has_session_secret {
+                    let session = self.sessions.iter_mut().find(|v| v.peer == peer).unwrap();
+                    session.last_recv_time = Instant::now();
+                    session.session_id
+                } else if !matches!(client_message.data, ClientMessageData::Join { .. })
+                    || client_message.session_id != 0
+                {
+                    debug!(
+                        "Ignore client message {} from {} without session",
+                        get_client_message_data_type(&client_message.data),
+                        peer
+                    );
+                    continue;
+                } else {
+                    self.sessions.retain(|v| v.peer != peer);
+                    if self.sessions.len() >= self.settings.max_sessions {
                         debug!(
                             "Ignore new session from {}, sessions: {}/{}",
                             peer,
@@ -402,17 +707,11 @@ impl UdpServer {
                             self.settings.max_sessions
                         );
                         continue;
-                    };
-                let mut client_message =
-                    match deserialize_client_message(&self.recv_buffer[0..size]) {
-                        Ok(v) => v,
-                        Err(e) => {
-                            debug!("Failed to deserialize client message: {}", e);
-                            continue;
-                        }
-                    };
+                    }
+                    self.add_session(peer)
+                };
                 if client_message.session_id != session_id
-                    && !(matches!(client_message.data, ClientMessageData::Join(..))
+                    && !(matches!(client_message.data, ClientMessageData::Join { .. })
                         && client_message.session_id == 0)
                 {
                     debug!("Server has received client message {} with invalid session_id: {}, expected: {}",
@@ -420,13 +719,31 @@ impl UdpServer {
                     continue;
                 }
                 client_message.session_id = session_id;
+                let session = self
+                    .sessions
+                    .iter_mut()
+                    .find(|v| v.session_id == session_id)
+                    .unwrap();
+                client_message.secret = session.secret;
+                session.reliable_sender.ack(client_message.ack);
+                if matches!(session.state, UdpSessionState::Done) {
+                    continue;
+                }
+                if let Some(number) = client_message.reliable {
+                    if !session.reliable_receiver.accept(number) {
+                        debug!(
+                            "Server has received duplicate or out of order client message {} with reliable number {} for session {}",
+                            get_client_message_data_type(&client_message.data),
+                            number,
+                            session_id
+                        );
+                        continue;
+                    }
+                }
                 if matches!(&client_message.data, ClientMessageData::Quit) {
-                    let session = self
-                        .sessions
-                        .iter_mut()
-                        .find(|v| v.session_id == session_id)
-                        .unwrap();
                     session.state = UdpSessionState::Done;
+                    session.reliable_sender =
+                        ReliableSender::new(MAX_PENDING_RELIABLE_MESSAGES_PER_SESSION);
                     info!("UDP session {} is done", session.session_id);
                 }
                 self.sender.send(client_message).ok();
@@ -439,13 +756,65 @@ impl UdpServer {
     }
 }
 
+fn is_same_secret(lhs: u64, rhs: u64) -> bool {
+    let mut difference = 0;
+    for (l, r) in lhs.to_le_bytes().iter().zip(rhs.to_le_bytes().iter()) {
+        difference |= l ^ r;
+    }
+    difference == 0
+}
+
+fn push_reliable_server_message(session: &mut UdpSession, data: Arc<ServerMessageData>) {
+    if session.reliable_sender.push(data).is_none() {
+        warn!(
+            "Dropped reliable server message for UDP session {}: too many pending messages",
+            session.session_id
+        );
+    }
+}
+
+async fn send_reliable_server_messages(
+    socket: &UdpSocket,
+    encryption: Option<&Encryption>,
+    message_counter: &mut u64,
+    session: &mut UdpSession,
+    now: Instant,
+) {
+    let session_id = session.session_id;
+    let ack = session.reliable_receiver.ack();
+    let server_messages: Vec<ServerMessage> = session
+        .reliable_sender
+        .poll(now)
+        .flat_map(|(number, data)| {
+            *message_counter += 1;
+            match make_server_messages(session_id, *message_counter, Some(*number), ack, data) {
+                Ok(v) => v,
+                Err(e) => {
+                    error!(
+                        "Failed to make reliable server message {} for session {}: {}",
+                        number, session_id, e
+                    );
+                    Vec::new()
+                }
+            }
+        })
+        .collect();
+    for server_message in server_messages.iter() {
+        send_server_message(socket, encryption, session, server_message).await;
+    }
+}
+
 async fn send_server_message(
     socket: &UdpSocket,
+    encryption: Option<&Encryption>,
     session: &UdpSession,
     server_message: &ServerMessage,
 ) {
     if let Err(e) = socket
-        .send_to(&serialize_server_message(server_message), session.peer)
+        .send_to(
+            &serialize_and_encrypt_server_message(server_message, encryption),
+            session.peer,
+        )
         .await
     {
         warn!(
@@ -455,11 +824,37 @@ async fn send_server_message(
     }
 }
 
+fn serialize_and_encrypt_server_message(
+    server_message: &ServerMessage,
+    encryption: Option<&Encryption>,
+) -> Vec<u8> {
+    let serialized = serialize_server_message(server_message);
+    match encryption {
+        Some(v) => v.encrypt(
+            Direction::ServerToClient,
+            server_message.session_id,
+            server_message.number,
+            server_message.fragment,
+            &serialized,
+        ),
+        None => serialized,
+    }
+}
+
 #[derive(Debug)]
 pub struct GameServerSettings {
     pub max_players: usize,
     pub update_period: Duration,
     pub session_timeout: Duration,
+    pub world_path: Option<String>,
+    pub game_mode: GameModeSettings,
+    pub teams: u8,
+    pub bots: usize,
+    pub max_lag_compensation: Duration,
+    pub quantization: Quantization,
+    pub capabilities: Capabilities,
+    pub resume_timeout: Duration,
+    pub area_of_interest_radius: Option<f64>,
 }
 
 pub fn run_game_server(
@@ -479,9 +874,14 @@ pub fn run_game_server(
         );
     }
     let time_step = settings.update_period.as_secs_f64();
+    let max_lag_compensation_frames =
+        (settings.max_lag_compensation.as_secs_f64() / time_step).round() as u64;
     let mut frame_rate_limiter = FrameRateLimiter::new(settings.update_period, Instant::now());
+    let mut resume_token_rng = StdRng::from_entropy();
     let mut sessions: Vec<GameSession> = Vec::new();
     let mut engine = Engine::default();
+    let mut game_mode = GameMode::new(settings.game_mode.clone(), &world);
+    let mut bots = Vec::new();
     let mut world_history = VecDeque::with_capacity(MAX_WORLD_HISTORY_SIZE);
     let mut world_updates_history = VecDeque::with_capacity(MAX_WORLD_HISTORY_SIZE - 1);
     world_history.push_back(world.clone());
@@ -501,18 +901,59 @@ pub fn run_game_server(
     while !stop.load(Ordering::Acquire) {
         meters.fps.add(Instant::now());
         meters.frame_duration.add(measure(|| {
-            handle_delayed_messages(&settings, &sender, &mut sessions, &mut world);
+            handle_delayed_messages(&settings, &sender, &mut sessions, &mut world, &mut engine);
             handle_new_client_messages(
                 &settings,
                 &sender,
                 &client_receiver,
                 &mut sessions,
                 &mut world,
+                &mut engine,
+                &mut resume_token_rng,
             );
-            close_timed_out_sessions(settings.session_timeout, &sender, &mut sessions);
+            close_timed_out_sessions(&settings, &sender, &mut sessions);
+            dispatch_chat_messages(&sender, &mut sessions, &world);
+            update_sessions_update_interval(&settings, &mut sessions, &world);
             handle_dropped_messages(&mut sessions);
-            remove_inactive_actors(&mut sessions, &mut world);
+            remove_inactive_actors(&mut sessions, &mut world, &mut engine);
+            update_bot_players(
+                &settings,
+                count_players(&sessions),
+                &mut bots,
+                &mut world,
+                &mut engine,
+            );
+            update_bots(&mut bots, &mut engine, &mut world, &mut rng);
+            add_lag_compensation(
+                max_lag_compensation_frames,
+                &sessions,
+                &world,
+                &world_history,
+                &mut engine,
+            );
             engine.update(time_step, &mut world, &mut rng);
+            broadcast_kill_feed(engine.world_events(), &sender, &sessions, &world);
+            match game_mode.update(&mut world) {
+                Some(GameModeEvent::RoundFinished(round_result)) => {
+                    info!("Round is finished: {:?}", round_result);
+                    sender
+                        .send(InternalServerMessage::Broadcast(
+                            ServerMessageData::GameUpdate(GameUpdate::RoundResult(round_result)),
+                        ))
+                        .ok();
+                }
+                Some(GameModeEvent::RoundStarted) => {
+                    match make_world(settings.world_path.as_deref(), &mut rng) {
+                        Ok(v) => world = reset_world(v, &world),
+                        Err(e) => error!("Failed to reset world: {}", e),
+                    }
+                    engine = Engine::default();
+                    engine.rebuild_index(&world);
+                    world_history.clear();
+                    world_updates_history.clear();
+                }
+                None => (),
+            }
             sessions.retain(|v| v.active);
             if world_history.len() >= MAX_WORLD_HISTORY_SIZE {
                 world_history.pop_front();
@@ -527,16 +968,20 @@ pub fn run_game_server(
                 &world,
                 &world_history,
                 &world_updates_history,
-                &sessions,
+                &mut sessions,
+                &settings.quantization,
+                settings.area_of_interest_radius,
             );
             world_history.push_back(world.clone());
             handle_admin_messages(
+                &settings,
                 &admin_receiver,
                 frame_rate_limiter.left(Instant::now()),
                 &sender,
                 &meters,
                 &mut sessions,
                 &mut world,
+                &mut engine,
                 &stop,
             );
         }));
@@ -553,7 +998,7 @@ struct Meters {
 struct GameSession {
     session_id: u64,
     active: bool,
-    player_id: PlayerId,
+    player_id: Option<PlayerId>,
     last_message_time: Instant,
     last_message_number: u64,
     messages_per_frame: u8,
@@ -561,15 +1006,31 @@ struct GameSession {
     delayed_messages: VecDeque<ClientMessage>,
     ack_world_frame: u64,
     ack_cast_action_frame: u64,
+    capabilities: Capabilities,
+    resume_token: u64,
+    detached_time: Option<Instant>,
+    chat_times: VecDeque<Instant>,
+    update_interval: u64,
+    update_interval_time: Instant,
+    last_world_update_frame: u64,
+    sent_world_frames: VecDeque<u64>,
+    known_world_frame: u64,
+    known_world_entities: WorldEntityIds,
+    sent_world_entities: VecDeque<(u64, WorldEntityIds)>,
+    area_of_interest_center: Option<Vec2f>,
+    new_chat_messages: Vec<ChatMessage>,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_admin_messages(
+    settings: &GameServerSettings,
     receiver: &Receiver<GameAdminMessage>,
     left: Duration,
     sender: &Sender<InternalServerMessage>,
     meters: &Meters,
-    sessions: &mut Vec<GameSession>,
+    sessions: &mut [GameSession],
     world: &mut World,
+    engine: &mut Engine,
     stop: &Arc<AtomicBool>,
 ) {
     let deadline = Instant::now() + left;
@@ -587,7 +1048,7 @@ fn handle_admin_messages(
                             .iter()
                             .map(|v| GameSessionInfo {
                                 session_id: v.session_id,
-                                player_id: v.player_id.0,
+                                player_id: v.player_id.map(|v| v.0),
                                 last_message_time: v.last_message_time.elapsed().as_secs_f64(),
                                 last_message_number: v.last_message_number,
                                 messages_per_frame: v.messages_per_frame,
@@ -598,6 +1059,10 @@ fn handle_admin_messages(
                                 since_last_message: (Instant::now() - v.last_message_time)
                                     .as_secs_f64(),
                                 world_frame_delay: world.frame - v.ack_world_frame,
+                                capabilities: v.capabilities,
+                                update_frequency: 1.0
+                                    / (settings.update_period.as_secs_f64()
+                                        * v.update_interval as f64),
                             })
                             .collect(),
                     )
@@ -608,7 +1073,9 @@ fn handle_admin_messages(
                 response,
             } => {
                 if let Some(session) = sessions.iter_mut().find(|v| v.session_id == session_id) {
-                    remove_player(session.player_id, world);
+                    if let Some(player_id) = session.player_id {
+                        engine.remove_player(player_id, world);
+                    }
                     sender
                         .send(InternalServerMessage::Unicast {
                             session_id: session.session_id,
@@ -648,6 +1115,25 @@ fn handle_admin_messages(
             GameAdminMessage::GetWorld(response) => {
                 response.try_send(Box::new(world.clone())).ok();
             }
+            GameAdminMessage::Announce { text, response } => {
+                if text.is_empty() || text.len() > MAX_CHAT_MESSAGE_LEN {
+                    response
+                        .try_send(Err(format!(
+                            "Announcement length should be from 1 to {} bytes",
+                            MAX_CHAT_MESSAGE_LEN
+                        )))
+                        .ok();
+                } else {
+                    info!("Server announcement: {}", text);
+                    let message = ChatMessage {
+                        sender: None,
+                        team: None,
+                        text,
+                    };
+                    broadcast_chat_message(&message, sender, sessions, world);
+                    response.try_send(Ok(())).ok();
+                }
+            }
         }
         if Instant::now() >= deadline {
             break;
@@ -660,20 +1146,32 @@ fn handle_delayed_messages(
     sender: &Sender<InternalServerMessage>,
     sessions: &mut [GameSession],
     world: &mut World,
+    engine: &mut Engine,
 ) {
     for session in sessions.iter_mut() {
         session.messages_per_frame = 0;
-        handle_session_delayed_messages(settings, sender, session, world);
+        handle_session_delayed_messages(settings, sender, session, world, engine);
     }
 }
 
 fn close_timed_out_sessions(
-    session_timeout: Duration,
+    settings: &GameServerSettings,
     sender: &Sender<InternalServerMessage>,
     sessions: &mut [GameSession],
 ) {
+    let now = Instant::now();
     for session in sessions.iter_mut() {
-        if session_timeout <= Instant::now() - session.last_message_time {
+        if let Some(detached_time) = session.detached_time {
+            if settings.resume_timeout <= now - detached_time {
+                warn!("Detached game session {} is expired", session.session_id);
+                session.active = false;
+            }
+        } else if settings.session_timeout <= now - session.last_message_time {
+            if settings.resume_timeout > Duration::from_secs(0) {
+                warn!("Game session {} is detached", session.session_id);
+                session.detached_time = Some(now);
+                continue;
+            }
             warn!("Game session {} is timed out", session.session_id);
             session.active = false;
             sender
@@ -691,10 +1189,11 @@ fn handle_session_delayed_messages(
     sender: &Sender<InternalServerMessage>,
     session: &mut GameSession,
     world: &mut World,
+    engine: &mut Engine,
 ) {
     while session.messages_per_frame < MAX_SESSION_MESSAGES_PER_FRAME {
         if let Some(message) = session.delayed_messages.pop_front() {
-            if !handle_session_delayed_message(message, settings, sender, session, world) {
+            if !handle_session_delayed_message(message, settings, sender, session, world, engine) {
                 break;
             }
         } else {
@@ -709,11 +1208,12 @@ fn handle_session_delayed_message(
     sender: &Sender<InternalServerMessage>,
     session: &mut GameSession,
     world: &mut World,
+    engine: &mut Engine,
 ) -> bool {
-    if message.number <= session.last_message_number {
+    if message.reliable.is_none() && message.number <= session.last_message_number {
         return false;
     }
-    handle_session_message(message, settings, sender, session, world);
+    handle_session_message(message, settings, sender, session, world, engine);
     true
 }
 
@@ -723,6 +1223,8 @@ fn handle_new_client_messages(
     receiver: &Receiver<ClientMessage>,
     sessions: &mut Vec<GameSession>,
     world: &mut World,
+    engine: &mut Engine,
+    resume_token_rng: &mut StdRng,
 ) {
     let mut messages_per_frame: usize = 0;
     while let Ok(message) = receiver.try_recv() {
@@ -730,21 +1232,43 @@ fn handle_new_client_messages(
             .iter_mut()
             .find(|v| v.session_id == message.session_id)
         {
-            handle_session_new_message(message, settings, sender, session, world);
-        } else if sessions.len() < settings.max_players {
+            handle_session_new_message(message, settings, sender, session, world, engine);
+        } else if let Some(session) = sessions
+            .iter_mut()
+            .find(|v| Some(v.resume_token) == get_resume_token(&message.data))
+        {
+            resume_session(message, settings, sender, session, world);
+        } else if get_resume_token(&message.data).is_some() {
+            sender
+                .send(InternalServerMessage::Unicast {
+                    session_id: message.session_id,
+                    data: ServerMessageData::GameUpdate(GameUpdate::GameOver(String::from(
+                        "Session is expired",
+                    ))),
+                })
+                .unwrap();
+        } else if is_spectator_join(&message.data) || count_players(sessions) < settings.max_players
+        {
+            let resume_token = make_resume_token(sessions, resume_token_rng);
             if let Some(session) =
-                create_new_session(settings.update_period, sender, message, world)
+                create_new_session(settings, sender, message, resume_token, world)
             {
-                info!(
-                    "New player has joined: session_id={} player_id={}",
-                    session.session_id, session.player_id.0
-                );
+                match session.player_id {
+                    Some(player_id) => info!(
+                        "New player has joined: session_id={} player_id={}",
+                        session.session_id, player_id.0
+                    ),
+                    None => info!(
+                        "New spectator has joined: session_id={}",
+                        session.session_id
+                    ),
+                }
                 sessions.push(session);
             }
         } else {
             warn!(
                 "Rejected new player, server players: {}/{}",
-                sessions.len(),
+                count_players(sessions),
                 settings.max_players
             );
             sender
@@ -767,12 +1291,13 @@ fn handle_session_new_message(
     sender: &Sender<InternalServerMessage>,
     session: &mut GameSession,
     world: &mut World,
+    engine: &mut Engine,
 ) {
-    if message.number <= session.last_message_number {
+    if message.reliable.is_none() && message.number <= session.last_message_number {
         return;
     }
     if session.messages_per_frame < MAX_SESSION_MESSAGES_PER_FRAME {
-        return handle_session_message(message, settings, sender, session, world);
+        return handle_session_message(message, settings, sender, session, world, engine);
     }
     if session.delayed_messages.len() >= MAX_DELAYED_MESSAGES_PER_SESSION {
         session.dropped_messages += 1;
@@ -787,23 +1312,33 @@ fn handle_session_message(
     sender: &Sender<InternalServerMessage>,
     session: &mut GameSession,
     world: &mut World,
+    engine: &mut Engine,
 ) {
     session.last_message_time = Instant::now();
-    session.last_message_number = message.number;
+    session.last_message_number = message.number.max(session.last_message_number);
     session.messages_per_frame += 1;
+    if session.detached_time.take().is_some() {
+        info!("Game session {} is reattached", session.session_id);
+    }
     match message.data {
         ClientMessageData::Quit => {
-            remove_player(session.player_id, world);
+            if let Some(player_id) = session.player_id {
+                engine.remove_player(player_id, world);
+            }
             session.active = false;
             info!("Game session {} is done", session.session_id);
         }
         ClientMessageData::Heartbeat => (),
-        ClientMessageData::Join(..) => sender
+        ClientMessageData::Join { .. } => sender
             .send(InternalServerMessage::Unicast {
                 session_id: session.session_id,
                 data: ServerMessageData::NewPlayer {
                     update_period: settings.update_period,
                     player_id: session.player_id,
+                    capabilities: session.capabilities,
+                    quantization: get_session_quantization(&session.capabilities, settings),
+                    resume_token: session.resume_token,
+                    secret: message.secret,
                 },
             })
             .unwrap(),
@@ -812,10 +1347,9 @@ fn handle_session_message(
                 .ack_world_frame
                 .max(session.ack_world_frame)
                 .min(world.frame);
-            if let Some(actor_index) = world
-                .actors
-                .iter()
-                .position(|v| v.player_id == session.player_id)
+            if let Some(actor_index) = session
+                .player_id
+                .and_then(|v| engine.index().player_actor(v))
             {
                 sanitize_actor_action(&mut player_control.actor_action, actor_index, world);
                 if player_control.actor_action.cast_action.is_some()
@@ -826,9 +1360,269 @@ fn handle_session_message(
                 } else {
                     player_control.actor_action.cast_action = None;
                 }
-                apply_actor_action(player_control.actor_action, actor_index, world);
+                engine.apply_actor_action(player_control.actor_action, actor_index, world);
             }
         }
+        ClientMessageData::Chat { text, team_only } => {
+            handle_chat_message(text, team_only, sender, session, world)
+        }
+    }
+}
+
+fn handle_chat_message(
+    text: String,
+    team_only: bool,
+    sender: &Sender<InternalServerMessage>,
+    session: &mut GameSession,
+    world: &World,
+) {
+    let player = match session
+        .player_id
+        .and_then(|id| world.players.iter().find(|v| v.id == id))
+    {
+        Some(v) => v,
+        None => {
+            return send_server_chat_message("Spectators can't send chat messages", sender, session)
+        }
+    };
+    if text.len() > MAX_CHAT_MESSAGE_LEN {
+        return send_server_chat_message("Chat message is too long", sender, session);
+    }
+    if team_only && player.team.is_none() {
+        return send_server_chat_message(
+            "Team chat is not available without teams",
+            sender,
+            session,
+        );
+    }
+    let now = Instant::now();
+    while matches!(session.chat_times.front(), Some(v) if now - *v >= SESSION_CHAT_PERIOD) {
+        session.chat_times.pop_front();
+    }
+    if session.chat_times.len() >= MAX_SESSION_CHAT_MESSAGES_PER_PERIOD {
+        return send_server_chat_message(
+            "Too many chat messages, try again later",
+            sender,
+            session,
+        );
+    }
+    session.chat_times.push_back(now);
+    if text.trim().is_empty() {
+        return;
+    }
+    session.new_chat_messages.push(ChatMessage {
+        sender: Some(player.id),
+        team: if team_only { player.team } else { None },
+        text,
+    });
+}
+
+fn send_server_chat_message(
+    text: &str,
+    sender: &Sender<InternalServerMessage>,
+    session: &GameSession,
+) {
+    let message = ChatMessage {
+        sender: None,
+        team: None,
+        text: String::from(text),
+    };
+    send_chat_message(message, sender, session);
+}
+
+fn send_chat_message(
+    message: ChatMessage,
+    sender: &Sender<InternalServerMessage>,
+    session: &GameSession,
+) {
+    sender
+        .send(InternalServerMessage::Unicast {
+            session_id: session.session_id,
+            data: ServerMessageData::GameUpdate(GameUpdate::ChatMessage(message)),
+        })
+        .ok();
+}
+
+fn dispatch_chat_messages(
+    sender: &Sender<InternalServerMessage>,
+    sessions: &mut [GameSession],
+    world: &World,
+) {
+    let messages: Vec<ChatMessage> = sessions
+        .iter_mut()
+        .flat_map(|v| std::mem::take(&mut v.new_chat_messages))
+        .collect();
+    for message in messages.iter() {
+        broadcast_chat_message(message, sender, sessions, world);
+    }
+}
+
+fn broadcast_chat_message(
+    message: &ChatMessage,
+    sender: &Sender<InternalServerMessage>,
+    sessions: &[GameSession],
+    world: &World,
+) {
+    for session in sessions.iter() {
+        if session.detached_time.is_some()
+            || (message.team.is_some() && get_session_team(session, world) != message.team)
+        {
+            continue;
+        }
+        send_chat_message(message.clone(), sender, session);
+    }
+}
+
+fn broadcast_kill_feed(
+    events: &[WorldEvent],
+    sender: &Sender<InternalServerMessage>,
+    sessions: &[GameSession],
+    world: &World,
+) {
+    for event in events.iter() {
+        if let Some(text) = format_kill_feed_message(event, &world.players) {
+            info!("{}", text);
+            let message = ChatMessage {
+                sender: None,
+                team: None,
+                text,
+            };
+            broadcast_chat_message(&message, sender, sessions, world);
+        }
+    }
+}
+
+fn get_session_team(session: &GameSession, world: &World) -> Option<TeamId> {
+    world
+        .players
+        .iter()
+        .find(|v| Some(v.id) == session.player_id)
+        .and_then(|v| v.team)
+}
+
+fn is_spectator_join(data: &ClientMessageData) -> bool {
+    matches!(
+        data,
+        ClientMessageData::Join {
+            spectator: true,
+            ..
+        }
+    )
+}
+
+fn count_players(sessions: &[GameSession]) -> usize {
+    sessions.iter().filter(|v| v.player_id.is_some()).count()
+}
+
+fn get_resume_token(data: &ClientMessageData) -> Option<u64> {
+    match data {
+        ClientMessageData::Join { resume_token, .. } => *resume_token,
+        _ => None,
+    }
+}
+
+fn make_resume_token(sessions: &[GameSession], rng: &mut StdRng) -> u64 {
+    loop {
+        let resume_token = rng.gen();
+        if resume_token != 0 && sessions.iter().all(|v| v.resume_token != resume_token) {
+            break resume_token;
+        }
+    }
+}
+
+fn resume_session(
+    message: ClientMessage,
+    settings: &GameServerSettings,
+    sender: &Sender<InternalServerMessage>,
+    session: &mut GameSession,
+    world: &World,
+) {
+    info!(
+        "Game session {} is resumed as {}: player_id={:?}",
+        session.session_id,
+        message.session_id,
+        session.player_id.map(|v| v.0)
+    );
+    if session.detached_time.is_none() {
+        sender
+            .send(InternalServerMessage::Unicast {
+                session_id: session.session_id,
+                data: ServerMessageData::GameUpdate(GameUpdate::GameOver(String::from(
+                    "Session is resumed by another client",
+                ))),
+            })
+            .unwrap();
+    }
+    session.session_id = message.session_id;
+    session.detached_time = None;
+    session.last_message_time = Instant::now();
+    session.last_message_number = message.number;
+    session.messages_per_frame += 1;
+    session.delayed_messages.clear();
+    session.ack_world_frame = 0;
+    session.ack_cast_action_frame = world.frame;
+    session.update_interval = 1;
+    session.sent_world_frames.clear();
+    session.known_world_frame = 0;
+    session.sent_world_entities.clear();
+    session.update_interval_time = Instant::now();
+    sender
+        .send(InternalServerMessage::Unicast {
+            session_id: session.session_id,
+            data: ServerMessageData::NewPlayer {
+                update_period: settings.update_period,
+                player_id: session.player_id,
+                capabilities: session.capabilities,
+                quantization: get_session_quantization(&session.capabilities, settings),
+                resume_token: session.resume_token,
+                secret: message.secret,
+            },
+        })
+        .unwrap();
+}
+
+fn update_sessions_update_interval(
+    settings: &GameServerSettings,
+    sessions: &mut [GameSession],
+    world: &World,
+) {
+    let max_ack_delay_frames = ((MAX_SESSION_ACK_DELAY.as_secs_f64()
+        / settings.update_period.as_secs_f64())
+    .ceil() as u64)
+        .max(1);
+    let now = Instant::now();
+    for session in sessions.iter_mut() {
+        if session.detached_time.is_some() || session.ack_world_frame == 0 {
+            continue;
+        }
+        let ack_world_frame = session.ack_world_frame;
+        let max_unacked_frames = session.update_interval + max_ack_delay_frames;
+        let congested = session
+            .sent_world_frames
+            .iter()
+            .any(|v| *v > ack_world_frame && world.frame - *v > max_unacked_frames);
+        let since_change = now - session.update_interval_time;
+        if congested {
+            if session.update_interval < MAX_SESSION_UPDATE_INTERVAL
+                && since_change >= SESSION_CONGESTION_BACKOFF_PERIOD
+            {
+                session.update_interval =
+                    (session.update_interval * 2).min(MAX_SESSION_UPDATE_INTERVAL);
+                session.update_interval_time = now;
+                debug!(
+                    "Game session {} is congested, update interval is increased to {} frames",
+                    session.session_id, session.update_interval
+                );
+            }
+        } else if session.update_interval > 1 && since_change >= SESSION_CONGESTION_RECOVERY_PERIOD
+        {
+            session.update_interval -= 1;
+            session.update_interval_time = now;
+            debug!(
+                "Game session {} is recovering, update interval is decreased to {} frames",
+                session.session_id, session.update_interval
+            );
+        }
     }
 }
 
@@ -844,62 +1638,151 @@ fn handle_dropped_messages(sessions: &mut [GameSession]) {
     }
 }
 
-fn remove_inactive_actors(sessions: &mut [GameSession], world: &mut World) {
+fn remove_inactive_actors(sessions: &mut [GameSession], world: &mut World, engine: &mut Engine) {
     for session in sessions.iter_mut() {
-        if !session.active {
-            remove_player(session.player_id, world);
+        if let (false, Some(player_id)) = (session.active, session.player_id) {
+            engine.remove_player(player_id, world);
         }
     }
 }
 
+fn add_lag_compensation(
+    max_frames: u64,
+    sessions: &[GameSession],
+    world: &World,
+    world_history: &VecDeque<World>,
+    engine: &mut Engine,
+) {
+    if max_frames == 0 {
+        return;
+    }
+    for session in sessions.iter() {
+        if session.ack_world_frame == 0 || session.ack_world_frame >= world.frame {
+            continue;
+        }
+        let actor_id = match world
+            .players
+            .iter()
+            .find(|v| Some(v.id) == session.player_id)
+            .and_then(|v| v.actor_id)
+        {
+            Some(v) => v,
+            None => continue,
+        };
+        let frame = session
+            .ack_world_frame
+            .max(world.frame.saturating_sub(max_frames));
+        if let Some(past_world) = world_history.iter().rev().find(|v| v.frame == frame) {
+            engine.add_lag_compensation(actor_id, past_world);
+        }
+    }
+}
+
+fn update_bot_players(
+    settings: &GameServerSettings,
+    players: usize,
+    bots: &mut Vec<Bot>,
+    world: &mut World,
+    engine: &mut Engine,
+) {
+    let number = settings
+        .bots
+        .min(settings.max_players.saturating_sub(players));
+    while bots.len() > number {
+        if let Some(bot) = bots.pop() {
+            engine.remove_player(bot.player_id(), world);
+        }
+    }
+    let mut name_number = 0;
+    while bots.len() < number {
+        let team = get_player_team(None, settings.teams, &world.players);
+        if let Some(player_id) = try_add_player(get_bot_name(name_number), team, world) {
+            bots.push(Bot::new(player_id));
+        }
+        name_number += 1;
+    }
+}
+
 fn create_new_session(
-    update_period: Duration,
+    settings: &GameServerSettings,
     sender: &Sender<InternalServerMessage>,
     message: ClientMessage,
+    resume_token: u64,
     world: &mut World,
 ) -> Option<GameSession> {
     match message.data {
-        ClientMessageData::Join(name) => {
-            if !is_valid_player_name(name.as_str()) {
-                sender
-                    .send(InternalServerMessage::Unicast {
-                        session_id: message.session_id,
-                        data: ServerMessageData::Error(String::from("Invalid player name")),
-                    })
-                    .unwrap();
-                return None;
-            }
-            if let Some(player_id) = try_add_player(name, world) {
-                sender
-                    .send(InternalServerMessage::Unicast {
-                        session_id: message.session_id,
-                        data: ServerMessageData::NewPlayer {
-                            update_period,
-                            player_id,
-                        },
-                    })
-                    .unwrap();
-                Some(GameSession {
+        ClientMessageData::Join {
+            player_name,
+            team,
+            spectator,
+            capabilities,
+            ..
+        } => {
+            let player_id = if spectator {
+                None
+            } else {
+                if !is_valid_player_name(player_name.as_str()) {
+                    sender
+                        .send(InternalServerMessage::Unicast {
+                            session_id: message.session_id,
+                            data: ServerMessageData::Error(String::from("Invalid player name")),
+                        })
+                        .unwrap();
+                    return None;
+                }
+                let team = get_player_team(team, settings.teams, &world.players);
+                match try_add_player(player_name, team, world) {
+                    Some(v) => Some(v),
+                    None => {
+                        sender
+                            .send(InternalServerMessage::Unicast {
+                                session_id: message.session_id,
+                                data: ServerMessageData::Error(String::from("Player name is busy")),
+                            })
+                            .unwrap();
+                        return None;
+                    }
+                }
+            };
+            let capabilities = capabilities.intersection(&settings.capabilities);
+            sender
+                .send(InternalServerMessage::Unicast {
                     session_id: message.session_id,
-                    active: true,
-                    player_id,
-                    last_message_time: Instant::now(),
-                    last_message_number: message.number,
-                    messages_per_frame: 1,
-                    delayed_messages: VecDeque::with_capacity(MAX_DELAYED_MESSAGES_PER_SESSION),
-                    dropped_messages: 0,
-                    ack_world_frame: 0,
-                    ack_cast_action_frame: world.frame,
+                    data: ServerMessageData::NewPlayer {
+                        update_period: settings.update_period,
+                        player_id,
+                        capabilities,
+                        quantization: get_session_quantization(&capabilities, settings),
+                        resume_token,
+                        secret: message.secret,
+                    },
                 })
-            } else {
-                sender
-                    .send(InternalServerMessage::Unicast {
-                        session_id: message.session_id,
-                        data: ServerMessageData::Error(String::from("Player name is busy")),
-                    })
-                    .unwrap();
-                None
-            }
+                .unwrap();
+            Some(GameSession {
+                session_id: message.session_id,
+                active: true,
+                player_id,
+                last_message_time: Instant::now(),
+                last_message_number: message.number,
+                messages_per_frame: 1,
+                delayed_messages: VecDeque::with_capacity(MAX_DELAYED_MESSAGES_PER_SESSION),
+                dropped_messages: 0,
+                ack_world_frame: 0,
+                ack_cast_action_frame: world.frame,
+                capabilities,
+                resume_token,
+                detached_time: None,
+                chat_times: VecDeque::with_capacity(MAX_SESSION_CHAT_MESSAGES_PER_PERIOD),
+                update_interval: 1,
+                update_interval_time: Instant::now(),
+                last_world_update_frame: 0,
+                sent_world_frames: VecDeque::new(),
+                known_world_frame: 0,
+                known_world_entities: WorldEntityIds::default(),
+                sent_world_entities: VecDeque::new(),
+                area_of_interest_center: None,
+                new_chat_messages: Vec::new(),
+            })
         }
         ClientMessageData::Quit => None,
         v => {
@@ -949,7 +1832,22 @@ impl FrameRateLimiter {
     }
 }
 
-fn try_add_player(name: String, world: &mut World) -> Option<PlayerId> {
+fn get_player_team(team: Option<TeamId>, teams: u8, players: &[Player]) -> Option<TeamId> {
+    if teams == 0 {
+        return None;
+    }
+    if let Some(team) = team.filter(|v| v.0 < teams) {
+        return Some(team);
+    }
+    (0..teams).map(TeamId).min_by_key(|team| {
+        players
+            .iter()
+            .filter(|v| v.active && v.team == Some(*team))
+            .count()
+    })
+}
+
+fn try_add_player(name: String, team: Option<TeamId>, world: &mut World) -> Option<PlayerId> {
     if world.players.iter().any(|v| v.name == name) || world.actors.iter().any(|v| v.name == name) {
         return None;
     }
@@ -958,9 +1856,14 @@ fn try_add_player(name: String, world: &mut World) -> Option<PlayerId> {
         id: player_id,
         active: true,
         name,
+        team,
         actor_id: None,
         spawn_time: world.time + world.settings.initial_player_actor_spawn_delay,
         deaths: 0,
+        kills: 0,
+        assists: 0,
+        damage_dealt: 0.0,
+        damage_healed: 0.0,
     });
     Some(player_id)
 }
@@ -974,16 +1877,41 @@ fn sanitize_actor_action(actor_action: &mut ActorAction, actor_index: usize, wor
     }
 }
 
+fn get_session_quantization(
+    capabilities: &Capabilities,
+    settings: &GameServerSettings,
+) -> Option<Quantization> {
+    if capabilities.quantized_world_updates {
+        Some(settings.quantization)
+    } else {
+        None
+    }
+}
+
 fn send_world_messages(
     sender: &Sender<InternalServerMessage>,
     world: &World,
     world_history: &VecDeque<World>,
     world_updates_history: &VecDeque<WorldUpdate>,
-    sessions: &[GameSession],
+    sessions: &mut [GameSession],
+    quantization: &Quantization,
+    area_of_interest_radius: Option<f64>,
 ) {
     let mut world_snapshot_session_indices = Vec::new();
-    let mut world_updates: Vec<(usize, Vec<usize>, WorldUpdate)> = Vec::new();
-    for (session_index, session) in sessions.iter().enumerate() {
+    let mut world_updates: Vec<(Option<usize>, Vec<usize>, WorldUpdate)> = Vec::new();
+    for (session_index, session) in sessions.iter_mut().enumerate() {
+        if session.detached_time.is_some()
+            || world.frame < session.last_world_update_frame + session.update_interval
+        {
+            continue;
+        }
+        session.last_world_update_frame = world.frame;
+        let ack_world_frame = session.ack_world_frame;
+        session.sent_world_frames.retain(|v| *v > ack_world_frame);
+        if session.sent_world_frames.len() >= MAX_WORLD_HISTORY_SIZE {
+            session.sent_world_frames.pop_front();
+        }
+        session.sent_world_frames.push_back(world.frame);
         if session.ack_world_frame == 0 {
             world_snapshot_session_indices.push(session_index);
             continue;
@@ -993,8 +1921,26 @@ fn send_world_messages(
             world_snapshot_session_indices.push(session_index);
             continue;
         }
-        if let Some((_, session_indices, _)) =
-            world_updates.iter_mut().find(|(v, _, _)| *v == offset)
+        if let Some(radius) = area_of_interest_radius {
+            update_known_world_entities(session);
+            if session.known_world_frame != session.ack_world_frame {
+                world_snapshot_session_indices.push(session_index);
+                continue;
+            }
+            let world_update = make_session_area_world_update(
+                world,
+                world_history,
+                world_updates_history,
+                session,
+                offset,
+                radius,
+            );
+            world_updates.push((None, vec![session_index], world_update));
+            continue;
+        }
+        if let Some((_, session_indices, _)) = world_updates
+            .iter_mut()
+            .find(|(v, _, _)| *v == Some(offset))
         {
             session_indices.push(session_index);
             continue;
@@ -1007,38 +1953,131 @@ fn send_world_messages(
                 .skip(world_updates_history.len() - offset),
             &mut world_update,
         );
-        world_updates.push((offset, vec![session_index], world_update));
+        world_updates.push((Some(offset), vec![session_index], world_update));
     }
     for (_, session_indices, world_update) in world_updates {
+        let mut quantized_world_update = None;
         for session_index in session_indices {
             let session = &sessions[session_index];
+            let data = if session.capabilities.quantized_world_updates {
+                let quantized = quantized_world_update.get_or_insert_with(|| {
+                    quantize_world_update(&world_update, &world.bounds, quantization)
+                });
+                ServerMessageData::QuantizedWorldUpdate {
+                    ack_actor_action_world_frame: session.ack_world_frame,
+                    ack_cast_action_world_frame: session.ack_cast_action_frame,
+                    world_update: Box::new(quantized.clone()),
+                }
+            } else {
+                ServerMessageData::GameUpdate(GameUpdate::WorldUpdate {
+                    ack_actor_action_world_frame: session.ack_world_frame,
+                    ack_cast_action_world_frame: session.ack_cast_action_frame,
+                    world_update: Box::new(world_update.clone()),
+                })
+            };
             sender
                 .send(InternalServerMessage::Unicast {
                     session_id: session.session_id,
-                    data: ServerMessageData::GameUpdate(GameUpdate::WorldUpdate {
-                        ack_actor_action_world_frame: session.ack_world_frame,
-                        ack_cast_action_world_frame: session.ack_cast_action_frame,
-                        world_update: Box::new(world_update.clone()),
-                    }),
+                    data,
                 })
                 .ok();
         }
     }
     for session_index in world_snapshot_session_indices {
-        let session = &sessions[session_index];
+        let session = &mut sessions[session_index];
+        let world = match area_of_interest_radius {
+            Some(radius) => {
+                let world = filter_session_world_area(world, session, radius);
+                add_sent_world_entities(session, &world);
+                world
+            }
+            None => world.clone(),
+        };
         sender
             .send(InternalServerMessage::Unicast {
                 session_id: session.session_id,
                 data: ServerMessageData::GameUpdate(GameUpdate::WorldSnapshot {
                     ack_actor_action_world_frame: session.ack_world_frame,
                     ack_cast_action_world_frame: session.ack_cast_action_frame,
-                    world: Box::new(world.clone()),
+                    world: Box::new(world),
                 }),
             })
             .ok();
     }
 }
 
+fn make_session_area_world_update(
+    world: &World,
+    world_history: &VecDeque<World>,
+    world_updates_history: &VecDeque<WorldUpdate>,
+    session: &mut GameSession,
+    offset: usize,
+    radius: f64,
+) -> WorldUpdate {
+    let after = filter_session_world_area(world, session, radius);
+    let mut known = session.known_world_entities.clone();
+    let mut sent = session.known_world_entities.clone();
+    for (_, entities) in session.sent_world_entities.iter() {
+        known.intersect(entities);
+        sent.extend(entities);
+    }
+    let before = make_known_world(
+        &world_history[world_history.len() - offset],
+        world,
+        &after,
+        &known,
+        &sent,
+    );
+    let mut world_update = make_world_update(&before, &after);
+    add_all_removed(
+        world_updates_history
+            .iter()
+            .skip(world_updates_history.len() - offset),
+        &mut world_update,
+    );
+    add_sent_world_entities(session, &after);
+    world_update
+}
+
+fn update_known_world_entities(session: &mut GameSession) {
+    while let Some((frame, entities)) = session.sent_world_entities.front() {
+        if *frame > session.ack_world_frame {
+            break;
+        }
+        if *frame == session.ack_world_frame {
+            session.known_world_frame = *frame;
+            session.known_world_entities = entities.clone();
+        }
+        session.sent_world_entities.pop_front();
+    }
+}
+
+fn filter_session_world_area(world: &World, session: &mut GameSession, radius: f64) -> World {
+    let player_id = match session.player_id {
+        Some(v) => v,
+        None => return world.clone(),
+    };
+    let center = match get_player_actor_position(world, player_id) {
+        Some(v) => {
+            session.area_of_interest_center = Some(v);
+            v
+        }
+        None => session
+            .area_of_interest_center
+            .unwrap_or_else(|| (world.bounds.min + world.bounds.max) / 2.0),
+    };
+    filter_world_area(world, center, radius)
+}
+
+fn add_sent_world_entities(session: &mut GameSession, world: &World) {
+    if session.sent_world_entities.len() >= MAX_WORLD_HISTORY_SIZE {
+        session.sent_world_entities.pop_front();
+    }
+    session
+        .sent_world_entities
+        .push_back((world.frame, WorldEntityIds::new(world)));
+}
+
 #[derive(Debug)]
 pub struct HttpServerSettings {
     pub address: String,
@@ -1076,6 +2115,7 @@ fn run_http_server(
             .service(web::resource("/remove_session").route(web::post().to(remove_sessions)))
             .service(web::resource("/status").route(web::get().to(status)))
             .service(web::resource("/world").route(web::get().to(world)))
+            .service(web::resource("/announce").route(web::post().to(announce)))
             .default_service(web::route().to(HttpResponse::NotFound))
     })
     .workers(1)
@@ -1212,3 +2252,32 @@ async fn world(game_admin_sender: web::Data<Sender<GameAdminMessage>>) -> HttpRe
         },
     })
 }
+
+#[derive(Deserialize)]
+struct Announce {
+    text: String,
+}
+
+async fn announce(
+    game_admin_sender: web::Data<Sender<GameAdminMessage>>,
+    query: web::Query<Announce>,
+) -> HttpResponse {
+    let (response, mut request) = tokio::sync::mpsc::channel(1);
+    if let Err(e) = game_admin_sender.send(GameAdminMessage::Announce {
+        text: query.into_inner().text,
+        response,
+    }) {
+        return HttpResponse::Ok().json(HttpMessage::Error {
+            message: format!("{}", e),
+        });
+    }
+    HttpResponse::Ok().json(match request.recv().await {
+        Some(v) => match v {
+            Ok(..) => HttpMessage::Ok,
+            Err(e) => HttpMessage::Error { message: e },
+        },
+        None => HttpMessage::Error {
+            message: String::from("Failed to get response"),
+        },
+    })
+}
@@ -24,6 +24,8 @@ pub struct World {
     pub guns: Vec<Gun>,
     pub shields: Vec<Shield>,
     pub temp_obstacles: Vec<TempObstacle>,
+    pub lightning_bolts: Vec<LightningBolt>,
+    pub mines: Vec<Mine>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -55,6 +57,19 @@ pub struct WorldSettings {
     pub temp_obstacle_magick_duration: f64,
     pub temp_area_duration: f64,
     pub max_actor_speed: f64,
+    pub lightning_angle: f64,
+    pub max_lightning_length: f64,
+    pub max_lightning_jumps: u8,
+    pub quake_distance_factor: f64,
+    pub quake_damage_factor: f64,
+    pub quake_velocity_z_factor: f64,
+    pub ice_spikes_number: u8,
+    pub ice_spike_force_factor: f64,
+    pub ice_spike_duration: f64,
+    pub mine_duration: f64,
+    pub mine_explosion_radius: f64,
+    pub mine_explosion_duration: f64,
+    pub friendly_fire_factor: f64,
 }
 
 impl Default for WorldSettings {
@@ -87,6 +102,19 @@ impl Default for WorldSettings {
             temp_obstacle_magick_duration: 20.0,
             temp_area_duration: 5.0,
             max_actor_speed: 10.0,
+            lightning_angle: std::f64::consts::FRAC_PI_4,
+            max_lightning_length: 10.0,
+            max_lightning_jumps: 3,
+            quake_distance_factor: 2.0,
+            quake_damage_factor: 0.05,
+            quake_velocity_z_factor: 1.0,
+            ice_spikes_number: 12,
+            ice_spike_force_factor: 2e4,
+            ice_spike_duration: 0.5,
+            mine_duration: 30.0,
+            mine_explosion_radius: 3.0,
+            mine_explosion_duration: 0.3,
+            friendly_fire_factor: 0.0,
         }
     }
 }
@@ -94,14 +122,22 @@ impl Default for WorldSettings {
 #[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
 pub struct PlayerId(pub u64);
 
+#[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct TeamId(pub u8);
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Player {
     pub id: PlayerId,
     pub active: bool,
     pub name: String,
+    pub team: Option<TeamId>,
     pub actor_id: Option<ActorId>,
     pub spawn_time: f64,
     pub deaths: u64,
+    pub kills: u64,
+    pub assists: u64,
+    pub damage_dealt: f64,
+    pub damage_healed: f64,
 }
 
 #[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
@@ -113,6 +149,7 @@ pub struct Actor {
     pub player_id: PlayerId,
     pub active: bool,
     pub name: String,
+    pub team: Option<TeamId>,
     pub body: Body<Disk>,
     pub position: Vec2f,
     pub health: f64,
@@ -128,6 +165,7 @@ pub struct Actor {
     pub position_z: f64,
     pub velocity_z: f64,
     pub occupation: ActorOccupation,
+    pub damagers: Vec<PlayerId>,
 }
 
 #[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
@@ -136,6 +174,7 @@ pub struct ProjectileId(pub u64);
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Projectile {
     pub id: ProjectileId,
+    pub actor_id: ActorId,
     pub body: Body<Disk>,
     pub position: Vec2f,
     pub health: f64,
@@ -144,6 +183,7 @@ pub struct Projectile {
     pub dynamic_force: Vec2f,
     pub position_z: f64,
     pub velocity_z: f64,
+    pub deadline: f64,
 }
 
 #[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
@@ -188,6 +228,7 @@ pub struct TempAreaId(pub u64);
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct TempArea {
     pub id: TempAreaId,
+    pub actor_id: ActorId,
     pub body: Body<Disk>,
     pub position: Vec2f,
     pub magick: Magick,
@@ -260,6 +301,41 @@ pub struct TempObstacle {
     pub deadline: f64,
 }
 
+#[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct LightningBoltId(pub u64);
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct LightningBolt {
+    pub id: LightningBoltId,
+    pub actor_id: ActorId,
+    pub magick: Magick,
+    pub spread: Option<f64>,
+    pub deadline: f64,
+}
+
+#[derive(Default, Debug, Clone, Copy, Deserialize, Serialize, PartialEq)]
+pub struct MineId(pub u64);
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+pub struct Mine {
+    pub id: MineId,
+    pub actor_id: ActorId,
+    pub body: Body<Disk>,
+    pub position: Vec2f,
+    pub magick: Magick,
+    pub detonated: bool,
+    pub deadline: f64,
+}
+
+#[derive(Debug, Copy, Clone, Deserialize, Serialize, PartialEq)]
+pub enum ObjectId {
+    Actor(ActorId),
+    Projectile(ProjectileId),
+    StaticObject(StaticObjectId),
+    Shield(ShieldId),
+    TempObstacle(TempObstacleId),
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct Body<Shape> {
     pub shape: Shape,
@@ -313,6 +389,7 @@ pub struct Magick {
 pub struct Effect {
     pub applied: [f64; 11],
     pub power: [f64; 11],
+    pub sources: [Option<ActorId>; 11],
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, Default, PartialEq)]
@@ -346,6 +423,7 @@ pub enum ActorOccupation {
         field_id: FieldId,
     },
     Beaming(BeamId),
+    Striking(LightningBoltId),
 }
 
 #[derive(Debug, Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Deserialize, Serialize)]
@@ -400,7 +478,7 @@ pub fn load_world<P: AsRef<Path>>(path: P) -> Result<World, String> {
     };
     match serde_json::from_reader(file) {
         Ok(v) => Ok(v),
-        Err(e) => return Err(format!("{}", e)),
+        Err(e) => Err(format!("{}", e)),
     }
 }
 
@@ -415,7 +493,7 @@ mod tests {
 
     #[test]
     fn serialized_default_world_size() {
-        assert_eq!(bincode::serialize(&World::default()).unwrap().len(), 354);
+        assert_eq!(bincode::serialize(&World::default()).unwrap().len(), 460);
     }
 
     #[test]
@@ -426,6 +504,7 @@ mod tests {
                 player_id: PlayerId(0),
                 active: true,
                 name: String::from("actor"),
+                team: None,
                 body: Body {
                     shape: Disk { radius: 1.0 },
                     material_type: MaterialType::Flesh,
@@ -444,10 +523,11 @@ mod tests {
                 position_z: 0.0,
                 velocity_z: 0.0,
                 occupation: ActorOccupation::None,
+                damagers: Vec::new(),
             })
             .unwrap()
             .len(),
-            371
+            391
         );
     }
 
@@ -456,6 +536,7 @@ mod tests {
         assert_eq!(
             bincode::serialize(&Projectile {
                 id: ProjectileId(1),
+                actor_id: ActorId(1),
                 body: Body {
                     shape: Disk { radius: 1.0 },
                     material_type: MaterialType::Stone,
@@ -467,10 +548,11 @@ mod tests {
                 dynamic_force: Vec2f::ZERO,
                 position_z: 1.0,
                 velocity_z: 0.0,
+                deadline: f64::MAX,
             })
             .unwrap()
             .len(),
-            180
+            196
         );
     }
 
@@ -485,7 +567,7 @@ mod tests {
             ))
             .unwrap()
             .len(),
-            232
+            243
         );
     }
 
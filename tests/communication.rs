@@ -1,6 +1,7 @@
 #[macro_use]
 extern crate log;
 
+use std::net::UdpSocket;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{Receiver, RecvTimeoutError, Sender};
 use std::sync::{Arc, Barrier};
@@ -11,13 +12,18 @@ use portpicker::pick_unused_port;
 use reqwest::blocking::{RequestBuilder, Response};
 
 use spell::client::{Client, GameClientSettings, UdpClientSettings};
+use spell::encryption::EncryptionKey;
+use spell::game_mode::GameModeType;
 use spell::protocol::{
-    apply_world_update, ActorAction, CastAction, GameUpdate, HttpMessage, PlayerControl,
-    ServerStatus,
+    apply_world_update, deserialize_server_message, deserialize_server_message_data,
+    get_challenge_cookie, get_rejected_protocol_version, serialize_client_message, ActorAction,
+    Capabilities, CastAction, ChatMessage, ClientMessage, ClientMessageData, GameUpdate,
+    HttpMessage, PlayerControl, ServerMessageData, ServerStatus, UdpSessionState,
+    CLIENT_MESSAGE_MAGIC, MAX_SERVER_MESSAGE_SIZE, MESSAGE_PREFIX_SIZE, PROTOCOL_VERSION,
 };
 use spell::server::{run_server, ServerParams};
 use spell::vec2::Vec2f;
-use spell::world::{Actor, Element, PlayerId, World};
+use spell::world::{Actor, Element, PlayerId, TeamId, World};
 
 #[test]
 fn server_should_terminate() {
@@ -36,6 +42,21 @@ fn server_should_terminate() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     run_background_server(server_params, stop).join().unwrap();
 }
@@ -56,6 +77,21 @@ fn server_should_provide_player_id() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server_and_client(
         server_params,
@@ -64,6 +100,9 @@ fn server_should_provide_player_id() {
             connect_timeout: Duration::from_secs(3),
             retry_period: Duration::from_secs_f64(0.25),
             player_name: String::from("test"),
+            team: None,
+            spectator: false,
+            capabilities: Capabilities::default(),
         },
         |_, game_update_receiver| {
             let game_update = game_update_receiver
@@ -94,6 +133,21 @@ fn server_should_move_player() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server_and_client(
         server_params,
@@ -102,6 +156,9 @@ fn server_should_move_player() {
             connect_timeout: Duration::from_secs(3),
             retry_period: Duration::from_secs_f64(0.25),
             player_name: String::from("test"),
+            team: None,
+            spectator: false,
+            capabilities: Capabilities::default(),
         },
         |player_control_sender, game_update_receiver| {
             let player_id = recv_player_id(game_update_receiver);
@@ -157,12 +214,30 @@ fn server_should_limit_number_of_sessions() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     let mut game_client_settings = GameClientSettings {
         id: 1,
         connect_timeout: Duration::from_secs(3),
         retry_period: Duration::from_secs_f64(0.25),
         player_name: String::from("test"),
+        team: None,
+        spectator: false,
+        capabilities: Capabilities::default(),
     };
     let mut udp_client_settings = UdpClientSettings {
         id: 1,
@@ -170,6 +245,8 @@ fn server_should_limit_number_of_sessions() {
             .parse()
             .unwrap(),
         read_timeout: Duration::from_secs(3),
+        reconnect_timeout: Duration::from_secs(1),
+        encryption_key: server_params.encryption_key.clone(),
     };
     with_background_server(server_params, |_| {
         let barrier1 = Arc::new(Barrier::new(2));
@@ -232,12 +309,30 @@ fn server_should_limit_number_of_players() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     let mut game_client_settings = GameClientSettings {
         id: 1,
         connect_timeout: Duration::from_secs(3),
         retry_period: Duration::from_secs_f64(0.25),
         player_name: String::from("test"),
+        team: None,
+        spectator: false,
+        capabilities: Capabilities::default(),
     };
     let mut udp_client_settings = UdpClientSettings {
         id: 1,
@@ -245,6 +340,8 @@ fn server_should_limit_number_of_players() {
             .parse()
             .unwrap(),
         read_timeout: Duration::from_secs(3),
+        reconnect_timeout: Duration::from_secs(1),
+        encryption_key: server_params.encryption_key.clone(),
     };
     with_background_server(server_params, |_| {
         let barrier1 = Arc::new(Barrier::new(2));
@@ -308,12 +405,30 @@ fn server_should_support_multiple_players() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     let game_client_settings = GameClientSettings {
         id: 1,
         connect_timeout: Duration::from_secs(3),
         retry_period: Duration::from_secs_f64(0.25),
         player_name: String::from("test"),
+        team: None,
+        spectator: false,
+        capabilities: Capabilities::default(),
     };
     let udp_client_settings = UdpClientSettings {
         id: 1,
@@ -321,6 +436,8 @@ fn server_should_support_multiple_players() {
             .parse()
             .unwrap(),
         read_timeout: Duration::from_secs(3),
+        reconnect_timeout: Duration::from_secs(1),
+        encryption_key: server_params.encryption_key.clone(),
     };
     with_background_server(server_params, |_| {
         let barrier = Arc::new(Barrier::new(players_number));
@@ -374,6 +491,21 @@ fn server_should_send_world_update_after_ack() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server_and_client(
         server_params,
@@ -382,6 +514,9 @@ fn server_should_send_world_update_after_ack() {
             connect_timeout: Duration::from_secs(3),
             retry_period: Duration::from_secs_f64(0.25),
             player_name: String::from("test"),
+            team: None,
+            spectator: false,
+            capabilities: Capabilities::default(),
         },
         |player_control_sender, game_update_receiver| {
             let mut last_server_message = game_update_receiver
@@ -420,6 +555,206 @@ fn server_should_send_world_update_after_ack() {
     );
 }
 
+#[test]
+fn server_should_send_quantized_world_update_after_ack() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.15"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 1,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.15"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    with_background_server_and_client(
+        server_params,
+        GameClientSettings {
+            id: 1,
+            connect_timeout: Duration::from_secs(3),
+            retry_period: Duration::from_secs_f64(0.25),
+            player_name: String::from("test"),
+            team: None,
+            spectator: false,
+            capabilities: Capabilities {
+                quantized_world_updates: true,
+            },
+        },
+        |player_control_sender, game_update_receiver| {
+            let mut last_server_message = game_update_receiver
+                .recv_timeout(Duration::from_secs(3))
+                .unwrap();
+            assert!(
+                matches!(last_server_message, GameUpdate::SetPlayerId(..)),
+                "{:?}",
+                last_server_message
+            );
+            let start = Instant::now();
+            while Instant::now() - start < Duration::from_secs(3) {
+                last_server_message = game_update_receiver
+                    .recv_timeout(Duration::from_secs(1))
+                    .unwrap();
+                match &last_server_message {
+                    GameUpdate::WorldUpdate { world_update, .. } => {
+                        assert!(world_update.after_frame > world_update.before_frame);
+                        break;
+                    }
+                    GameUpdate::WorldSnapshot { world, .. } => {
+                        player_control_sender
+                            .send(PlayerControl {
+                                ack_world_frame: world.frame,
+                                cast_action_world_frame: 0,
+                                actor_action: ActorAction::default(),
+                            })
+                            .unwrap();
+                    }
+                    _ => (),
+                }
+            }
+            assert!(
+                matches!(last_server_message, GameUpdate::WorldUpdate { .. }),
+                "{:?}",
+                last_server_message
+            );
+        },
+    );
+}
+
+#[test]
+fn server_should_accept_spectator_when_players_limit_is_reached() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.20"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 2,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.20"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let game_client_settings = GameClientSettings {
+        id: 1,
+        connect_timeout: Duration::from_secs(3),
+        retry_period: Duration::from_secs_f64(0.25),
+        player_name: String::from("test"),
+        team: None,
+        spectator: false,
+        capabilities: Capabilities::default(),
+    };
+    let udp_client_settings = UdpClientSettings {
+        id: 1,
+        server_address: format!("{}:{}", server_params.address, server_params.port)
+            .parse()
+            .unwrap(),
+        read_timeout: Duration::from_secs(3),
+        reconnect_timeout: Duration::from_secs(1),
+        encryption_key: server_params.encryption_key.clone(),
+    };
+    with_background_server(server_params, |http_client| {
+        let barrier1 = Arc::new(Barrier::new(2));
+        let barrier2 = Arc::new(Barrier::new(2));
+        let player_session = {
+            let player_game_client_settings = game_client_settings.clone();
+            let player_udp_client_settings = udp_client_settings.clone();
+            let barrier1 = barrier1.clone();
+            let barrier2 = barrier2.clone();
+            spawn(move || {
+                with_background_client(
+                    player_game_client_settings,
+                    player_udp_client_settings,
+                    |_, game_update_receiver| {
+                        recv_player_id(game_update_receiver);
+                        barrier1.wait();
+                        barrier2.wait();
+                    },
+                );
+            })
+        };
+        barrier1.wait();
+        with_background_client(
+            GameClientSettings {
+                id: 2,
+                player_name: String::from("spectator"),
+                spectator: true,
+                ..game_client_settings
+            },
+            UdpClientSettings {
+                id: 2,
+                ..udp_client_settings
+            },
+            |_, game_update_receiver| {
+                let game_update = game_update_receiver
+                    .recv_timeout(Duration::from_secs(3))
+                    .unwrap();
+                assert!(
+                    matches!(game_update, GameUpdate::WorldSnapshot { .. }),
+                    "{:?}",
+                    game_update
+                );
+                match http_client.sessions() {
+                    HttpMessage::Sessions { sessions } => {
+                        assert_eq!(sessions.len(), 2);
+                        assert_eq!(
+                            sessions
+                                .iter()
+                                .filter(|v| matches!(&v.game, Some(v) if v.player_id.is_none()))
+                                .count(),
+                            1
+                        );
+                    }
+                    v => panic!("{:?}", v),
+                }
+                match http_client.world() {
+                    HttpMessage::World { world } => assert_eq!(world.players.len(), 1),
+                    v => panic!("{:?}", v),
+                }
+            },
+        );
+        barrier2.wait();
+        player_session.join().unwrap();
+    });
+}
+
 #[test]
 fn server_should_response_to_http_ping() {
     init_logger();
@@ -436,6 +771,21 @@ fn server_should_response_to_http_ping() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server(server_params, |http_client| {
         assert_eq!(http_client.ping(), HttpMessage::Ok);
@@ -458,6 +808,21 @@ fn server_should_response_to_http_status() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server(server_params, |http_client| {
         let result = http_client.status();
@@ -490,6 +855,21 @@ fn server_should_response_to_http_sessions() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server(server_params, |http_client| {
         assert_eq!(
@@ -517,6 +897,21 @@ fn server_should_response_to_http_remove_session() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server(server_params, |http_client| {
         assert_eq!(
@@ -544,6 +939,21 @@ fn server_should_response_to_http_world() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server(server_params, |http_client| {
         let result = http_client.world();
@@ -567,6 +977,21 @@ fn server_should_response_to_http_stop() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server(server_params, |http_client| {
         assert_eq!(http_client.stop(), HttpMessage::Ok);
@@ -589,6 +1014,21 @@ fn server_should_add_spell_on_client_request() {
         http_port: pick_unused_port().unwrap(),
         http_max_connections: 1,
         world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
     };
     with_background_server_and_client(
         server_params,
@@ -597,6 +1037,9 @@ fn server_should_add_spell_on_client_request() {
             connect_timeout: Duration::from_secs(3),
             retry_period: Duration::from_secs_f64(0.25),
             player_name: String::from("test"),
+            team: None,
+            spectator: false,
+            capabilities: Capabilities::default(),
         },
         |player_control_sender, game_update_receiver| {
             let player_id = recv_player_id(game_update_receiver);
@@ -643,6 +1086,1041 @@ fn server_should_add_spell_on_client_request() {
     );
 }
 
+#[test]
+fn server_should_reject_client_with_incompatible_protocol_version() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.16"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 1,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.16"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |_| {
+        let socket = connect_raw_udp_socket("127.0.0.16:0", &server_address);
+        let cookie = send_and_recv_raw(
+            &socket,
+            &make_join_message(0, PROTOCOL_VERSION + 1, 0, None),
+            get_challenge_cookie,
+        );
+        assert_eq!(
+            send_and_recv_raw(
+                &socket,
+                &make_join_message(1, PROTOCOL_VERSION + 1, cookie, None),
+                |v| Some((v.len(), get_rejected_protocol_version(v)?)),
+            ),
+            (MESSAGE_PREFIX_SIZE, PROTOCOL_VERSION)
+        );
+    });
+}
+
+#[test]
+fn server_should_require_challenge_and_secret_for_session() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.17"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 2,
+        max_players: 2,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.17"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |http_client| {
+        let socket = connect_raw_udp_socket("127.0.0.17:0", &server_address);
+        let cookie = send_and_recv_raw(
+            &socket,
+            &make_join_message(1, PROTOCOL_VERSION, 0, None),
+            get_challenge_cookie,
+        );
+        assert_eq!(
+            send_and_recv_raw(
+                &socket,
+                &make_join_message(2, PROTOCOL_VERSION, cookie.wrapping_add(1), None),
+                get_challenge_cookie,
+            ),
+            cookie
+        );
+        assert_eq!(
+            http_client.sessions(),
+            HttpMessage::Sessions {
+                sessions: Vec::new()
+            }
+        );
+        let secret = match send_and_recv_server_message_data(
+            &socket,
+            &make_join_message(3, PROTOCOL_VERSION, cookie, None),
+            |v| matches!(v, ServerMessageData::NewPlayer { .. }),
+        ) {
+            ServerMessageData::NewPlayer { secret, .. } => secret,
+            v => panic!("{:?}", v),
+        };
+        assert_ne!(secret, cookie);
+        let session_id = match http_client.sessions() {
+            HttpMessage::Sessions { sessions } => {
+                assert_eq!(sessions.len(), 1);
+                sessions[0].session_id
+            }
+            v => panic!("{:?}", v),
+        };
+        let quit = serialize_client_message(&ClientMessage {
+            session_id,
+            number: 4,
+            data: ClientMessageData::Quit,
+            secret,
+            reliable: None,
+            ack: 0,
+        });
+        let other_socket = connect_raw_udp_socket("127.0.0.17:0", &server_address);
+        other_socket.send(&quit).unwrap();
+        let cookie_secret_quit = serialize_client_message(&ClientMessage {
+            session_id,
+            number: 5,
+            data: ClientMessageData::Quit,
+            secret: cookie,
+            reliable: None,
+            ack: 0,
+        });
+        socket.send(&cookie_secret_quit).unwrap();
+        sleep(Duration::from_secs_f64(0.5));
+        match http_client.sessions() {
+            HttpMessage::Sessions { sessions } => {
+                assert_eq!(sessions.len(), 1);
+                assert_eq!(sessions[0].session_id, session_id);
+                assert_eq!(sessions[0].state, UdpSessionState::Established);
+            }
+            v => panic!("{:?}", v),
+        }
+    });
+}
+
+#[test]
+fn server_should_accept_only_encrypted_messages_with_encryption_key() {
+    init_logger();
+    let encryption_key: EncryptionKey =
+        "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+            .parse()
+            .unwrap();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.18"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 1,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.18"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: Some(encryption_key.clone()),
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |http_client| {
+        let socket = connect_raw_udp_socket("127.0.0.18:0", &server_address);
+        socket
+            .send(&make_join_message(1, PROTOCOL_VERSION, 0, None))
+            .unwrap();
+        let mut buffer = vec![0u8; 65_507];
+        assert!(socket.recv(&mut buffer).is_err());
+        assert_eq!(
+            http_client.sessions(),
+            HttpMessage::Sessions {
+                sessions: Vec::new()
+            }
+        );
+        with_background_client(
+            GameClientSettings {
+                id: 1,
+                connect_timeout: Duration::from_secs(3),
+                retry_period: Duration::from_secs_f64(0.25),
+                player_name: String::from("test"),
+                team: None,
+                spectator: false,
+                capabilities: Capabilities::default(),
+            },
+            UdpClientSettings {
+                id: 1,
+                server_address: server_address.parse().unwrap(),
+                read_timeout: Duration::from_secs(3),
+                reconnect_timeout: Duration::from_secs(1),
+                encryption_key: Some(encryption_key),
+            },
+            |_, game_update_receiver| {
+                let game_update = game_update_receiver
+                    .recv_timeout(Duration::from_secs(3))
+                    .unwrap();
+                assert!(
+                    matches!(game_update, GameUpdate::SetPlayerId(..)),
+                    "{:?}",
+                    game_update
+                );
+            },
+        );
+    });
+}
+
+#[test]
+fn server_should_resume_detached_session_with_resume_token() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.19"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 2,
+        max_players: 1,
+        udp_session_timeout: 1.0,
+        game_session_timeout: 0.5,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.19"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |http_client| {
+        let join = |socket: &UdpSocket, number: u64, resume_token: Option<u64>| {
+            let cookie = send_and_recv_raw(
+                socket,
+                &make_join_message(number, PROTOCOL_VERSION, 0, resume_token),
+                get_challenge_cookie,
+            );
+            send_and_recv_server_message_data(
+                socket,
+                &make_join_message(number + 1, PROTOCOL_VERSION, cookie, resume_token),
+                |v| {
+                    matches!(
+                        v,
+                        ServerMessageData::NewPlayer { .. }
+                            | ServerMessageData::GameUpdate(GameUpdate::GameOver(..))
+                    )
+                },
+            )
+        };
+        let (player_id, resume_token) = match join(
+            &connect_raw_udp_socket("127.0.0.19:0", &server_address),
+            1,
+            None,
+        ) {
+            ServerMessageData::NewPlayer {
+                player_id: Some(player_id),
+                resume_token,
+                ..
+            } => (player_id, resume_token),
+            v => panic!("{:?}", v),
+        };
+        let start = Instant::now();
+        let actor_id = loop {
+            assert!(Instant::now() - start < Duration::from_secs(3));
+            let actor_id = match http_client.world() {
+                HttpMessage::World { world } => {
+                    world
+                        .players
+                        .iter()
+                        .find(|v| v.id == player_id)
+                        .unwrap()
+                        .actor_id
+                }
+                v => panic!("{:?}", v),
+            };
+            if let Some(v) = actor_id {
+                break v;
+            }
+            sleep(Duration::from_secs_f64(0.1));
+        };
+        sleep(Duration::from_secs_f64(1.5));
+        let socket = connect_raw_udp_socket("127.0.0.19:0", &server_address);
+        assert_eq!(
+            join(&socket, 3, Some(resume_token.wrapping_add(1))),
+            ServerMessageData::GameUpdate(GameUpdate::GameOver(String::from("Session is expired")))
+        );
+        let secret = match join(&socket, 5, Some(resume_token)) {
+            ServerMessageData::NewPlayer {
+                player_id: resumed_player_id,
+                resume_token: resumed_resume_token,
+                secret,
+                ..
+            } => {
+                assert_eq!(resumed_player_id, Some(player_id));
+                assert_eq!(resumed_resume_token, resume_token);
+                secret
+            }
+            v => panic!("{:?}", v),
+        };
+        send_and_recv_server_message_data(
+            &socket,
+            &make_join_message(7, PROTOCOL_VERSION, secret, Some(resume_token)),
+            |v| {
+                matches!(
+                    v,
+                    ServerMessageData::GameUpdate(GameUpdate::WorldSnapshot { .. })
+                )
+            },
+        );
+        match http_client.world() {
+            HttpMessage::World { world } => {
+                assert_eq!(world.players.len(), 1);
+                assert_eq!(world.players[0].id, player_id);
+                assert_eq!(world.players[0].actor_id, Some(actor_id));
+            }
+            v => panic!("{:?}", v),
+        }
+    });
+}
+
+#[test]
+fn server_should_close_previous_client_session_when_attached_session_is_resumed() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.25"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 2,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.25"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |http_client| {
+        let join = |socket: &UdpSocket, resume_token: Option<u64>| {
+            let cookie = send_and_recv_raw(
+                socket,
+                &make_join_message(1, PROTOCOL_VERSION, 0, resume_token),
+                get_challenge_cookie,
+            );
+            send_and_recv_server_message_data(
+                socket,
+                &make_join_message(2, PROTOCOL_VERSION, cookie, resume_token),
+                |v| matches!(v, ServerMessageData::NewPlayer { .. }),
+            )
+        };
+        let socket = connect_raw_udp_socket("127.0.0.25:0", &server_address);
+        let (player_id, resume_token) = match join(&socket, None) {
+            ServerMessageData::NewPlayer {
+                player_id: Some(player_id),
+                resume_token,
+                ..
+            } => (player_id, resume_token),
+            v => panic!("{:?}", v),
+        };
+        let other_socket = connect_raw_udp_socket("127.0.0.25:0", &server_address);
+        match join(&other_socket, Some(resume_token)) {
+            ServerMessageData::NewPlayer {
+                player_id: resumed_player_id,
+                ..
+            } => assert_eq!(resumed_player_id, Some(player_id)),
+            v => panic!("{:?}", v),
+        }
+        let messages = recv_reliable_server_messages(&socket, Duration::from_secs(1));
+        assert!(
+            messages.iter().any(|(_, _, data)| data
+                == &ServerMessageData::GameUpdate(GameUpdate::GameOver(String::from(
+                    "Session is resumed by another client"
+                )))),
+            "{:?}",
+            messages
+        );
+        match http_client.sessions() {
+            HttpMessage::Sessions { sessions } => {
+                assert_eq!(sessions.len(), 2);
+                assert_eq!(
+                    sessions
+                        .iter()
+                        .filter(|v| v.state == UdpSessionState::Established)
+                        .count(),
+                    1
+                );
+            }
+            v => panic!("{:?}", v),
+        }
+    });
+}
+
+#[test]
+fn server_should_deliver_chat_messages_and_announcements() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.21"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 2,
+        max_players: 2,
+        udp_session_timeout: 11.0,
+        game_session_timeout: 10.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.21"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 2,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let udp_client_settings = UdpClientSettings {
+        id: 1,
+        server_address: format!("{}:{}", server_params.address, server_params.port)
+            .parse()
+            .unwrap(),
+        read_timeout: Duration::from_secs(3),
+        reconnect_timeout: Duration::from_secs(1),
+        encryption_key: None,
+    };
+    let make_client = |id: u64, team: u8| {
+        Client::new(
+            GameClientSettings {
+                id,
+                connect_timeout: Duration::from_secs(3),
+                retry_period: Duration::from_secs_f64(0.25),
+                player_name: format!("test{}", (b'a' + team) as char),
+                team: Some(TeamId(team)),
+                spectator: false,
+                capabilities: Capabilities::default(),
+            },
+            UdpClientSettings {
+                id,
+                ..udp_client_settings.clone()
+            },
+        )
+    };
+    with_background_server(server_params, |http_client| {
+        let first = make_client(1, 0);
+        let second = make_client(2, 1);
+        let first_player_id = recv_player_id(first.receiver());
+        recv_player_id(second.receiver());
+        first
+            .send_chat_message(String::from("hello"), false)
+            .unwrap();
+        first
+            .send_chat_message(String::from("secret"), true)
+            .unwrap();
+        assert_eq!(
+            recv_chat_message(first.receiver()),
+            ChatMessage {
+                sender: Some(first_player_id),
+                team: None,
+                text: String::from("hello"),
+            }
+        );
+        assert_eq!(
+            recv_chat_message(first.receiver()),
+            ChatMessage {
+                sender: Some(first_player_id),
+                team: Some(TeamId(0)),
+                text: String::from("secret"),
+            }
+        );
+        assert_eq!(http_client.announce("announcement"), HttpMessage::Ok);
+        let announcement = ChatMessage {
+            sender: None,
+            team: None,
+            text: String::from("announcement"),
+        };
+        assert_eq!(recv_chat_message(first.receiver()), announcement);
+        assert_eq!(
+            recv_chat_message(second.receiver()),
+            ChatMessage {
+                sender: Some(first_player_id),
+                team: None,
+                text: String::from("hello"),
+            }
+        );
+        assert_eq!(recv_chat_message(second.receiver()), announcement);
+        assert!(matches!(
+            http_client.announce(""),
+            HttpMessage::Error { .. }
+        ));
+    });
+}
+
+#[test]
+fn server_should_deliver_chat_message_once_over_reliable_channel() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.26"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 1,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.26"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |http_client| {
+        let socket = connect_raw_udp_socket("127.0.0.26:0", &server_address);
+        let cookie = send_and_recv_raw(
+            &socket,
+            &make_join_message(1, PROTOCOL_VERSION, 0, None),
+            get_challenge_cookie,
+        );
+        let (player_id, secret) = match send_and_recv_server_message_data(
+            &socket,
+            &make_join_message(2, PROTOCOL_VERSION, cookie, None),
+            |v| matches!(v, ServerMessageData::NewPlayer { .. }),
+        ) {
+            ServerMessageData::NewPlayer {
+                player_id, secret, ..
+            } => (player_id, secret),
+            v => panic!("{:?}", v),
+        };
+        let session_id = match http_client.sessions() {
+            HttpMessage::Sessions { sessions } => sessions[0].session_id,
+            v => panic!("{:?}", v),
+        };
+        for number in 3..5 {
+            socket
+                .send(&serialize_client_message(&ClientMessage {
+                    session_id,
+                    number,
+                    data: ClientMessageData::Chat {
+                        text: String::from("hello"),
+                        team_only: false,
+                    },
+                    secret,
+                    reliable: Some(1),
+                    ack: 0,
+                }))
+                .unwrap();
+        }
+        let chat_message = ServerMessageData::GameUpdate(GameUpdate::ChatMessage(ChatMessage {
+            sender: player_id,
+            team: None,
+            text: String::from("hello"),
+        }));
+        let numbers: Vec<u64> = recv_reliable_server_messages(&socket, Duration::from_secs(1))
+            .into_iter()
+            .filter(|(_, _, data)| data == &chat_message)
+            .map(|(_, number, _)| number)
+            .collect();
+        assert!(numbers.len() >= 2, "{:?}", numbers);
+        assert!(numbers.iter().all(|v| *v == numbers[0]), "{:?}", numbers);
+    });
+}
+
+#[test]
+fn server_should_resend_reliable_messages_until_ack() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.22"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 1,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.22"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |_| {
+        let socket = connect_raw_udp_socket("127.0.0.22:0", &server_address);
+        let cookie = send_and_recv_raw(
+            &socket,
+            &make_join_message(1, PROTOCOL_VERSION, 0, None),
+            get_challenge_cookie,
+        );
+        socket
+            .send(&make_join_message(2, PROTOCOL_VERSION, cookie, None))
+            .unwrap();
+        let messages = recv_reliable_server_messages(&socket, Duration::from_secs(1));
+        assert!(messages.len() >= 2, "{:?}", messages);
+        let session_id = messages[0].0;
+        let secret = match &messages[0].2 {
+            ServerMessageData::NewPlayer { secret, .. } => *secret,
+            v => panic!("{:?}", v),
+        };
+        for (_, number, data) in messages.iter() {
+            assert_eq!(*number, 1);
+            assert!(matches!(data, ServerMessageData::NewPlayer { .. }));
+        }
+        socket
+            .send(&serialize_client_message(&ClientMessage {
+                session_id,
+                number: 3,
+                data: ClientMessageData::Heartbeat,
+                secret,
+                reliable: None,
+                ack: 1,
+            }))
+            .unwrap();
+        recv_reliable_server_messages(&socket, Duration::from_secs_f64(0.5));
+        assert_eq!(
+            recv_reliable_server_messages(&socket, Duration::from_secs(1)),
+            Vec::new()
+        );
+    });
+}
+
+#[test]
+fn server_should_adapt_session_update_frequency_to_ack_delay() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.23"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 1,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.23"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: None,
+    };
+    let server_address = format!("{}:{}", server_params.address, server_params.port);
+    with_background_server(server_params, |http_client| {
+        let socket = connect_raw_udp_socket("127.0.0.23:0", &server_address);
+        let cookie = send_and_recv_raw(
+            &socket,
+            &make_join_message(1, PROTOCOL_VERSION, 0, None),
+            get_challenge_cookie,
+        );
+        let secret = match send_and_recv_server_message_data(
+            &socket,
+            &make_join_message(2, PROTOCOL_VERSION, cookie, None),
+            |v| matches!(v, ServerMessageData::NewPlayer { .. }),
+        ) {
+            ServerMessageData::NewPlayer { secret, .. } => secret,
+            v => panic!("{:?}", v),
+        };
+        let get_session = || match http_client.sessions() {
+            HttpMessage::Sessions { mut sessions } => {
+                assert_eq!(sessions.len(), 1);
+                sessions.remove(0)
+            }
+            v => panic!("{:?}", v),
+        };
+        let session_id = get_session().session_id;
+        let ack = |number: u64| {
+            let ack_world_frame = match http_client.world() {
+                HttpMessage::World { world } => world.frame,
+                v => panic!("{:?}", v),
+            };
+            let message = ClientMessage {
+                session_id,
+                number,
+                data: ClientMessageData::PlayerControl(PlayerControl {
+                    ack_world_frame,
+                    cast_action_world_frame: 0,
+                    actor_action: ActorAction::default(),
+                }),
+                secret,
+                reliable: None,
+                ack: 0,
+            };
+            socket.send(&serialize_client_message(&message)).unwrap();
+        };
+        let get_update_frequency = || get_session().game.unwrap().update_frequency;
+        ack(3);
+        sleep(Duration::from_secs_f64(0.1));
+        assert!((get_update_frequency() - 60.0).abs() < 1e-3);
+        sleep(Duration::from_secs(2));
+        assert!((get_update_frequency() - 7.5).abs() < 1e-3);
+        let mut update_frequency = 7.5;
+        for number in 4..120 {
+            ack(number);
+            sleep(Duration::from_secs_f64(0.1));
+            let new_update_frequency = get_update_frequency();
+            assert!(new_update_frequency >= update_frequency);
+            update_frequency = new_update_frequency;
+            if (update_frequency - 60.0).abs() < 1e-3 {
+                break;
+            }
+        }
+        assert!((update_frequency - 60.0).abs() < 1e-3);
+    });
+}
+
+#[test]
+fn server_should_send_only_entities_in_session_area_of_interest() {
+    init_logger();
+    let server_params = ServerParams {
+        address: String::from("127.0.0.24"),
+        port: pick_unused_port().unwrap(),
+        max_sessions: 1,
+        max_players: 1,
+        udp_session_timeout: 4.0,
+        game_session_timeout: 3.0,
+        update_frequency: 60.0,
+        random_seed: Some(42),
+        http_address: String::from("127.0.0.24"),
+        http_port: pick_unused_port().unwrap(),
+        http_max_connections: 1,
+        world: None,
+        game_mode: GameModeType::FreeForAll,
+        score_limit: 20,
+        time_limit: 600.0,
+        intermission: 10.0,
+        teams: 0,
+        bots: 0,
+        max_lag_compensation: 0.2,
+        position_precision: 0.001,
+        velocity_precision: 0.001,
+        direction_bits: 12,
+        health_precision: 0.0001,
+        disable_quantized_world_updates: false,
+        encryption_key: None,
+        resume_timeout: 10.0,
+        area_of_interest_radius: Some(20.0),
+    };
+    let game_client_settings = GameClientSettings {
+        id: 1,
+        connect_timeout: Duration::from_secs(3),
+        retry_period: Duration::from_secs_f64(0.25),
+        player_name: String::from("test"),
+        team: None,
+        spectator: false,
+        capabilities: Capabilities {
+            quantized_world_updates: true,
+        },
+    };
+    let udp_client_settings = UdpClientSettings {
+        id: game_client_settings.id,
+        server_address: format!("{}:{}", server_params.address, server_params.port)
+            .parse()
+            .unwrap(),
+        read_timeout: Duration::from_secs(3),
+        reconnect_timeout: Duration::from_secs(1),
+        encryption_key: None,
+    };
+    with_background_server(server_params, |http_client| {
+        with_background_client(
+            game_client_settings,
+            udp_client_settings,
+            |player_control_sender, game_update_receiver| {
+                let player_id = recv_player_id(game_update_receiver);
+                let start = Instant::now();
+                let mut world = World::default();
+                let mut max_actors = 0;
+                let mut max_static_objects = 0;
+                while Instant::now() - start < Duration::from_secs(2) {
+                    match game_update_receiver
+                        .recv_timeout(Duration::from_secs(1))
+                        .unwrap()
+                    {
+                        GameUpdate::WorldUpdate { world_update, .. } => {
+                            apply_world_update(*world_update, &mut world);
+                        }
+                        GameUpdate::WorldSnapshot { world: w, .. } => {
+                            world = *w;
+                        }
+                        _ => continue,
+                    }
+                    player_control_sender
+                        .send(PlayerControl {
+                            ack_world_frame: world.frame,
+                            cast_action_world_frame: 0,
+                            actor_action: ActorAction {
+                                moving: true,
+                                target_direction: Vec2f::only_x(1.0),
+                                cast_action: None,
+                            },
+                        })
+                        .unwrap();
+                    if let Some(actor) = find_player_actor(player_id, &world) {
+                        for v in world.actors.iter() {
+                            assert!(
+                                v.position.distance(actor.position)
+                                    <= 20.0 + v.body.shape.radius + 0.1,
+                                "{:?} {:?}",
+                                v.position,
+                                actor.position
+                            );
+                        }
+                    }
+                    max_actors = max_actors.max(world.actors.len());
+                    max_static_objects = max_static_objects.max(world.static_objects.len());
+                }
+                let (server_actors, server_static_objects) = match http_client.world() {
+                    HttpMessage::World { world } => {
+                        (world.actors.len(), world.static_objects.len())
+                    }
+                    v => panic!("{:?}", v),
+                };
+                assert!(
+                    max_static_objects < server_static_objects,
+                    "{} {}",
+                    max_static_objects,
+                    server_static_objects
+                );
+                assert!(max_actors > 0);
+                assert!(
+                    max_actors < server_actors,
+                    "{} {}",
+                    max_actors,
+                    server_actors
+                );
+            },
+        );
+    });
+}
+
+fn connect_raw_udp_socket(local_address: &str, server_address: &str) -> UdpSocket {
+    let socket = UdpSocket::bind(local_address).unwrap();
+    socket.connect(server_address).unwrap();
+    socket
+        .set_read_timeout(Some(Duration::from_secs_f64(0.25)))
+        .unwrap();
+    socket
+}
+
+fn make_join_message(
+    number: u64,
+    protocol_version: u64,
+    secret: u64,
+    resume_token: Option<u64>,
+) -> Vec<u8> {
+    let mut message = serialize_client_message(&ClientMessage {
+        session_id: 0,
+        number,
+        data: ClientMessageData::Join {
+            player_name: String::from("test"),
+            team: None,
+            spectator: false,
+            capabilities: Capabilities::default(),
+            resume_token,
+        },
+        secret,
+        reliable: None,
+        ack: 0,
+    });
+    message[CLIENT_MESSAGE_MAGIC.len()..MESSAGE_PREFIX_SIZE]
+        .copy_from_slice(&protocol_version.to_le_bytes());
+    message
+}
+
+fn send_and_recv_raw<T, F>(socket: &UdpSocket, message: &[u8], parse: F) -> T
+where
+    F: Fn(&[u8]) -> Option<T>,
+{
+    let mut buffer = vec![0u8; MAX_SERVER_MESSAGE_SIZE];
+    let start = Instant::now();
+    loop {
+        assert!(Instant::now() - start < Duration::from_secs(3));
+        socket.send(message).unwrap();
+        while let Ok(size) = socket.recv(&mut buffer) {
+            if let Some(v) = parse(&buffer[0..size]) {
+                return v;
+            }
+        }
+    }
+}
+
+fn send_and_recv_server_message_data<F>(
+    socket: &UdpSocket,
+    message: &[u8],
+    filter: F,
+) -> ServerMessageData
+where
+    F: Fn(&ServerMessageData) -> bool,
+{
+    let mut buffer = vec![0u8; MAX_SERVER_MESSAGE_SIZE];
+    let start = Instant::now();
+    loop {
+        assert!(Instant::now() - start < Duration::from_secs(3));
+        socket.send(message).unwrap();
+        while let Ok(size) = socket.recv(&mut buffer) {
+            if get_challenge_cookie(&buffer[0..size]).is_some() {
+                continue;
+            }
+            let server_message = deserialize_server_message(&buffer[0..size]).unwrap();
+            if server_message.fragments > 1 {
+                continue;
+            }
+            let data = deserialize_server_message_data(
+                &server_message.data,
+                server_message.decompressed_data_size as usize,
+            )
+            .unwrap();
+            if filter(&data) {
+                return data;
+            }
+        }
+    }
+}
+
+fn recv_reliable_server_messages(
+    socket: &UdpSocket,
+    duration: Duration,
+) -> Vec<(u64, u64, ServerMessageData)> {
+    let mut buffer = vec![0u8; MAX_SERVER_MESSAGE_SIZE];
+    let mut result = Vec::new();
+    let start = Instant::now();
+    while Instant::now() - start < duration {
+        if let Ok(size) = socket.recv(&mut buffer) {
+            let server_message = deserialize_server_message(&buffer[0..size]).unwrap();
+            if let Some(number) = server_message.reliable {
+                let data = deserialize_server_message_data(
+                    &server_message.data,
+                    server_message.decompressed_data_size as usize,
+                )
+                .unwrap();
+                result.push((server_message.session_id, number, data));
+            }
+        }
+    }
+    result
+}
+
 fn init_logger() {
     env_logger::try_init().ok();
 }
@@ -660,6 +2138,8 @@ fn with_background_server_and_client<F>(
             .parse()
             .unwrap(),
         read_timeout: Duration::from_secs(3),
+        reconnect_timeout: Duration::from_secs(1),
+        encryption_key: server_params.encryption_key.clone(),
     };
     let w = move |_| {
         with_background_client(game_client_settings, upd_client_settings, f);
@@ -759,6 +2239,17 @@ impl HttpClient {
         .unwrap()
     }
 
+    fn announce(&self, text: &str) -> HttpMessage {
+        send_with_retries(
+            self.client
+                .post(self.url("announce").as_str())
+                .query(&[("text", text)])
+                .timeout(Duration::from_secs(5)),
+        )
+        .json()
+        .unwrap()
+    }
+
     fn stop(&self) -> HttpMessage {
         send_with_retries(
             self.client
@@ -805,6 +2296,18 @@ fn recv_player_id(game_update_receiver: &Receiver<GameUpdate>) -> PlayerId {
     }
 }
 
+fn recv_chat_message(game_update_receiver: &Receiver<GameUpdate>) -> ChatMessage {
+    let deadline = Instant::now() + Duration::from_secs(3);
+    loop {
+        match game_update_receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            Ok(GameUpdate::ChatMessage(v)) => break v,
+            Ok(..) => (),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
 fn find_player_actor(player_id: PlayerId, world: &World) -> Option<&Actor> {
     world.actors.iter().find(|v| v.player_id == player_id)
 }